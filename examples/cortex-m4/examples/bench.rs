@@ -28,6 +28,8 @@ fn main() -> ! {
     static mut stack2: [u32; 128] = [0; 128];
     static mut stack3: [u32; 128] = [0; 128];
 
+    init().unwrap();
+
     spawn!(task1, stack1, {
         hprintln!("TASK 1");
     });
@@ -40,7 +42,6 @@ fn main() -> ! {
         cortex_m::asm::bkpt();
     });
 
-    init();
     release(TaskMask::generate([task1, task2, task3]));
-    start_kernel()
+    start_kernel().unwrap()
 }
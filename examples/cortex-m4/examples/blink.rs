@@ -67,6 +67,8 @@ fn main() -> ! {
     static mut stack1: [u32; 512] = [0; 512];
     static mut stack2: [u32; 512] = [0; 512];
 
+    init().unwrap();
+
     spawn!(task1, stack1, {
         board_peripherals.acquire(|perf| {
             let perf = &mut *perf.borrow_mut();
@@ -94,7 +96,6 @@ fn main() -> ! {
         });
     });
 
-    init();
     cortex_peripherals.acquire(|perf| {
         let perf = &mut *perf.borrow_mut();
         event::systick_start(
@@ -102,5 +103,5 @@ fn main() -> ! {
             80_000_00,
         )
     });
-    start_kernel()
+    start_kernel().unwrap()
 }
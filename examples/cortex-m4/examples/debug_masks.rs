@@ -0,0 +1,43 @@
+#![no_std]
+#![no_main]
+
+// This example requires the "debug_support" feature of harsark, add it to the list of features in Cargo.toml.
+
+extern crate panic_halt;
+extern crate stm32f4;
+
+use cortex_m_rt::entry;
+use cortex_m_semihosting::hprintln;
+
+use harsark::tasks::*;
+use harsark::helpers::TaskMask;
+use harsark::debug::{kernel_blocked_mask, kernel_ready_mask};
+use harsark::spawn;
+
+const task1: u32 = 1;
+const task2: u32 = 2;
+const task3: u32 = 3;
+
+static mut stack1: [u32; 512] = [0; 512];
+static mut stack2: [u32; 512] = [0; 512];
+static mut stack3: [u32; 512] = [0; 512];
+
+// Task 3 runs with tasks 1 and 2 released, and then task 2 suspended : the ready mask is 0b1110 (the idle
+// task has no bit), and then 0b1010; no task is blocked.
+#[entry]
+fn main() -> ! {
+    init().unwrap();
+
+    spawn!(task1, stack1, {});
+    spawn!(task2, stack2, {});
+    spawn!(task3, stack3, {
+        hprintln!("TASK 3: ready : {:#b}", kernel_ready_mask());
+        suspend_task(task2).unwrap();
+        hprintln!("TASK 3: ready : {:#b}", kernel_ready_mask());
+        hprintln!("TASK 3: blocked : {:#b}", kernel_blocked_mask());
+        resume_task(task2).unwrap();
+    });
+
+    release(TaskMask::generate([task1, task2, task3]));
+    start_kernel().unwrap()
+}
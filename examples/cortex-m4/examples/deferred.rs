@@ -0,0 +1,55 @@
+#![no_std]
+#![no_main]
+
+extern crate panic_halt;
+extern crate stm32f4;
+
+use cortex_m_rt::entry;
+use cortex_m_semihosting::hprintln;
+
+use stm32f4::stm32f407::{self, interrupt};
+use cortex_m::peripheral::NVIC;
+
+use harsark::tasks::*;
+use harsark::helpers::TaskMask;
+use harsark::deferred::{defer, process, register_deferred, set_worker};
+use harsark::spawn;
+
+const worker: u32 = 1;
+const trigger: u32 = 2;
+
+const RX_WORK: usize = 0;
+
+static mut stack1: [u32; 512] = [0; 512];
+static mut stack2: [u32; 512] = [0; 512];
+
+fn handle_rx() {
+    hprintln!("WORKER: deferred work of EXTI0");
+}
+
+#[interrupt]
+fn EXTI0() {
+    hprintln!("ISR: EXTI0");
+    defer(RX_WORK).unwrap();
+}
+
+// The trigger task pends EXTI0 in place of a device. The ISR only defers its work, which the low priority
+// worker runs once the trigger is done : "ISR: EXTI0", "TRIGGER: END", then "WORKER: deferred work of EXTI0".
+#[entry]
+fn main() -> ! {
+    init().unwrap();
+    register_deferred(RX_WORK, handle_rx).unwrap();
+    set_worker(worker).unwrap();
+
+    spawn!(worker, stack1, {
+        process();
+    });
+    spawn!(trigger, stack2, {
+        unsafe { NVIC::unmask(stm32f407::Interrupt::EXTI0) };
+        NVIC::pend(stm32f407::Interrupt::EXTI0);
+        hprintln!("TRIGGER: END");
+    });
+
+    release(TaskMask::generate([trigger]));
+    start_kernel().unwrap()
+}
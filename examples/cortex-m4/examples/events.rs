@@ -31,7 +31,7 @@ fn main() -> ! {
         );
 
     let event1 = events::new(true, 3, || {
-        msg1.broadcast(Some([1,2]));
+        msg1.broadcast(Some([1,2])).unwrap();
     });
     let event2 = events::new(true, 2, || {
         sem2.signal_and_release(TaskMask::generate([task2]));
@@ -44,6 +44,8 @@ fn main() -> ! {
     static mut stack2: [u32; 300] = [0; 300];
     static mut stack3: [u32; 300] = [0; 300];
 
+    init().unwrap();
+
     spawn!(task1, stack1, {
         hprintln!("TASK 1: Enter");
         if let Ok(true) = sem2.test_and_reset() {
@@ -67,10 +69,9 @@ fn main() -> ! {
         hprintln!("TASK 3: End");
     });
 
-    init();
     timer::start_timer(
         &mut peripherals,
         80_000_00,
     );
-    start_kernel()
+    start_kernel().unwrap()
 }
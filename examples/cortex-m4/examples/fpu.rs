@@ -0,0 +1,51 @@
+#![no_std]
+#![no_main]
+
+extern crate panic_halt;
+extern crate stm32f4;
+
+use cortex_m_rt::entry;
+use cortex_m_semihosting::hprintln;
+
+use harsark::tasks::*;
+
+#[entry]
+fn main() -> ! {
+    const task1: u32 = 1;
+    const task2: u32 = 2;
+
+    static mut stack1: [u32; 256] = [0; 256];
+    static mut stack2: [u32; 256] = [0; 256];
+
+    init().unwrap();
+
+    /*
+    Both tasks keep a running floating point value in registers; task1 releases the higher priority
+    task2 midway, hence it is preempted in the middle of its computation. Each task checks that its value is not contaminated by the other.
+    */
+    create_task_fpu(task1, unsafe { &mut stack1 }, || loop {
+        let mut acc: f32 = 2.0;
+        for i in 0..100 {
+            acc += 0.5;
+            if i == 50 {
+                release(1 << task2);
+            }
+        }
+        assert!(acc == 52.0);
+        hprintln!("TASK 1: {}", acc);
+        task_exit();
+    }).unwrap();
+    create_task_fpu(task2, unsafe { &mut stack2 }, || loop {
+        let mut acc: f32 = 1.0;
+        for _ in 0..100 {
+            acc *= 1.5;
+            acc /= 1.5;
+        }
+        assert!(acc == 1.0);
+        hprintln!("TASK 2: {}", acc);
+        task_exit();
+    }).unwrap();
+
+    release(1 << task1);
+    start_kernel().unwrap()
+}
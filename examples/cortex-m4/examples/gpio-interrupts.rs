@@ -15,6 +15,7 @@ use stm32f4::stm32f407::interrupt;
 use cortex_m::peripheral::NVIC;
 
 use harsark::tasks::*;
+use harsark::interrupts::{bind_interrupt, notify_from_isr, IrqBinding};
 use harsark::helpers::TaskMask;
 use harsark::primitives::*;
 use harsark::spawn;
@@ -22,6 +23,10 @@ use harsark::spawn;
 const task1: u32 = 1;
 const task2: u32 = 2;
 
+// Each button interrupt wakes up the task which toggles its LED.
+static EXTI3_TASK: IrqBinding = bind_interrupt(task1);
+static EXTI4_TASK: IrqBinding = bind_interrupt(task2);
+
 lazy_static! {
     static ref board_peripherals: Resource<RefCell<Peripherals>> = Resource::new(
         RefCell::new(Peripherals::take().unwrap()),
@@ -90,20 +95,16 @@ fn peripherals_configure(peripherals: &mut Peripherals) {
 
 #[interrupt]
 fn EXTI3() {
-    board_peripherals.acquire(|peripherals| {
-        let peripherals = &mut *peripherals.borrow_mut();
-        peripherals.EXTI.pr.write(|w| w.pr3().set_bit());
-    });
-    release(2);
+    // Resources can't be locked from interrupt handlers, the pending bit is cleared directly.
+    unsafe { (*stm32f407::EXTI::ptr()).pr.write(|w| w.pr3().set_bit()) };
+    notify_from_isr(EXTI3_TASK);
 }
 
 #[interrupt]
 fn EXTI4() {
-    board_peripherals.acquire(|peripherals| {
-        let peripherals = &mut *peripherals.borrow_mut();
-        peripherals.EXTI.pr.write(|w| w.pr4().set_bit());
-    });
-    release(4);
+    // Resources can't be locked from interrupt handlers, the pending bit is cleared directly.
+    unsafe { (*stm32f407::EXTI::ptr()).pr.write(|w| w.pr4().set_bit()) };
+    notify_from_isr(EXTI4_TASK);
 }
 
 #[entry]
@@ -118,6 +119,8 @@ fn main() -> ! {
     static mut stack1: [u32; 300] = [0; 300];
     static mut stack2: [u32; 300] = [0; 300];
 
+    init().unwrap();
+
     spawn!(task1, stack1, {
         board_peripherals.acquire(|perf| {
             let perf = &mut *perf.borrow_mut();
@@ -145,6 +148,5 @@ fn main() -> ! {
         });
     });
 
-    init();
-    start_kernel()
+    start_kernel().unwrap()
 }
@@ -44,6 +44,8 @@ fn main() -> ! {
     
     static resource1: Resource<RefCell<Vec<u32>>> = Resource::new(RefCell::new(Vec::new()), TaskMask::generate([1, 2]));
     
+    init().unwrap();
+
     spawn!(task1, stack1, {
         hprintln!("TASK 1: Enter");
         resource1.acquire(|res| {
@@ -63,7 +65,6 @@ fn main() -> ! {
         hprintln!("TASK 2: End");
     });
 
-    init();
     release(TaskMask::generate([task1, task2]));
-    start_kernel()
+    start_kernel().unwrap()
 }
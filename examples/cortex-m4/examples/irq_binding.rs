@@ -0,0 +1,49 @@
+#![no_std]
+#![no_main]
+
+extern crate panic_halt;
+extern crate stm32f4;
+
+use cortex_m_rt::entry;
+use cortex_m_semihosting::hprintln;
+
+use stm32f4::stm32f407::{self, interrupt};
+use cortex_m::peripheral::NVIC;
+
+use harsark::tasks::*;
+use harsark::helpers::TaskMask;
+use harsark::interrupts::{bind_interrupt, notify_from_isr, IrqBinding};
+use harsark::spawn;
+
+const handler_task: u32 = 2;
+const trigger_task: u32 = 1;
+
+static mut stack1: [u32; 512] = [0; 512];
+static mut stack2: [u32; 512] = [0; 512];
+
+static EXTI0_TASK: IrqBinding = bind_interrupt(handler_task);
+
+#[interrupt]
+fn EXTI0() {
+    hprintln!("ISR: EXTI0");
+    notify_from_isr(EXTI0_TASK);
+}
+
+// No device raises EXTI0, the trigger task pends it in software instead. The interrupt releases the higher
+// priority handler task, which runs as soon as the ISR returns : "ISR: EXTI0", then "HANDLER", then "TRIGGER: END".
+#[entry]
+fn main() -> ! {
+    init().unwrap();
+
+    spawn!(handler_task, stack2, {
+        hprintln!("HANDLER");
+    });
+    spawn!(trigger_task, stack1, {
+        unsafe { NVIC::unmask(stm32f407::Interrupt::EXTI0) };
+        NVIC::pend(stm32f407::Interrupt::EXTI0);
+        hprintln!("TRIGGER: END");
+    });
+
+    release(TaskMask::generate([trigger_task]));
+    start_kernel().unwrap()
+}
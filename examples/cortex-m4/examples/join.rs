@@ -0,0 +1,42 @@
+#![no_std]
+#![no_main]
+
+extern crate panic_halt;
+extern crate stm32f4;
+
+use cortex_m_rt::entry;
+use cortex_m_semihosting::hprintln;
+
+use harsark::tasks::*;
+use harsark::helpers::TaskMask;
+use harsark::spawn;
+use harsark::timer;
+
+const worker: u32 = 1;
+const joiner: u32 = 2;
+
+static mut stack1: [u32; 512] = [0; 512];
+static mut stack2: [u32; 512] = [0; 512];
+
+// The joiner releases the lower priority worker and blocks until it exits; it is woken by the exit
+// long before its timeout, and `join` returns `Ok(())`.
+#[entry]
+fn main() -> ! {
+    let mut peripherals = cortex_m::Peripherals::take().unwrap();
+
+    init().unwrap();
+
+    spawn!(worker, stack1, {
+        hprintln!("WORKER: running");
+    });
+    spawn!(joiner, stack2, {
+        release(TaskMask::generate([worker]));
+        let res = join(worker, 1000);
+        hprintln!("JOINER: worker joined : {:?}", res);
+        hprintln!("JOINER: worker state : {:?}", task_state(worker));
+    });
+
+    release(TaskMask::generate([joiner]));
+    timer::start_timer(&mut peripherals, 80_000_00);
+    start_kernel().unwrap()
+}
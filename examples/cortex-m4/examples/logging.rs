@@ -39,9 +39,11 @@ fn main() -> ! {
     The third variable corresponds to the task stack.
     The fourth variable corresponds to the task body.
     */
+    init().unwrap();
+
     spawn!(task1, stack1, {
         hprintln!("TASK 1");
-        logging::process(|log: logging::LogEvent| {
+        logging::process(|log: logging::LogRecord| {
             hprintln!("{:?}", log);
         });
     });
@@ -54,7 +56,6 @@ fn main() -> ! {
 
 
     // Initializes the kernel in preemptive mode.
-    init();
     
     // Releases tasks task1, task2, task3
     logging::set_all(true);
@@ -66,5 +67,5 @@ fn main() -> ! {
     It requires a reference to the peripherals so as to start the SysTick timer.
     150_000 corresponds to the tick interval of the SysTick timer.
     */
-    start_kernel()
+    start_kernel().unwrap()
 }
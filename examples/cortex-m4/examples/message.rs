@@ -31,9 +31,11 @@ fn main() -> ! {
         );
 
 
+    init().unwrap();
+
     spawn!(task1, stack1, {
         hprintln!("TASK 1: Enter");
-        msg1.broadcast(Some([4, 5]));
+        msg1.broadcast(Some([4, 5])).unwrap();
         sem3.signal_and_release(0);
         hprintln!("TASK 1: END");
     });
@@ -54,7 +56,6 @@ fn main() -> ! {
         hprintln!("TASK 3: END");
     });
 
-    init();
     release(TaskMask::generate([task1]));
-    start_kernel()
+    start_kernel().unwrap()
 }
@@ -0,0 +1,52 @@
+#![no_std]
+#![no_main]
+
+extern crate panic_halt;
+extern crate stm32f4;
+
+use cortex_m_rt::entry;
+use cortex_m_semihosting::hprintln;
+
+use harsark::tasks::*;
+use harsark::helpers::TaskMask;
+use harsark::primitives::*;
+use harsark::spawn;
+
+const receiver: u32 = 1;
+const deleted: u32 = 2;
+const producer: u32 = 3;
+
+static mut stack1: [u32; 512] = [0; 512];
+static mut stack2: [u32; 512] = [0; 512];
+static mut stack3: [u32; 512] = [0; 512];
+
+static msg1: Message<u32> = Message::new(
+    TaskMask::generate([receiver, deleted]),
+    TaskMask::generate([receiver, deleted]),
+    0,
+);
+
+// The producer deletes one of the two receivers after the first broadcast; the deleted task is removed from
+// the receivers of the message, so the second broadcast only notifies the remaining receiver : the receivers
+// are 0b110 before the deletion and 0b10 after it.
+#[entry]
+fn main() -> ! {
+    init().unwrap();
+
+    spawn!(receiver, stack1, {
+        hprintln!("RECEIVER: received : {:?}", msg1.receive());
+    });
+    spawn!(deleted, stack2, {
+        hprintln!("DELETED: received : {:?}", msg1.receive());
+    });
+    spawn!(producer, stack3, {
+        msg1.broadcast(Some(1)).unwrap();
+        hprintln!("PRODUCER: receivers : {:#b}", msg1.receivers());
+        delete_task(deleted).unwrap();
+        hprintln!("PRODUCER: receivers after delete : {:#b}", msg1.receivers());
+        msg1.broadcast(Some(2)).unwrap();
+    });
+
+    release(TaskMask::generate([producer]));
+    start_kernel().unwrap()
+}
@@ -0,0 +1,49 @@
+#![no_std]
+#![no_main]
+
+extern crate panic_halt;
+extern crate stm32f4;
+
+use cortex_m_rt::entry;
+use cortex_m_semihosting::hprintln;
+
+use harsark::tasks::*;
+use harsark::helpers::TaskMask;
+use harsark::primitives::*;
+use harsark::spawn;
+
+const receiver: u32 = 1;
+const late_receiver: u32 = 2;
+const producer: u32 = 3;
+
+static mut stack1: [u32; 512] = [0; 512];
+static mut stack2: [u32; 512] = [0; 512];
+static mut stack3: [u32; 512] = [0; 512];
+
+static msg1: Message<u32> = Message::new(
+    TaskMask::generate([receiver, late_receiver]),
+    TaskMask::generate([receiver, late_receiver]),
+    0,
+);
+
+// The producer broadcasts before the late receiver is created: the receiver gets the value exactly once,
+// i.e. `Some(7)` and then `None`, while the late receiver misses the broadcast and gets `None`.
+#[entry]
+fn main() -> ! {
+    init().unwrap();
+
+    spawn!(receiver, stack1, {
+        hprintln!("RECEIVER: first receive : {:?}", msg1.receive());
+        hprintln!("RECEIVER: second receive : {:?}", msg1.receive());
+    });
+    spawn!(producer, stack3, {
+        msg1.broadcast(Some(7)).unwrap();
+        spawn!(late_receiver, stack2, {
+            hprintln!("LATE RECEIVER: receive : {:?}", msg1.receive());
+        });
+        release(TaskMask::generate([late_receiver]));
+    });
+
+    release(TaskMask::generate([producer]));
+    start_kernel().unwrap()
+}
@@ -0,0 +1,57 @@
+#![no_std]
+#![no_main]
+
+extern crate panic_halt;
+extern crate stm32f4;
+
+use cortex_m_rt::entry;
+use cortex_m_semihosting::hprintln;
+
+use harsark::tasks::*;
+use harsark::helpers::TaskMask;
+use harsark::primitives::*;
+use harsark::spawn;
+
+const task1: u32 = 1;
+const task2: u32 = 2;
+const task3: u32 = 3;
+
+static mut stack1: [u32; 512] = [0; 512];
+static mut stack2: [u32; 512] = [0; 512];
+static mut stack3: [u32; 512] = [0; 512];
+
+// The classic priority inversion: the low priority task1 holds the resource when the high priority
+// task3 needs it, and the medium priority task2 becomes ready meanwhile. With priority inheritance
+// task1 runs with the priority of task3 until it unlocks, so the output order is
+// TASK 1, TASK 3 waits, TASK 1 unlocks, TASK 3, TASK 2.
+#[entry]
+fn main() -> ! {
+
+    static res: PiResource<u32> = PiResource::new(0);
+
+    init().unwrap();
+
+    spawn!(task1, stack1, {
+        res.acquire(|counter| {
+            hprintln!("TASK 1: holds res");
+            // task3 preempts and waits for res, task2 becomes ready but can't preempt task1.
+            release(TaskMask::generate([task2, task3]));
+            schedule();
+            *counter += 1;
+            hprintln!("TASK 1: unlocks res");
+        }).unwrap();
+    });
+    spawn!(task2, stack2, {
+        hprintln!("TASK 2");
+    });
+    spawn!(task3, stack3, {
+        hprintln!("TASK 3: waits for res");
+        res.acquire(|counter| {
+            *counter += 1;
+            hprintln!("TASK 3: res : {}", counter);
+        }).unwrap();
+    });
+
+    release(TaskMask::generate([task1]));
+    start_kernel().unwrap()
+}
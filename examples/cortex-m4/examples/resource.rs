@@ -29,6 +29,8 @@ fn main() -> ! {
     static res2: Resource<[u32; 2]> = Resource::new([4, 5], TaskMask::generate([task3]));
 
 
+    init().unwrap();
+
     spawn!(task1, stack1, {
         hprintln!("TASK 1: Enter");
         // If res1 is free, then the closure passed on is executed on the resource.
@@ -56,7 +58,6 @@ fn main() -> ! {
         hprintln!("TASK 3: End");
     });
 
-    init();
     release(TaskMask::generate([task1]));
-    start_kernel()
+    start_kernel().unwrap()
 }
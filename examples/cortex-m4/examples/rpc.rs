@@ -0,0 +1,48 @@
+#![no_std]
+#![no_main]
+
+extern crate panic_halt;
+extern crate stm32f4;
+
+use cortex_m_rt::entry;
+use cortex_m_semihosting::hprintln;
+
+use harsark::tasks::*;
+use harsark::helpers::TaskMask;
+use harsark::primitives::*;
+use harsark::spawn;
+use harsark::timer;
+
+const server: u32 = 1;
+const client2: u32 = 2;
+const client3: u32 = 3;
+
+static mut stack1: [u32; 512] = [0; 512];
+static mut stack2: [u32; 512] = [0; 512];
+static mut stack3: [u32; 512] = [0; 512];
+
+// Both clients call the server before it runs, as it has the lowest priority; each of them
+// gets the square of its own request back.
+static squares: RpcChannel<u32, u32> = RpcChannel::new(server);
+
+#[entry]
+fn main() -> ! {
+    let mut peripherals = cortex_m::Peripherals::take().unwrap();
+
+    init().unwrap();
+
+    spawn!(server, stack1, {
+        let served = squares.serve(|req| req * req);
+        hprintln!("SERVER: served {} requests", served);
+    });
+    spawn!(client2, stack2, {
+        hprintln!("CLIENT 2: 2^2 = {:?}", squares.call(2, 100));
+    });
+    spawn!(client3, stack3, {
+        hprintln!("CLIENT 3: 3^2 = {:?}", squares.call(3, 100));
+    });
+
+    release(TaskMask::generate([client2, client3]));
+    timer::start_timer(&mut peripherals, 80_000_00);
+    start_kernel().unwrap()
+}
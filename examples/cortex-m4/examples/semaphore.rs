@@ -35,6 +35,8 @@ fn main() -> ! {
     static sem2: Semaphore = Semaphore::new(TaskMask::generate([task2]));
 
 
+    init().unwrap();
+
     spawn!(task1, stack1, {
         hprintln!("TASK 1: Enter");
         sem2.signal_and_release(TaskMask::generate([task2]));
@@ -57,7 +59,6 @@ fn main() -> ! {
         hprintln!("TASK 3: End");
     });
 
-    init();
     release(TaskMask::generate([task2, task3]));
-    start_kernel()
+    start_kernel().unwrap()
 }
@@ -0,0 +1,69 @@
+#![no_std]
+#![no_main]
+
+extern crate panic_halt;
+extern crate stm32f4;
+
+use cortex_m_rt::entry;
+use cortex_m_semihosting::hprintln;
+use stm32f4::stm32f407::{Peripherals, SPI1};
+
+use harsark::tasks::*;
+use harsark::helpers::TaskMask;
+use harsark::primitives::*;
+use harsark::spawn;
+
+const sensor: u32 = 1;
+const display: u32 = 2;
+
+static mut stack1: [u32; 512] = [0; 512];
+static mut stack2: [u32; 512] = [0; 512];
+
+/// A minimal driver for the SPI1 bus; like a HAL driver, its methods take `&mut self`.
+struct SpiBus {
+    spi: SPI1,
+}
+
+impl SpiBus {
+    fn new(spi: SPI1) -> Self {
+        spi.cr1.write(|w| w.mstr().set_bit().ssm().set_bit().ssi().set_bit().spe().set_bit());
+        Self { spi }
+    }
+
+    fn transfer(&mut self, byte: u8) -> u8 {
+        while self.spi.sr.read().txe().bit_is_clear() {}
+        self.spi.dr.write(|w| w.dr().bits(byte as u16));
+        while self.spi.sr.read().rxne().bit_is_clear() {}
+        self.spi.dr.read().dr().bits() as u8
+    }
+}
+
+// The bus starts out empty, the driver is moved into it once the peripheral is taken.
+static spi_bus: Resource<Option<SpiBus>> = Resource::new(None, TaskMask::generate([sensor, display]));
+
+// Both tasks use the bus through `acquire_mut`; the ceiling of the resource keeps the sensor, released by the
+// display while it holds the bus, from preempting it in the middle of a transfer : it runs once the bus is unlocked.
+#[entry]
+fn main() -> ! {
+    let peripherals = Peripherals::take().unwrap();
+    peripherals.RCC.apb2enr.modify(|_, w| w.spi1en().set_bit());
+
+    init().unwrap();
+    spi_bus.acquire_mut(|bus| *bus = Some(SpiBus::new(peripherals.SPI1))).unwrap();
+
+    spawn!(sensor, stack1, {
+        let id = spi_bus.acquire_mut(|bus| bus.as_mut().map(|bus| bus.transfer(0x9F)));
+        hprintln!("SENSOR: id : {:?}", id);
+    });
+    spawn!(display, stack2, {
+        let status = spi_bus.acquire_mut(|bus| {
+            // The sensor only runs once the bus is unlocked.
+            release(TaskMask::generate([sensor]));
+            bus.as_mut().map(|bus| bus.transfer(0x05))
+        });
+        hprintln!("DISPLAY: status : {:?}", status);
+    });
+
+    release(TaskMask::generate([display]));
+    start_kernel().unwrap()
+}
@@ -0,0 +1,53 @@
+#![no_std]
+#![no_main]
+
+extern crate panic_halt;
+extern crate stm32f4;
+
+use cortex_m_rt::entry;
+use cortex_m_semihosting::hprintln;
+
+use harsark::tasks::*;
+use harsark::helpers::TaskMask;
+use harsark::primitives::*;
+use harsark::spawn;
+use harsark::syscall;
+
+const task1: u32 = 1;
+const task2: u32 = 2;
+
+static mut stack1: [u32; 256] = [0; 256];
+static mut stack2: [u32; 256] = [0; 256];
+
+static msg1: Message<u32> = Message::new(
+    TaskMask::generate([task2]),
+    TaskMask::generate([task2]),
+    0,
+);
+
+// Task 1 drops its privileges and then uses each syscall: the resource it creates is only shared with
+// existing tasks, hence a mask with task 3 fails with `NotFound`; the broadcast releases task 2, which
+// receives `Some(42)`.
+#[entry]
+fn main() -> ! {
+    init().unwrap();
+    // Unprivileged tasks can only broadcast registered messages.
+    msg1.register();
+
+    spawn!(task1, stack1, {
+        syscall::drop_privileges();
+        let res1 = syscall::create_resource(0u32, TaskMask::generate([task1, task2]));
+        hprintln!("TASK 1: create resource : {:?}", res1.map(|_| ()));
+        let res2 = syscall::create_resource(0u32, TaskMask::generate([task1, 3]));
+        hprintln!("TASK 1: create resource with task 3 : {:?}", res2.map(|_| ()));
+        hprintln!("TASK 1: broadcast : {:?}", syscall::broadcast(&msg1, Some(42)));
+        syscall::release(TaskMask::generate([task2]));
+        hprintln!("TASK 1: END");
+    });
+    spawn!(task2, stack2, {
+        hprintln!("TASK 2: received : {:?}", msg1.receive());
+    });
+
+    release(TaskMask::generate([task1]));
+    start_kernel().unwrap()
+}
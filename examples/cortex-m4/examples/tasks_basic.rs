@@ -48,6 +48,8 @@ fn main() -> ! {
     static mut stack2: [u32; 128] = [0; 128];
     static mut stack3: [u32; 128] = [0; 128];
 
+    init().unwrap();
+
     spawn!(task1, stack1, {
         hprintln!("TASK 1");
     });
@@ -60,7 +62,6 @@ fn main() -> ! {
 
 
     // Initializes the kernel in preemptive mode.
-    init();
 
     // Releases tasks task1, task2, task3
     // logging::set_all(true);
@@ -71,5 +72,5 @@ fn main() -> ! {
     It requires a reference to the peripherals so as to start the SysTick timer.
     150_000 corresponds to the tick interval of the SysTick timer.
     */
-    start_kernel()
+    start_kernel().unwrap()
 }
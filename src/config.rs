@@ -2,10 +2,13 @@
 
 #[cfg(all(
     any(feature = "tasks_32",feature="default"),
-    not(any(feature="tasks_16",feature="tasks_8"))
+    not(any(feature="tasks_16",feature="tasks_8",feature="tasks_64"))
 ))]
 pub const MAX_TASKS: usize = 32;
 
+#[cfg(feature = "tasks_64")]
+pub const MAX_TASKS: usize = 64;
+
 #[cfg(all(feature = "tasks_16",not(feature="tasks_32")))]
 pub const MAX_TASKS: usize = 16;
 
@@ -33,4 +36,15 @@ pub const EVENT_COUNT: usize = 16;
 #[cfg(feature = "events_64")]
 pub const EVENT_COUNT: usize = 64;
 
-pub const MAX_LOGS: usize = 128;
\ No newline at end of file
+pub const MAX_LOGS: usize = 128;
+
+/// The maximum number of words of a task stack which `paint_stack` fills with the paint value; the unused
+/// stack of a task is measured up to this many words.
+pub const WATERMARK_FILL_LIMIT: usize = 256;
+
+pub const MAX_DEFERRED_WORK: usize = 16;
+
+/// The number of device interrupts which can be routed to tasks with `on_irq`.
+pub const NVIC_IRQ_COUNT: usize = 96;
+
+pub const MAX_SOFT_TIMERS: usize = 8;
\ No newline at end of file
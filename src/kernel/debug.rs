@@ -0,0 +1,89 @@
+//! # Debugger Support
+//!
+//! Exposes the state of the scheduler to kernel-aware debuggers (e.g. GDB or OpenOCD scripts) which
+//! inspect a halted target, without them depending on the layout of `Scheduler`.
+
+use crate::kernel::tasks::TaskManager;
+use crate::system::scheduler::{BooleanVector, Scheduler};
+use crate::config::MAX_TASKS;
+use crate::utils::arch::critical_section;
+
+/// The version of `KernelDebugDescriptor`, incremented whenever its layout changes.
+pub const KERNEL_DEBUG_VERSION: u32 = 2;
+
+/// Describes where a debugger finds the scheduler state in memory. All offsets are in bytes from
+/// `scheduler_addr`; the task state vectors are `vector_size` bytes wide, with a bit per TaskId.
+/// With the `tasks_64` feature the vectors are 8 bytes wide, and `kernel_ready_mask` and `kernel_blocked_mask`
+/// return a `u64`, in `r0` (low word) and `r1` (high word); else they return a `u32` in `r0`.
+#[repr(C)]
+pub struct KernelDebugDescriptor {
+    /// `KERNEL_DEBUG_VERSION`; 0 until the Kernel is initialized.
+    pub version: u32,
+    /// The number of task slots.
+    pub max_tasks: u32,
+    /// The size of a task state vector in bytes.
+    pub vector_size: u32,
+    /// The address of the scheduler state.
+    pub scheduler_addr: u32,
+    /// The offset of the TaskId of the running task (a `usize`), which is `max_tasks` while the idle task runs.
+    pub curr_tid_offset: u32,
+    /// The offset of the vector of the active tasks.
+    pub active_tasks_offset: u32,
+    /// The offset of the vector of the blocked tasks.
+    pub blocked_tasks_offset: u32,
+    /// The offset of the vector of the suspended tasks.
+    pub suspended_tasks_offset: u32,
+    /// The offset of the vector of the faulted tasks.
+    pub faulted_tasks_offset: u32,
+}
+
+/// The descriptor read by debuggers, it is filled in by `init`.
+#[no_mangle]
+pub static mut KERNEL_DEBUG_DESCRIPTOR: KernelDebugDescriptor = KernelDebugDescriptor {
+    version: 0,
+    max_tasks: MAX_TASKS as u32,
+    vector_size: core::mem::size_of::<BooleanVector>() as u32,
+    scheduler_addr: 0,
+    curr_tid_offset: 0,
+    active_tasks_offset: 0,
+    blocked_tasks_offset: 0,
+    suspended_tasks_offset: 0,
+    faulted_tasks_offset: 0,
+};
+
+/// Fills in `KERNEL_DEBUG_DESCRIPTOR` with the location of the scheduler state.
+pub fn init_descriptor() {
+    critical_section(|cs_token| {
+        let handler: &Scheduler = &TaskManager.borrow(cs_token).borrow();
+        let base = handler as *const Scheduler as usize;
+        let offset = |field: usize| (field - base) as u32;
+        unsafe {
+            KERNEL_DEBUG_DESCRIPTOR.scheduler_addr = base as u32;
+            KERNEL_DEBUG_DESCRIPTOR.curr_tid_offset = offset(&handler.curr_tid as *const usize as usize);
+            KERNEL_DEBUG_DESCRIPTOR.active_tasks_offset = offset(&handler.active_tasks as *const BooleanVector as usize);
+            KERNEL_DEBUG_DESCRIPTOR.blocked_tasks_offset = offset(&handler.blocked_tasks as *const BooleanVector as usize);
+            KERNEL_DEBUG_DESCRIPTOR.suspended_tasks_offset = offset(&handler.suspended_tasks as *const BooleanVector as usize);
+            KERNEL_DEBUG_DESCRIPTOR.faulted_tasks_offset = offset(&handler.faulted_tasks as *const BooleanVector as usize);
+            KERNEL_DEBUG_DESCRIPTOR.version = KERNEL_DEBUG_VERSION;
+        }
+    })
+}
+
+/// Returns the boolean vector of the tasks in the ready state. It can be called by a debugger on a halted target.
+/// The target may have been halted while the scheduler is borrowed, hence it is read through a raw pointer,
+/// like the panic handler does, rather than borrowed.
+#[no_mangle]
+pub extern "C" fn kernel_ready_mask() -> BooleanVector {
+    critical_section(|cs_token| {
+        let handler = unsafe { &*TaskManager.borrow(cs_token).as_ptr() };
+        handler.active_tasks & !handler.blocked_tasks & !handler.suspended_tasks & !handler.faulted_tasks
+            & !handler.sleeping_tasks & !handler.pi_waiting
+    })
+}
+
+/// Returns the boolean vector of the blocked tasks. It can be called by a debugger on a halted target; like
+/// `kernel_ready_mask`, it reads the scheduler through a raw pointer.
+#[no_mangle]
+pub extern "C" fn kernel_blocked_mask() -> BooleanVector {
+    critical_section(|cs_token| unsafe { (*TaskManager.borrow(cs_token).as_ptr()).blocked_tasks })
+}
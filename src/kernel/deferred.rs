@@ -0,0 +1,116 @@
+//! # Deferred Work Management Module
+//!
+//! Defines Kernel routines which let interrupt handlers defer work to task context.
+//!
+//! This is the Kernel's bottom-half (tasklet) mechanism: a handler is registered for a work id with
+//! `register_deferred`, an interrupt handler calls `defer` with the id, and the worker task set with
+//! `set_worker` runs the handler in task context via `process`. Work which needs an argument is queued
+//! with `defer_call` instead.
+
+use core::cell::RefCell;
+
+use crate::KernelError;
+use crate::config::MAX_TASKS;
+use crate::kernel::tasks::{release, schedule};
+use crate::system::deferred::*;
+use crate::system::scheduler::TaskId;
+use crate::utils::arch::{Mutex,critical_section};
+
+/// Global instance of the deferred work queue.
+static WorkQueueGlobal: Mutex<RefCell<WorkQueue>> = Mutex::new(RefCell::new(WorkQueue::new()));
+
+/// Sets the task which drains the deferred work queue, it calls `process` in its body. Deferred work runs
+/// at its priority; e.g. a low priority worker keeps deferred work from delaying the other tasks.
+/// Returns `KernelError::NotFound` if `tid` is not below `MAX_TASKS`; in particular the idle task (`IDLE_TASK`),
+/// which is never released, can't be the worker.
+pub fn set_worker(tid: TaskId) -> Result<(),KernelError> {
+    if tid as usize >= MAX_TASKS {
+        return Err(KernelError::NotFound);
+    }
+    critical_section(|cs_token| {
+        WorkQueueGlobal.borrow(cs_token).borrow_mut().worker = Some(tid);
+    });
+    Ok(())
+}
+
+/// Registers `handler` as the deferred work `work_id`, which `defer` schedules. Returns
+/// `KernelError::LimitExceeded` if `work_id` is not below `DEFERRED_SLOTS`.
+pub fn register_deferred(work_id: usize, handler: fn()) -> Result<(),KernelError> {
+    critical_section(|cs_token| WorkQueueGlobal.borrow(cs_token).borrow_mut().register(work_id, handler))
+}
+
+/// Marks the deferred work `work_id` pending, and releases the worker, which calls its handler. Deferring
+/// the work again before it runs does not run it twice. It is intended to be called from interrupt handlers,
+/// keeping them short. Returns `KernelError::NotFound` if no handler is registered for `work_id`.
+pub fn defer(work_id: usize) -> Result<(),KernelError> {
+    let worker = critical_section(|cs_token| {
+        let queue = &mut WorkQueueGlobal.borrow(cs_token).borrow_mut();
+        queue.set_pending(work_id)?;
+        Ok(queue.worker)
+    })?;
+    release_worker(worker);
+    Ok(())
+}
+
+/// Enqueues `handler` to be called with `arg` from the worker task, and releases the worker.
+/// It is intended to be called from interrupt handlers, keeping them short.
+pub fn defer_call(handler: fn(u32), arg: u32) -> Result<(),KernelError> {
+    let worker = critical_section(|cs_token| {
+        let queue = &mut WorkQueueGlobal.borrow(cs_token).borrow_mut();
+        queue.push(Work::new(handler, arg))?;
+        Ok(queue.worker)
+    })?;
+    release_worker(worker);
+    Ok(())
+}
+
+fn release_worker(worker: Option<TaskId>) {
+    if let Some(worker) = worker {
+        release(1 << worker);
+        schedule();
+    }
+}
+
+/// Executes all work items in the deferred work queue, and then the pending deferred work. Work is
+/// executed outside the critical section, hence interrupts may defer further work meanwhile.
+pub fn process() {
+    while let Some(work) = critical_section(|cs_token| WorkQueueGlobal.borrow(cs_token).borrow_mut().pop()) {
+        work.run();
+    }
+    while let Some(handler) = critical_section(|cs_token| WorkQueueGlobal.borrow(cs_token).borrow_mut().take_pending()) {
+        handler();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::system::scheduler::IDLE_TASK;
+    use core::sync::atomic::{AtomicU32, Ordering};
+
+    static RUNS: AtomicU32 = AtomicU32::new(0);
+
+    fn count() {
+        RUNS.fetch_add(1, Ordering::SeqCst);
+    }
+
+    #[test]
+    fn deferred_work_runs_in_the_worker() {
+        register_deferred(5, count).unwrap();
+        // As from an interrupt handler, the work is only marked pending.
+        defer(5).unwrap();
+        assert_eq!(RUNS.load(Ordering::SeqCst), 0);
+        // As from the body of the worker task.
+        process();
+        assert_eq!(RUNS.load(Ordering::SeqCst), 1);
+        process();
+        assert_eq!(RUNS.load(Ordering::SeqCst), 1);
+        assert!(matches!(defer(6), Err(KernelError::NotFound)));
+    }
+
+    #[test]
+    fn worker_must_be_a_task() {
+        assert!(matches!(set_worker(MAX_TASKS as TaskId), Err(KernelError::NotFound)));
+        assert!(matches!(set_worker(IDLE_TASK), Err(KernelError::NotFound)));
+    }
+}
@@ -26,6 +26,16 @@ pub fn sweep_event_table() {
     })
 }
 
+/// Returns the number of ticks until an enabled event is next dispatched.
+pub fn ticks_to_next_dispatch(curr_time: u32) -> Option<u32> {
+    critical_section(|cs_token| {
+        event_manager
+            .borrow(cs_token)
+            .borrow()
+            .ticks_to_next_dispatch(curr_time)
+    })
+}
+
 /// This function is used to enable events.
 pub fn enable(event_id: EventId) -> Result<(),KernelError> {
     critical_section(|cs_token| {
@@ -0,0 +1,118 @@
+//! # Interrupt Routing
+//!
+//! A table of the tasks to release on each device interrupt, so that interrupt handlers do not hard code
+//! the task masks they release.
+
+use core::cell::RefCell;
+
+use cortex_m::interrupt::Nr;
+use cortex_m::peripheral::SCB;
+use cortex_m::peripheral::scb::VectActive;
+
+use crate::KernelError;
+use crate::config::{MAX_TASKS, NVIC_IRQ_COUNT};
+use crate::kernel::tasks::{release, schedule};
+use crate::system::scheduler::{BooleanVector, TaskId};
+use crate::utils::arch::{Mutex, critical_section};
+
+#[cfg(feature = "system_logger")]
+use {
+    crate::system::system_logger::LogEventType,
+    crate::kernel::logging,
+};
+
+/// For each interrupt number, the boolean vector of the tasks it releases.
+static IrqTable: Mutex<RefCell<[BooleanVector; NVIC_IRQ_COUNT]>> = Mutex::new(RefCell::new([0; NVIC_IRQ_COUNT]));
+
+/// Registers the task `task` to be released whenever the interrupt `irq` is dispatched. An interrupt can
+/// release several tasks. Returns `KernelError::InvalidArgument` if `irq` is not below `NVIC_IRQ_COUNT`
+/// or `task` is not below `MAX_TASKS`.
+pub fn on_irq<I: Nr>(irq: I, task: TaskId) -> Result<(), KernelError> {
+    let irqn = irq.nr() as usize;
+    if irqn >= NVIC_IRQ_COUNT || task as usize >= MAX_TASKS {
+        return Err(KernelError::InvalidArgument);
+    }
+    critical_section(|cs_token| {
+        IrqTable.borrow(cs_token).borrow_mut()[irqn] |= 1 << task;
+    });
+    Ok(())
+}
+
+/// Returns true if the task `task` is registered for any interrupt.
+pub(crate) fn is_routed(task: TaskId) -> bool {
+    critical_section(|cs_token| IrqTable.borrow(cs_token).borrow().iter().any(|&tasks_mask| tasks_mask & (1 << task) != 0))
+}
+
+/// Removes all the tasks registered for the interrupt `irq`.
+pub fn clear_irq<I: Nr>(irq: I) {
+    let irqn = irq.nr() as usize;
+    if irqn < NVIC_IRQ_COUNT {
+        critical_section(|cs_token| IrqTable.borrow(cs_token).borrow_mut()[irqn] = 0);
+    }
+}
+
+/// Releases the tasks registered for the interrupt number `irqn`, and schedules them if they have a higher
+/// priority than the interrupted task. Returns the boolean vector of the released tasks.
+pub fn dispatch_irq(irqn: u8) -> BooleanVector {
+    let tasks_mask = critical_section(|cs_token| {
+        IrqTable.borrow(cs_token).borrow().get(irqn as usize).copied().unwrap_or(0)
+    });
+    if tasks_mask != 0 {
+        #[cfg(feature = "system_logger")] {
+            if logging::get_release() {
+                logging::report(LogEventType::IrqRelease(irqn, tasks_mask));
+            }
+        }
+        release(tasks_mask);
+        schedule();
+    }
+    tasks_mask
+}
+
+/// Like `dispatch_irq`, for the interrupt being serviced. It is meant to be called from within a device
+/// interrupt handler; elsewhere no task is released.
+pub fn dispatch_active_irq() -> BooleanVector {
+    match SCB::vect_active() {
+        VectActive::Interrupt { irqn } => dispatch_irq(irqn),
+        _ => 0,
+    }
+}
+
+// The tests rely on preemption, which the `cooperative` feature disables.
+#[cfg(all(test, not(feature = "cooperative")))]
+mod tests {
+    use super::*;
+    use crate::kernel::tasks::{current_task, get_active_tasks, task_exit};
+    use crate::utils::host::{boot, kernel_test, run_isr, take_trace, trace};
+
+    struct Irq(u8);
+
+    unsafe impl Nr for Irq {
+        fn nr(&self) -> u8 {
+            self.0
+        }
+    }
+
+    #[test]
+    fn dispatch_releases_the_routed_tasks() {
+        fn task() -> ! {
+            loop {
+                trace(current_task());
+                task_exit();
+            }
+        }
+        let _kernel = kernel_test();
+        boot(&[(1, task), (2, task)]);
+        assert!(matches!(on_irq(Irq(NVIC_IRQ_COUNT as u8), 1), Err(KernelError::InvalidArgument)));
+        assert!(matches!(on_irq(Irq(5), MAX_TASKS as TaskId), Err(KernelError::InvalidArgument)));
+        on_irq(Irq(5), 1).unwrap();
+        on_irq(Irq(5), 2).unwrap();
+        let (released, active) = run_isr(|| (dispatch_irq(5), get_active_tasks()));
+        // The routing is global, a task left routed would be pinned in other tests.
+        clear_irq(Irq(5));
+        assert_eq!((released, active), (0b110, 0b110));
+        assert_eq!(take_trace(), [2, 1]);
+        assert!(!is_routed(1) && !is_routed(2));
+        assert_eq!(run_isr(|| dispatch_irq(5)), 0);
+    }
+}
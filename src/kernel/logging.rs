@@ -1,4 +1,5 @@
 use core::cell::RefCell;
+use core::fmt;
 
 use crate::KernelError;
 use crate::priv_execute;
@@ -6,19 +7,51 @@ use crate::system::scheduler::*;
 use crate::utils::arch::{svc_call,Mutex,critical_section};
 use crate::utils::arch::is_privileged;
 use crate::system::system_logger::*;
+use crate::system::log_sink::LogSink;
 use crate::kernel::timer::get_time;
 
 static Logger: Mutex<RefCell<SystemLogger>> = Mutex::new(RefCell::new(SystemLogger::new()));
 
+/// Reports a log event, timestamped with the current Kernel time. If a sink has been registered with
+/// `set_sink`, the record is written through it, else it is buffered until it is handled with `process`.
+/// Events which concern none of the tasks in the task filter (see `set_task_log_filter`) are dropped.
 pub fn report(event_type: LogEventType) {
     critical_section(|cs_token| {
-        Logger.borrow(cs_token).borrow_mut().push(LogEvent::new(event_type, get_time()));
+        let logger = &mut Logger.borrow(cs_token).borrow_mut();
+        if event_type.tasks() & logger.task_filter == 0 {
+            return;
+        }
+        let record = LogRecord::new(event_type, get_time());
+        match logger.sink {
+            Some(sink) => sink.write_event(&record),
+            None => logger.push(record),
+        }
+    })
+}
+
+/// Moves the oldest buffered log events into `out`, and returns the number of events moved.
+/// It lets a low-priority task flush the logs later over any transport, events which do not fit into
+/// `out` are left for the next call.
+pub fn drain_logs(out: &mut [LogRecord]) -> usize {
+    critical_section(|cs_token| Logger.borrow(cs_token).borrow_mut().drain_logs(out))
+}
+
+/// Returns the current Kernel time in ticks, the clock with which log events are timestamped.
+/// Application code can use it to timestamp its own events on the same clock.
+pub fn now_ticks() -> u32 {
+    get_time()
+}
+
+/// Registers the sink to which log events are written, it is meant to be called once during initialization.
+pub fn set_sink(sink: &'static dyn LogSink) {
+    critical_section(|cs_token| {
+        Logger.borrow(cs_token).borrow_mut().sink = Some(sink);
     })
 }
 
 pub fn process<F> (handler: F) 
 where
-    F: Fn(LogEvent),
+    F: Fn(LogRecord),
 {
     critical_section(|cs_token| {
         while let Some(event) = Logger.borrow(cs_token).borrow_mut().pop() {
@@ -27,6 +60,75 @@ where
     })
 }
 
+/// The size of the buffer into which a `write!` call to `KernelLogger` is formatted, on the stack of the
+/// caller. Longer text is truncated.
+pub const TEXT_BUFFER_SIZE: usize = 128;
+
+/// A `core::fmt::Write` target for free-form text logs. A whole `write!` call is formatted into a buffer
+/// of `TEXT_BUFFER_SIZE` bytes, which is then handed to the text sink (see `set_text_sink`) in a single
+/// critical section, hence log lines from different tasks do not interleave, and the formatting does not
+/// delay interrupts. Text is dropped until a text sink is set.
+///
+/// ## Example
+/// ```rust
+/// write!(KernelLogger, "x = {}\n", x);
+/// ```
+pub struct KernelLogger;
+
+impl fmt::Write for KernelLogger {
+    fn write_str(&mut self, text: &str) -> fmt::Result {
+        critical_section(|cs_token| {
+            if let Some(sink) = Logger.borrow(cs_token).borrow().text_sink {
+                sink(text);
+            }
+        });
+        Ok(())
+    }
+
+    fn write_fmt(&mut self, args: fmt::Arguments<'_>) -> fmt::Result {
+        let mut buffer = TextBuffer::new();
+        fmt::write(&mut buffer, args)?;
+        self.write_str(buffer.as_str())
+    }
+}
+
+/// The text of a `write!` call, truncated to `TEXT_BUFFER_SIZE` bytes.
+struct TextBuffer {
+    bytes: [u8; TEXT_BUFFER_SIZE],
+    len: usize,
+}
+
+impl TextBuffer {
+    const fn new() -> Self {
+        Self { bytes: [0; TEXT_BUFFER_SIZE], len: 0 }
+    }
+
+    fn as_str(&self) -> &str {
+        // Only whole characters are copied into the buffer.
+        core::str::from_utf8(&self.bytes[..self.len]).unwrap_or("")
+    }
+}
+
+impl fmt::Write for TextBuffer {
+    fn write_str(&mut self, text: &str) -> fmt::Result {
+        let mut len = text.len().min(TEXT_BUFFER_SIZE - self.len);
+        while !text.is_char_boundary(len) {
+            len -= 1;
+        }
+        self.bytes[self.len..self.len + len].copy_from_slice(&text.as_bytes()[..len]);
+        self.len += len;
+        Ok(())
+    }
+}
+
+/// Sets the sink to which `KernelLogger` writes text logs (RTT, UART, etc.). Semihosting is not used by
+/// default, as it halts the core on every write.
+pub fn set_text_sink(sink: fn(&str)) {
+    critical_section(|cs_token| {
+        Logger.borrow(cs_token).borrow_mut().text_sink = Some(sink);
+    })
+}
+
 pub fn set_all(val: bool) {
     critical_section(|cs_token| {
         Logger.borrow(cs_token).borrow_mut().release_log = val;
@@ -43,6 +145,13 @@ pub fn set_all(val: bool) {
     })
 }
 
+/// Restricts logging to the events which concern the tasks in `mask`, it is all tasks by default.
+pub fn set_task_log_filter(mask: BooleanVector) {
+    critical_section(|cs_token| {
+        Logger.borrow(cs_token).borrow_mut().task_filter = mask;
+    })
+}
+
 pub fn set_release(val: bool) {
     critical_section(|cs_token| {
         Logger.borrow(cs_token).borrow_mut().release_log = val;
@@ -173,4 +282,41 @@ pub fn get_timer_event() -> bool {
     critical_section(|cs_token| {
         Logger.borrow(cs_token).borrow_mut().timer_event_log
     })
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::kernel::timer::update_time;
+
+    #[test]
+    fn sequential_events_have_non_decreasing_ticks() {
+        report(LogEventType::ReleaseTasks(1 << 1));
+        update_time(1);
+        report(LogEventType::ReleaseTasks(1 << 2));
+        let mut records = [LogRecord::new(LogEventType::ReleaseTasks(0), 0); 2];
+        assert_eq!(drain_logs(&mut records), 2);
+        assert!(records[0].tick <= records[1].tick);
+        assert!(records[1].tick <= now_ticks());
+    }
+
+    static TEXT: std::sync::Mutex<Vec<String>> = std::sync::Mutex::new(Vec::new());
+
+    fn text_sink(text: &str) {
+        TEXT.lock().unwrap().push(text.into());
+    }
+
+    #[test]
+    fn write_is_handed_to_the_sink_at_once() {
+        use core::fmt::Write;
+        write!(KernelLogger, "dropped").unwrap();
+        set_text_sink(text_sink);
+        write!(KernelLogger, "x = {}, y = {}", 1, 2).unwrap();
+        write!(KernelLogger, "{:é<1$}", "", TEXT_BUFFER_SIZE).unwrap();
+        let text = TEXT.lock().unwrap();
+        assert_eq!(text[0], "x = 1, y = 2");
+        // The text is truncated to whole characters.
+        assert_eq!(text[1], "é".repeat(TEXT_BUFFER_SIZE / 2));
+        assert_eq!(text.len(), 2);
+    }
+}
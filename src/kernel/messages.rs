@@ -0,0 +1,74 @@
+//! # Message Registry Module
+//!
+//! Keeps track of the messages in use, so that the Kernel can remove a deleted task from their masks.
+//! A message is registered the first time it is broadcast, received or subscribed to; the registered
+//! messages form an intrusive list, hence the registry has no capacity limit.
+
+use core::alloc::Layout;
+use core::cell::RefCell;
+
+use crate::KernelError;
+use crate::system::scheduler::TaskId;
+use crate::utils::arch::{Mutex,critical_section};
+
+/// The part of a message which does not depend on its value type.
+pub(crate) trait RegisteredMessage: Sync {
+    /// Removes the task `task` from the receivers of the message and from the tasks it releases.
+    fn unregister_receiver(&'static self, task: TaskId);
+    /// Moves the task `from` to the TaskId `to` in the masks of the message, e.g. when its priority changes.
+    fn move_receiver(&'static self, from: TaskId, to: TaskId);
+    /// Returns the message registered before this one.
+    fn next(&self) -> Option<&'static dyn RegisteredMessage>;
+    /// Returns the layout of the `Option` of the message value type, which `broadcast_raw` reads.
+    fn value_layout(&self) -> Layout;
+    /// Takes the value out of the `Option` at the address `value` and broadcasts it, see `Message::broadcast`.
+    ///
+    /// # Safety
+    /// `value` must address a valid `Option` of the message value type, see `value_layout`.
+    unsafe fn broadcast_raw(&'static self, value: usize) -> Result<u32, KernelError>;
+}
+
+/// The most recently registered message, the head of the list.
+static Messages: Mutex<RefCell<Option<&'static dyn RegisteredMessage>>> = Mutex::new(RefCell::new(None));
+
+/// Makes `message` the head of the list, and returns the previous head, which the message must link to.
+/// The caller must link it within the same critical section.
+pub(crate) fn register(message: &'static dyn RegisteredMessage) -> Option<&'static dyn RegisteredMessage> {
+    critical_section(|cs_token| Messages.borrow(cs_token).replace(Some(message)))
+}
+
+/// Removes the task `task` from the masks of all registered messages.
+pub(crate) fn unregister_task(task: TaskId) {
+    critical_section(|cs_token| {
+        let mut message = *Messages.borrow(cs_token).borrow();
+        while let Some(registered) = message {
+            registered.unregister_receiver(task);
+            message = registered.next();
+        }
+    })
+}
+
+/// Moves the task `from` to the TaskId `to` in the masks of all registered messages.
+pub(crate) fn move_task(from: TaskId, to: TaskId) {
+    critical_section(|cs_token| {
+        let mut message = *Messages.borrow(cs_token).borrow();
+        while let Some(registered) = message {
+            registered.move_receiver(from, to);
+            message = registered.next();
+        }
+    })
+}
+
+/// Returns the registered message at the address `address`, if any.
+pub(crate) fn find(address: usize) -> Option<&'static dyn RegisteredMessage> {
+    critical_section(|cs_token| {
+        let mut message = *Messages.borrow(cs_token).borrow();
+        while let Some(registered) = message {
+            if registered as *const dyn RegisteredMessage as *const u8 as usize == address {
+                return Some(registered);
+            }
+            message = registered.next();
+        }
+        None
+    })
+}
@@ -1,6 +1,10 @@
 //! Kernel module and routines declaration. `Private`
 
 pub mod tasks;
+pub mod deferred;
+pub mod syscall;
+pub mod interrupts;
+pub mod messages;
 
 #[cfg(any(feature = "events_32", feature = "events_16", feature = "events_64"))]
 pub mod events;
@@ -12,4 +16,19 @@ pub mod logging;
 pub mod task_monitor;
 
 #[cfg(feature="timer")]
-pub mod timer;
\ No newline at end of file
+pub mod timer;
+
+#[cfg(feature="timer")]
+pub mod soft_timer;
+
+#[cfg(feature="timer")]
+pub mod watchdog;
+
+#[cfg(feature="tickless")]
+pub mod tickless;
+
+#[cfg(feature="debug_support")]
+pub mod debug;
+
+#[cfg(feature="panic_task_exit")]
+pub mod panic;
\ No newline at end of file
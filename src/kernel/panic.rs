@@ -0,0 +1,163 @@
+//! # Panic Handler
+//!
+//! A panic in a task halts only that task, the other tasks keep being scheduled. A panic in an interrupt
+//! handler, in the idle task, or while the scheduler state is borrowed is unrecoverable, and halts the CPU.
+//! Either way, the hook registered with `set_panic_hook` is called first, e.g. to report a broken
+//! `kernel_assert!` invariant along with the task which was running.
+
+use core::cell::Cell;
+
+use crate::kernel::tasks::TaskManager;
+use crate::system::scheduler::{TaskId, IDLE_TASK};
+use crate::utils::arch::{Mutex, critical_section};
+
+#[cfg(not(test))]
+use {
+    core::sync::atomic::{compiler_fence, Ordering},
+    cortex_m::peripheral::SCB,
+    cortex_m::peripheral::scb::VectActive,
+    crate::kernel::tasks::schedule,
+    crate::utils::arch::wait_for_interrupt,
+};
+
+#[cfg(feature = "system_logger")]
+use {
+    crate::system::system_logger::LogEventType,
+    crate::kernel::logging,
+};
+
+/// The panic passed to the hook; on a host (`cfg(test)`) that of the std panic hook, which calls `run_panic_hook`.
+#[cfg(not(test))]
+pub type PanicInfo<'a> = core::panic::PanicInfo<'a>;
+#[cfg(test)]
+pub type PanicInfo<'a> = std::panic::PanicHookInfo<'a>;
+
+/// The hook called on a panic, with the TaskId of the running task.
+static PanicHook: Mutex<Cell<Option<fn(&PanicInfo, TaskId)>>> = Mutex::new(Cell::new(None));
+
+/// Registers the hook called with the panic and the TaskId of the running task, before the task or the
+/// CPU is halted. It runs in the context which panicked, with the Kernel state possibly inconsistent;
+/// hence it must only report, e.g. log or store the panic.
+pub fn set_panic_hook(hook: fn(&PanicInfo, TaskId)) {
+    critical_section(|cs_token| PanicHook.borrow(cs_token).set(Some(hook)))
+}
+
+/// Calls the registered hook, if any, with `info` and the TaskId of the running task.
+pub(crate) fn run_panic_hook(info: &PanicInfo) {
+    let (hook, curr_tid) = critical_section(|cs_token| {
+        // The scheduler state is possibly borrowed by the code which panicked, and it is never released.
+        let curr_tid = unsafe { (*TaskManager.borrow(cs_token).as_ptr()).curr_tid };
+        (PanicHook.borrow(cs_token).get(), curr_tid as TaskId)
+    });
+    if let Some(hook) = hook {
+        hook(info, curr_tid);
+    }
+}
+
+/// Halts the running task after it has panicked in thread mode, and returns its TaskId; None if the
+/// panic is unrecoverable, i.e. the Kernel has not started, the idle task panicked, or the scheduler
+/// state is borrowed.
+pub(crate) fn halt_panicking_task() -> Option<TaskId> {
+    critical_section(|cs_token| {
+        let handler = &mut TaskManager.borrow(cs_token).try_borrow_mut().ok()?;
+        if !handler.started || handler.curr_tid == IDLE_TASK as usize {
+            return None;
+        }
+        // The task is never resumed, as its stack is left in the middle of the panicking function;
+        // it can be recovered with `restart_task`.
+        handler.halt_curr_task().ok();
+        Some(handler.curr_tid as TaskId)
+    })
+}
+
+#[cfg(not(test))]
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    run_panic_hook(info);
+    let halted_task = match SCB::vect_active() {
+        VectActive::ThreadMode => halt_panicking_task(),
+        _ => None,
+    };
+    match halted_task {
+        Some(_tid) => {
+            #[cfg(feature = "system_logger")] {
+                let line = info.location().map(|location| location.line()).unwrap_or(0);
+                logging::report(LogEventType::TaskPanic(_tid, line));
+            }
+            schedule();
+            loop {
+                wait_for_interrupt();
+            }
+        },
+        None => {
+            let _ = info;
+            cortex_m::interrupt::disable();
+            loop {
+                compiler_fence(Ordering::SeqCst);
+            }
+        },
+    }
+}
+
+#[cfg(all(test, not(feature = "cooperative")))]
+mod tests {
+    use super::*;
+    use crate::kernel::tasks::{release_tasks, schedule, task_exit, task_state};
+    use crate::system::scheduler::TaskState;
+    use crate::utils::host::{boot, kernel_test, take_trace, trace};
+
+    #[test]
+    fn hook_receives_the_panicking_task() {
+        fn hook(info: &PanicInfo, tid: TaskId) {
+            // Panics of other tests, and that of the test thread below, are not traced.
+            if info.payload_as_str().map_or(false, |msg| msg.ends_with("the task 2 broke")) {
+                trace(tid);
+            }
+        }
+        fn task2() -> ! {
+            loop {
+                crate::kernel_assert!(false, "the task 2 broke");
+            }
+        }
+        let _kernel = kernel_test();
+        set_panic_hook(hook);
+        std::panic::set_hook(Box::new(|info| run_panic_hook(info)));
+        boot(&[(2, task2)]);
+        // The test thread panics as well once the task has panicked, see `utils::host`.
+        let result = std::panic::catch_unwind(|| release_tasks(1 << 2));
+        let _ = std::panic::take_hook();
+        assert!(result.is_err());
+        assert_eq!(take_trace(), [2]);
+    }
+
+    #[test]
+    fn other_tasks_keep_running_after_a_task_panics() {
+        fn task1() -> ! {
+            loop {
+                trace(1);
+                task_exit();
+            }
+        }
+        fn task2() -> ! {
+            loop {
+                trace(2);
+                // The host test thread panics along with a task, hence the panic handler is called by hand.
+                if std::panic::catch_unwind(|| crate::kernel_assert!(false, "the task 2 broke")).is_err() {
+                    trace(halt_panicking_task().unwrap());
+                    schedule();
+                }
+                task_exit();
+            }
+        }
+        let _kernel = kernel_test();
+        boot(&[(1, task1), (2, task2)]);
+        release_tasks(1 << 1 | 1 << 2).unwrap();
+        assert_eq!(take_trace(), [2, 2, 1]);
+        assert_eq!(task_state(2), TaskState::Suspended);
+        // The halted task is skipped when released again.
+        release_tasks(1 << 1 | 1 << 2).unwrap();
+        assert_eq!(take_trace(), [1]);
+        // Once idle, the test thread panics as the idle task would.
+        assert_eq!(halt_panicking_task(), None);
+    }
+}
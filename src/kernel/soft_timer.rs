@@ -0,0 +1,73 @@
+//! # Software Timer Management Module
+//!
+//! Defines Kernel routines for one-shot and periodic software timers. Timers are advanced by the
+//! SysTick handler, but their handlers are dispatched through the deferred work queue; hence they
+//! run in the context of the deferred work task (see `deferred::set_worker`) rather than the interrupt.
+
+use core::cell::RefCell;
+
+use crate::KernelError;
+use crate::config::MAX_SOFT_TIMERS;
+use crate::kernel::deferred::defer_call;
+use crate::system::soft_timer::*;
+use crate::utils::arch::{Mutex,critical_section};
+
+/// Global instance of the software timer table.
+static SoftTimers: Mutex<RefCell<SoftTimerTable>> = Mutex::new(RefCell::new(SoftTimerTable::new()));
+
+/// Handle to a software timer.
+pub struct SoftTimer {
+    id: SoftTimerId,
+}
+
+impl SoftTimer {
+    /// Creates a timer which calls `handler` once, after `ticks` ticks.
+    pub fn after(ticks: u32, handler: fn()) -> Result<Self, KernelError> {
+        let id = critical_section(|cs_token| SoftTimers.borrow(cs_token).borrow_mut().create(ticks, None, handler))?;
+        Ok(Self { id })
+    }
+
+    /// Creates a timer which calls `handler` every `ticks` ticks.
+    pub fn every(ticks: u32, handler: fn()) -> Result<Self, KernelError> {
+        let id = critical_section(|cs_token| SoftTimers.borrow(cs_token).borrow_mut().create(ticks, Some(ticks), handler))?;
+        Ok(Self { id })
+    }
+
+    /// Cancels the timer. Returns `KernelError::NotFound` if it was a one-shot timer which already fired.
+    pub fn cancel(self) -> Result<(), KernelError> {
+        critical_section(|cs_token| SoftTimers.borrow(cs_token).borrow_mut().cancel(self.id))
+    }
+}
+
+/// Advances all software timers by `ticks` ticks and defers the handlers of the timers which fired.
+/// If the deferred work queue is full, the firing is dropped.
+pub fn sweep_soft_timers(ticks: u32) {
+    let fired = critical_section(|cs_token| {
+        let table = &mut SoftTimers.borrow(cs_token).borrow_mut();
+        let fired = table.sweep(ticks);
+        let mut ids = [None; MAX_SOFT_TIMERS];
+        for slot in 0..MAX_SOFT_TIMERS {
+            if fired & (1 << slot) != 0 {
+                ids[slot] = Some(table.id_of(slot));
+            }
+        }
+        ids
+    });
+    // The generation in the id keeps a timer cancelled meanwhile from dispatching a later timer in its slot.
+    for id in fired.iter().flatten() {
+        defer_call(dispatch, id.to_raw()).ok();
+    }
+}
+
+/// Returns the number of ticks until the nearest software timer fires.
+pub fn ticks_to_next_expiry() -> Option<u32> {
+    critical_section(|cs_token| SoftTimers.borrow(cs_token).borrow().ticks_to_next_expiry())
+}
+
+/// Calls the handler of the timer `id` (packed with `SoftTimerId::to_raw`), it is executed from the deferred work task.
+fn dispatch(id: u32) {
+    let handler = critical_section(|cs_token| SoftTimers.borrow(cs_token).borrow_mut().take_handler(SoftTimerId::from_raw(id)));
+    if let Some(handler) = handler {
+        handler();
+    }
+}
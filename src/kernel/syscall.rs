@@ -0,0 +1,166 @@
+//! # System Call Module
+//!
+//! Lets unprivileged tasks perform privileged Kernel operations by trapping into the SVC handler.
+//!
+//! ## SVC number convention
+//! The immediate of the `svc` instruction selects the `Syscall`, arguments are passed in `r0`-`r3` and
+//! the result is returned in `r0`: `0` on success, else the `KernelError::code()` of the error; a syscall
+//! which returns a value places it in `r1`. `Syscall::Schedule` returns nothing, its `r0` is preserved.
+//! Tasks run on the process stack; hence the SVC handler reads the arguments from the exception frame
+//! on the PSP and writes the result back into the stacked `r0` and `r1`.
+//!
+//! The handler runs privileged on behalf of a possibly unprivileged task; hence it validates every argument
+//! which refers to memory or to other tasks, rather than trusting the caller.
+
+use crate::KernelError;
+use crate::config::MAX_TASKS;
+use crate::system::message::Message;
+use crate::system::resource::Resource;
+use crate::system::scheduler::{BooleanVector, VECTOR_BITS};
+use crate::kernel::{messages, tasks};
+#[cfg(test)]
+use crate::utils::host::svc;
+
+/// The operations which can be requested via the SVC instruction, the discriminant is the SVC number.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[repr(u8)]
+pub enum Syscall {
+    /// `tasks::schedule`, i.e. requests a context switch if preemption is enabled, the calling task can no longer
+    /// run or it has yielded. It takes no arguments and returns nothing.
+    Schedule = 1,
+    /// `tasks::release`, `r0` holds the task mask (and `r1` its upper word with the `tasks_64` feature).
+    Release = 2,
+    /// Validates the task mask of a `Resource` created by the calling task, `r0` holds the task mask (and `r1`
+    /// its upper word with the `tasks_64` feature). The mask must include the calling task, and only tasks
+    /// which exist.
+    CreateResource = 3,
+    /// `Message::broadcast`, `r0` holds the address of a registered `Message` and `r1` the address of the
+    /// `Option` of its value type, which must lie within the stack of the calling task. The value is taken out
+    /// of it. `r1` returns the count of `Message::broadcast`.
+    Broadcast = 4,
+}
+
+impl Syscall {
+    fn from_number(number: u8) -> Option<Self> {
+        match number {
+            1 => Some(Syscall::Schedule),
+            2 => Some(Syscall::Release),
+            3 => Some(Syscall::CreateResource),
+            4 => Some(Syscall::Broadcast),
+            _ => None,
+        }
+    }
+}
+
+/// Executes the syscall `number` with the arguments `args`, and returns the values to be placed in `r0` and
+/// `r1`, or `None` if the stacked registers must be left untouched. It is called from the SVC handler, i.e. in
+/// privileged handler mode.
+pub fn dispatch(number: u8, args: [u32; 4]) -> Option<[u32; 2]> {
+    let result = match Syscall::from_number(number) {
+        Some(Syscall::Schedule) => {
+            tasks::schedule();
+            return None;
+        },
+        Some(Syscall::Release) => {
+            tasks::release(mask_of(args));
+            Ok(0)
+        },
+        Some(Syscall::CreateResource) => check_resource_mask(mask_of(args)).map(|_| 0),
+        Some(Syscall::Broadcast) => broadcast_raw(args[0] as usize, args[1] as usize),
+        None => Err(KernelError::NotFound),
+    };
+    Some(encode(result))
+}
+
+/// Returns the task mask passed in `r0` (and `r1`).
+fn mask_of(args: [u32; 4]) -> BooleanVector {
+    (args[0] as u64 | (args[1] as u64) << 32) as BooleanVector
+}
+
+/// Checks that `tasks_mask` refers to existing tasks only, and includes the running task.
+fn check_resource_mask(tasks_mask: BooleanVector) -> Result<(), KernelError> {
+    if MAX_TASKS < VECTOR_BITS && tasks_mask >> MAX_TASKS != 0 {
+        return Err(KernelError::InvalidArgument);
+    }
+    if tasks_mask & !tasks::get_existing_tasks() != 0 {
+        return Err(KernelError::NotFound);
+    }
+    if tasks_mask & (1 << tasks::current_task()) == 0 {
+        return Err(KernelError::AccessDenied);
+    }
+    Ok(())
+}
+
+/// Broadcasts the value at the address `value` on the message at the address `message`. Returns
+/// `KernelError::NotFound` if no message is registered at `message`, and `KernelError::AccessDenied` if the
+/// value does not lie within the stack of the running task.
+fn broadcast_raw(message: usize, value: usize) -> Result<u32, KernelError> {
+    let message = messages::find(message).ok_or(KernelError::NotFound)?;
+    let layout = message.value_layout();
+    if value % layout.align() != 0 || !tasks::curr_stack_contains(value, layout.size()) {
+        return Err(KernelError::AccessDenied);
+    }
+    unsafe { message.broadcast_raw(value) }
+}
+
+fn encode(result: Result<u32, KernelError>) -> [u32; 2] {
+    match result {
+        Ok(value) => [0, value],
+        Err(error) => [error.code() as u32, 0],
+    }
+}
+
+fn decode(code: u32, value: u32) -> Result<u32, KernelError> {
+    match code {
+        0 => Ok(value),
+        code => Err(KernelError::from_code(code as u8).unwrap_or(KernelError::NotFound)),
+    }
+}
+
+/// Releases the tasks in `tasks_mask` via the SVC gateway.
+pub fn release(tasks_mask: BooleanVector) {
+    #[cfg(not(test))]
+    unsafe {
+        asm!("svc 2", inout("r0") tasks_mask as u32 => _, inout("r1") (tasks_mask as u64 >> 32) as u32 => _);
+    }
+    #[cfg(test)]
+    svc(Syscall::Release as u8, [tasks_mask as u32, (tasks_mask as u64 >> 32) as u32, 0, 0]);
+}
+
+/// Creates a `Resource` shared by the tasks in `tasks_mask`, after the Kernel validated the mask via the SVC
+/// gateway. Returns `KernelError::AccessDenied` if the mask does not include the running task,
+/// `KernelError::NotFound` if it refers to a task which does not exist, and `KernelError::InvalidArgument` if
+/// it refers to a task at or above `MAX_TASKS`.
+pub fn create_resource<T: Sized>(val: T, tasks_mask: BooleanVector) -> Result<Resource<T>, KernelError> {
+    #[cfg(not(test))]
+    let (code, value): (u32, u32);
+    #[cfg(not(test))]
+    unsafe {
+        asm!("svc 3", inout("r0") tasks_mask as u32 => code, inout("r1") (tasks_mask as u64 >> 32) as u32 => value);
+    }
+    #[cfg(test)]
+    let [code, value] = svc(Syscall::CreateResource as u8, [tasks_mask as u32, (tasks_mask as u64 >> 32) as u32, 0, 0]);
+    decode(code, value)?;
+    Ok(Resource::new(val, tasks_mask))
+}
+
+/// Broadcasts `msg` on `message` via the SVC gateway, see `Message::broadcast`. The message must have been
+/// registered with `Message::register`, else `KernelError::NotFound` is returned.
+pub fn broadcast<T: Sized + Clone>(message: &'static Message<T>, msg: Option<T>) -> Result<u32, KernelError> {
+    // The Kernel takes the value out of `msg`, which lives on the stack of the running task.
+    let mut msg = msg;
+    #[cfg(not(test))]
+    let (code, value): (u32, u32);
+    #[cfg(not(test))]
+    unsafe {
+        asm!(
+            "svc 4",
+            inout("r0") message as *const Message<T> as u32 => code,
+            inout("r1") &mut msg as *mut Option<T> as u32 => value,
+        );
+    }
+    // The addresses don't fit in the 32 bit registers of the host, hence the validation is bypassed there.
+    #[cfg(test)]
+    let [code, value] = encode(message.broadcast(msg.take()));
+    decode(code, value)
+}
@@ -28,6 +28,17 @@ pub fn clear_deadline(tid: TaskId) {
     })
 }
 
+/// Returns true if a deadline is set for the task `tid`.
+pub(crate) fn has_deadline(tid: TaskId) -> bool {
+    critical_section(|cs_token| TASK_MONITOR.borrow(cs_token).borrow().has_deadline(tid))
+}
+
+pub fn ticks_to_next_deadline(curr_time: u32) -> Option<u32> {
+    critical_section(|cs_token| {
+        TASK_MONITOR.borrow(cs_token).borrow().ticks_to_next_deadline(curr_time)
+    })
+}
+
 pub fn sweep_deadlines() {
     critical_section(|cs_token| {
         TASK_MONITOR.borrow(cs_token).borrow_mut().sweep_deadlines(get_time());
@@ -1,15 +1,36 @@
 //! # Task Management module
 //! Defines Kernel routines which will take care of Task management functionality.
 //! Declares a global instance of Scheduler that will be used by the Kernel routines to provide the functionality.
+//!
+//! `TaskManager` is the only scheduler state in the Kernel. Resources, messages and semaphores access it
+//! exclusively through the routines of this module; only the timer sweep and the exception handlers in
+//! `utils::arch`, which run in the context switch path, borrow it directly within their critical section.
 
 use core::cell::RefCell;
 
 use crate::KernelError;
 use crate::priv_execute;
 use crate::system::scheduler::*;
-use crate::utils::arch::{svc_call,Mutex,critical_section,set_pendsv};
+use crate::utils::arch::{svc_call,Mutex,critical_section,set_pendsv,set_thread_privileged};
 use crate::utils::arch::is_privileged;
 
+#[cfg(feature = "cpu_stats")]
+use crate::utils::arch::enable_cycle_counter;
+#[cfg(feature = "mpu")]
+use crate::utils::arch::enable_mpu;
+
+#[cfg(feature = "timer")]
+use crate::kernel::timer::{cancel_sleep, get_time, sleep_until, wake};
+#[cfg(feature = "timer")]
+use crate::system::sleep_table::{WakeReason, tick_reached};
+use crate::config::MAX_TASKS;
+use crate::kernel::messages::{move_task, unregister_task};
+use crate::kernel::interrupts::is_routed;
+use crate::system::resource::{get_pi_mask, system_ceiling};
+#[cfg(feature = "timer")]
+use crate::kernel::watchdog;
+#[cfg(feature = "task_monitor")]
+use crate::kernel::task_monitor::{clear_deadline, has_deadline};
 #[cfg(feature = "system_logger")]
 use crate::kernel::logging; 
 #[cfg(feature = "system_logger")]
@@ -20,13 +41,32 @@ use crate::system::system_logger::LogEventType;
 pub static TaskManager: Mutex<RefCell<Scheduler>> = Mutex::new(RefCell::new(Scheduler::new()));
 
 /// Initializes the Kernel scheduler and creates the idle task, a task that puts the CPU to sleep in a loop. 
-/// The idle task is created with zero priority; hence, it is only executed when no other task is in Ready state.
+/// The idle task lies below every priority (its TaskId is `IDLE_TASK`); hence, it is only executed when no other
+/// task is in Ready state, and application tasks can use every priority from 0 up to `MAX_TASKS - 1`.
+///
+/// The Kernel must be used in the order `init` → `create_task`/`spawn!` (and `release`) → `start_kernel`.
+/// Errors in creating the idle task are propagated rather than unwrapped.
+/// Calling `init` a second time returns `KernelError::Exists`, or `KernelError::AlreadyRunning` if the Kernel has started.
+///
+/// Scheduling is preemptive, unless the `cooperative` feature is enabled: then the running task is never
+/// preempted, it is switched out only when it yields, blocks or exits; released tasks wait until then.
 pub fn init() -> Result<(),KernelError>{
-    critical_section(|cs_token| TaskManager.borrow(cs_token).borrow_mut().init() )
+    critical_section(|cs_token| TaskManager.borrow(cs_token).borrow_mut().init() )?;
+    #[cfg(feature="debug_support")]
+    crate::kernel::debug::init_descriptor();
+    Ok(())
 }
 
-/// Starts the Kernel scheduler, which starts scheduling tasks on the CPU.
-pub fn start_kernel() -> ! {
+/// Starts the Kernel scheduler, which starts scheduling tasks on the CPU. It returns only on error:
+/// `KernelError::NotInitialized` if `init` was not called, and `KernelError::AlreadyRunning` if the
+/// Kernel has already started.
+///
+/// The Kernel does not take `Peripherals`; the application takes them and lends the ones the Kernel
+/// needs, e.g. `timer::start_timer(&mut peripherals, ...)`.
+pub fn start_kernel() -> Result<!, KernelError> {
+    critical_section(|cs_token| TaskManager.borrow(cs_token).borrow_mut().start())?;
+    #[cfg(feature="cpu_stats")]
+    enable_cycle_counter();
     loop {
         schedule();
     }
@@ -34,6 +74,10 @@ pub fn start_kernel() -> ! {
 
 #[cfg(feature="task_monitor")]
 /// Create a new task with the configuration set as arguments passed.
+/// Each task is given its own stack, hence stacks can be sized per task; `stack` must be at least
+/// 32 words long, else `KernelError::StackTooSmall` is returned.
+/// Returns `KernelError::NotInitialized` if the Kernel has not been initialized with `init`, and
+/// `KernelError::Exists` if a task already has the priority `priority`; the existing task is left untouched.
 pub fn create_task(
     priority: TaskId,
     deadline: u32,
@@ -48,6 +92,10 @@ pub fn create_task(
 
 #[cfg(not(feature="task_monitor"))]
 /// Create a new task with the configuration set as arguments passed.
+/// Each task is given its own stack, hence stacks can be sized per task; `stack` must be at least
+/// 32 words long, else `KernelError::StackTooSmall` is returned.
+/// Returns `KernelError::NotInitialized` if the Kernel has not been initialized with `init`, and
+/// `KernelError::Exists` if a task already has the priority `priority`; the existing task is left untouched.
 pub fn create_task(
     priority: TaskId,
     stack: &mut [u32],
@@ -58,13 +106,201 @@ pub fn create_task(
         critical_section(|cs_token| TaskManager.borrow(cs_token).borrow_mut().create_task(priority as usize, stack, handler_fn))
     })
 }
+#[cfg(feature="task_monitor")]
+/// Creates a new task at the lowest free priority and returns its TaskId, for when the priority of
+/// the task does not matter. Returns `KernelError::LimitExceeded` if every slot is taken.
+pub fn spawn_task(
+    deadline: u32,
+    stack: &mut [u32],
+    handler_fn: fn() -> !,
+) -> Result<TaskId, KernelError>
+{
+    priv_execute!({
+        critical_section(|cs_token| {
+            let handler = &mut TaskManager.borrow(cs_token).borrow_mut();
+            let tid = handler.free_slot()?;
+            handler.create_task(tid, deadline, stack, handler_fn)?;
+            Ok(tid as TaskId)
+        })
+    })
+}
+
+#[cfg(not(feature="task_monitor"))]
+/// Creates a new task at the lowest free priority and returns its TaskId, for when the priority of
+/// the task does not matter. Returns `KernelError::LimitExceeded` if every slot is taken.
+pub fn spawn_task(
+    stack: &mut [u32],
+    handler_fn: fn() -> !,
+) -> Result<TaskId, KernelError>
+{
+    priv_execute!({
+        critical_section(|cs_token| {
+            let handler = &mut TaskManager.borrow(cs_token).borrow_mut();
+            let tid = handler.free_slot()?;
+            handler.create_task(tid, stack, handler_fn)?;
+            Ok(tid as TaskId)
+        })
+    })
+}
+
+#[cfg(all(feature="fpu", feature="task_monitor"))]
+/// Creates a task which uses the FPU. Its floating point registers (S0-S31 and FPSCR) are saved and
+/// restored on context switches, which costs 50 words of its stack; hence `stack` must be at least 64 words long.
+/// Tasks created with `create_task` must not execute floating point instructions, as their FPU state is not preserved.
+pub fn create_task_fpu(
+    priority: TaskId,
+    deadline: u32,
+    stack: &mut [u32],
+    handler_fn: fn() -> !,
+) -> Result<(), KernelError>
+{
+    let mut meta = SchedMeta::new();
+    meta.deadline = deadline;
+    meta.uses_fpu = true;
+    priv_execute!({
+        critical_section(|cs_token| TaskManager.borrow(cs_token).borrow_mut().create_task_with_meta(priority as usize, meta, stack, handler_fn))
+    })
+}
+
+#[cfg(all(feature="fpu", not(feature="task_monitor")))]
+/// Creates a task which uses the FPU. Its floating point registers (S0-S31 and FPSCR) are saved and
+/// restored on context switches, which costs 50 words of its stack; hence `stack` must be at least 64 words long.
+/// Tasks created with `create_task` must not execute floating point instructions, as their FPU state is not preserved.
+pub fn create_task_fpu(
+    priority: TaskId,
+    stack: &mut [u32],
+    handler_fn: fn() -> !,
+) -> Result<(), KernelError>
+{
+    let mut meta = SchedMeta::new();
+    meta.uses_fpu = true;
+    priv_execute!({
+        critical_section(|cs_token| TaskManager.borrow(cs_token).borrow_mut().create_task_with_meta(priority as usize, meta, stack, handler_fn))
+    })
+}
+
+/// Changes the priority of the task `task` to `new_priority`, and then schedules the highest priority task.
+/// As the priority of a task is its TaskId, the task is identified by `new_priority` afterwards; it is moved
+/// along in the masks of the messages in use, but the constant task masks of Resources and Semaphores are not
+/// updated. Returns `KernelError::DoesNotExist` if `task` does not exist, `KernelError::NotFound` if `new_priority`
+/// is out of range, and `KernelError::Exists` if `new_priority` is occupied. `KernelError::AccessDenied` is
+/// returned while Kernel state refers to the task by its TaskId and can't be moved along: while it holds a locked
+/// resource or is blocked by one (i.e. `task` or `new_priority` is not above the system ceiling), is routed an
+/// interrupt, has a watchdog or monitor deadline, sleeps, joins or is joined, takes part in priority inheritance,
+/// or is a `yield_to` target.
+pub fn set_priority(task: TaskId, new_priority: TaskId) -> Result<(), KernelError> {
+    priv_execute!({
+        critical_section(|cs_token| {
+            if is_pinned(task, new_priority) {
+                return Err(KernelError::AccessDenied);
+            }
+            TaskManager.borrow(cs_token).borrow_mut().set_priority(task as usize, new_priority as usize)?;
+            move_task(task, new_priority);
+            Ok(())
+        })?;
+        schedule();
+        Ok(())
+    })
+}
+
+/// Returns true if state outside the scheduler refers to the task `task` by its TaskId, and can't be moved
+/// to `new_priority`: a locked resource (which records its owner, and blocks the tasks up to its ceiling), an
+/// interrupt route, or a deadline. Locked resources with a ceiling below both TaskIds don't concern the task.
+fn is_pinned(task: TaskId, new_priority: TaskId) -> bool {
+    if task as usize >= MAX_TASKS || new_priority as usize >= MAX_TASKS {
+        return false;
+    }
+    #[cfg(feature = "timer")] {
+        if watchdog::is_armed(task) {
+            return true;
+        }
+    }
+    #[cfg(feature = "task_monitor")] {
+        if has_deadline(task) {
+            return true;
+        }
+    }
+    let lowest = core::cmp::min(task, new_priority) as i32;
+    lowest <= system_ceiling() || is_routed(task)
+}
+
+/// Deletes the task `task`, zeroing its stack, and then schedules the highest priority task. The slot can be
+/// reused by `create_task` afterwards. The currently running task can't be deleted
+/// (`KernelError::AccessDenied`); a task terminates itself with `task_exit`.
+/// The deleted task is no longer released by the primitives whose task masks include it, and it is removed
+/// from the masks of all messages in use, so a task later created in the slot does not inherit its subscriptions.
+/// `task_exit` keeps the subscriptions, since an exited task keeps its slot and is released again.
+pub fn delete_task(task: TaskId) -> Result<(), KernelError> {
+    priv_execute!({
+        let joiners = critical_section(|cs_token| {
+            let handler = &mut TaskManager.borrow(cs_token).borrow_mut();
+            handler.delete_task(task as usize)?;
+            Ok(handler.take_joiners(task as usize))
+        })?;
+        // The joiners sleep in `join`, which requires the timer.
+        #[cfg(feature = "timer")]
+        wake(joiners);
+        #[cfg(not(feature = "timer"))]
+        let _ = joiners;
+        #[cfg(feature = "timer")]
+        cancel_sleep(task);
+        #[cfg(feature = "task_monitor")]
+        clear_deadline(task);
+        #[cfg(feature = "timer")]
+        watchdog::watchdog_disarm(task).ok();
+        unregister_task(task);
+        schedule();
+        Ok(())
+    })
+}
+
+/// Restarts the task `task` from the top of its handler, e.g. to recover it after a fault, without
+/// recreating it. The task is released, and then the highest priority task is scheduled.
+/// The currently running task can't be restarted (`KernelError::AccessDenied`).
+pub fn restart_task(task: TaskId) -> Result<(), KernelError> {
+    priv_execute!({
+        critical_section(|cs_token| TaskManager.borrow(cs_token).borrow_mut().restart_task(task as usize))?;
+        #[cfg(feature = "timer")]
+        cancel_sleep(task);
+        release(1 << task);
+        schedule();
+        Ok(())
+    })
+}
+
+/// Suspends the task `task`; it is not scheduled, even if released, until `resume_task` is called.
+/// Suspension is independent of the blocking done by resource locking. A task may suspend itself.
+/// Returns `KernelError::AccessDenied` if called from an unprivileged context, or if the task is sleeping.
+pub fn suspend_task(task: TaskId) -> Result<(), KernelError> {
+    priv_execute!({
+        critical_section(|cs_token| TaskManager.borrow(cs_token).borrow_mut().suspend_task(task as usize))?;
+        schedule();
+        Ok(())
+    })
+}
+
+/// Resumes the suspended task `task`, and then schedules the highest priority task.
+/// Returns `KernelError::AccessDenied` if called from an unprivileged context.
+pub fn resume_task(task: TaskId) -> Result<(), KernelError> {
+    priv_execute!({
+        critical_section(|cs_token| TaskManager.borrow(cs_token).borrow_mut().resume_task(task as usize))?;
+        schedule();
+        Ok(())
+    })
+}
+
 /// This function is called from both privileged and unprivileged context.
 /// Hence if the function is called from privileged context, then `preempt()` is called.
 /// Else, the `svc_call()` is executed, this function creates the SVC exception.
 /// And the SVC handler calls schedule again. Thus, the permission level is raised to privileged via the exception.
 pub fn schedule() {
-    let is_preemptive = critical_section(|cs_token| TaskManager.borrow(cs_token).borrow_mut().is_preemptive);
-    if is_preemptive {
+    let (is_preemptive, is_curr_ready, switch_requested) = critical_section(|cs_token| {
+        let handler = TaskManager.borrow(cs_token).borrow();
+        (handler.is_preemptive, handler.is_curr_ready(), handler.switch_requested)
+    });
+    // A task which can no longer run (e.g. it has blocked or exited), or which has yielded, is switched out
+    // even without preemption.
+    if is_preemptive || !is_curr_ready || switch_requested {
         match is_privileged() {
             true => preempt(),
             false => svc_call(),
@@ -72,14 +308,76 @@ pub fn schedule() {
     } 
 }
 
+/// The currently running task voluntarily gives up the CPU. As the highest priority ready task is
+/// always dispatched, the task is excluded from scheduling until the next context switch, so that a
+/// lower priority task (possibly the idle task) runs until the next scheduling point.
+/// The context switch is requested even if preemption is disabled; hence it is mainly useful with
+/// cooperative scheduling.
+pub fn task_yield() {
+    critical_section(|cs_token| TaskManager.borrow(cs_token).borrow_mut().yield_curr_task());
+    schedule();
+}
+
+/// The currently running task hands the CPU to the lower priority task `task`, which runs even though it is
+/// not the highest priority ready task; the priority based selection resumes at the following scheduling point.
+/// E.g. a stage of a pipeline passes control to its downstream stage. Like `task_yield`, the context switch
+/// is requested even if preemption is disabled. Returns `KernelError::NotFound` if `task` is not ready, and
+/// `KernelError::AccessDenied` if it does not have a lower priority than the currently running task.
+pub fn yield_to(task: TaskId) -> Result<(), KernelError> {
+    critical_section(|cs_token| TaskManager.borrow(cs_token).borrow_mut().yield_to(task as usize))?;
+    schedule();
+    Ok(())
+}
+
 fn preempt() {
     set_pendsv();
 }
 
+/// Returns true if the highest priority task in the ready state is not the currently running task,
+/// i.e. a call to `schedule` would result in a context switch.
+pub fn is_reschedule_needed() -> bool {
+    critical_section(|cs_token| {
+        let handler = TaskManager.borrow(cs_token).borrow();
+        handler.get_next_tid() != handler.curr_tid
+    })
+}
+
+/// Returns the TaskId of the currently running task in the kernel. Every subsystem identifies the running
+/// task through it.
+pub fn current_task() -> TaskId {
+    critical_section(|cs_token| {
+        TaskManager.borrow(cs_token).borrow().curr_tid as TaskId
+    })
+}
+
 /// Returns the TaskId of the currently running task in the kernel.
+#[deprecated(note = "use `current_task`")]
 pub fn get_curr_tid() -> TaskId {
+    current_task()
+}
+
+/// Returns the boolean vector of the active tasks, i.e. the tasks which have been released and not yet exited.
+pub fn get_active_tasks() -> BooleanVector {
+    critical_section(|cs_token| TaskManager.borrow(cs_token).borrow().active_tasks)
+}
+
+/// Returns the boolean vector of the existing tasks, i.e. the tasks which have been created and not deleted.
+pub fn get_existing_tasks() -> BooleanVector {
+    critical_section(|cs_token| TaskManager.borrow(cs_token).borrow().existing_tasks())
+}
+
+/// Returns true if the `len` bytes at the address `start` lie within the stack of the currently running task.
+pub(crate) fn curr_stack_contains(start: usize, len: usize) -> bool {
     critical_section(|cs_token| {
-        TaskManager.borrow(cs_token).borrow().curr_tid as TaskId
+        let handler = TaskManager.borrow(cs_token).borrow();
+        match handler.tcb(handler.curr_tid) {
+            Some(tcb) => {
+                let base = tcb.meta.stack_base;
+                let end = base + tcb.meta.stack_size * core::mem::size_of::<u32>();
+                start >= base && start.checked_add(len).map_or(false, |stop| stop <= end)
+            },
+            None => false,
+        }
     })
 }
 
@@ -104,8 +402,10 @@ pub fn unblock_tasks(tasks_mask: BooleanVector) {
 }
 
 /// The `task_exit` function is called just after a task finishes execution. It marks the current running task as finished and then schedules the next high priority task.
+/// It never fails; if no other task is ready, the idle task is scheduled.
+/// The tasks waiting in `join` for the task are woken.
 pub fn task_exit() {
-    critical_section(|cs_token| {
+    let joiners = critical_section(|cs_token| {
         let handler = &mut TaskManager.borrow(cs_token).borrow_mut();
         let curr_tid = handler.curr_tid;
         #[cfg(feature = "system_logger")] {
@@ -113,10 +413,76 @@ pub fn task_exit() {
                 logging::report(LogEventType::TaskExit(curr_tid as TaskId));
             }
         }
-        handler.active_tasks &= !(1 << curr_tid as u32);
+        handler.task_exit();
+        handler.take_joiners(curr_tid)
     });
+    // The joiners sleep in `join`, which requires the timer.
+    #[cfg(feature = "timer")]
+    wake(joiners);
+    #[cfg(not(feature = "timer"))]
+    let _ = joiners;
     schedule()
 }
+
+/// Blocks the currently running task until the task `task` exits (with `task_exit` or `delete_task`), or
+/// `timeout` timer ticks elapse, in which case `KernelError::TimedOut` is returned. Returns immediately if
+/// `task` is not active. Returns `KernelError::AccessDenied` if `task` is the running task, and
+/// `KernelError::NotFound` if it does not exist. If the task is woken before the deadline while `task` is
+/// still alive, it blocks again.
+#[cfg(feature = "timer")]
+pub fn join(task: TaskId, timeout: u32) -> Result<(), KernelError> {
+    let curr_tid = current_task();
+    let deadline = get_time().wrapping_add(timeout);
+    loop {
+        let waiting = critical_section(|cs_token| {
+            {
+                let handler = &mut TaskManager.borrow(cs_token).borrow_mut();
+                if task == curr_tid || curr_tid == IDLE_TASK {
+                    return Err(KernelError::AccessDenied);
+                }
+                if task as usize >= MAX_TASKS || handler.task_control_blocks[task as usize].is_none() {
+                    return Err(KernelError::NotFound);
+                }
+                if handler.active_tasks & (1 << task) == 0 {
+                    return Ok(false);
+                }
+                if timeout == 0 || tick_reached(get_time(), deadline) {
+                    return Err(KernelError::TimedOut);
+                }
+                handler.joiners[task as usize] |= 1 << curr_tid;
+            }
+            // The context switch is carried out once the critical section ends.
+            sleep_until(deadline);
+            Ok(true)
+        })?;
+        if !waiting {
+            return Ok(());
+        }
+        let exited = critical_section(|cs_token| {
+            TaskManager.borrow(cs_token).borrow_mut().joiners[task as usize] &= !(1 << curr_tid);
+            // The joiners of a task are woken when it exits.
+            cancel_sleep(curr_tid) == WakeReason::Woken
+        });
+        if exited {
+            return Ok(());
+        }
+    }
+}
+
+/// The initial return address of every task. A task handler never returns by signature, but if it does
+/// (e.g. from a trap), the task exits cleanly instead of jumping to an arbitrary address.
+pub extern "C" fn task_return() -> ! {
+    loop {
+        task_exit();
+    }
+}
+
+/// Removes the currently running task from the ready state; the task resumes from where it left off
+/// once it is released again. The caller must call `schedule` afterwards to dispatch the next task.
+pub fn deactivate_curr_task() {
+    critical_section(|cs_token| TaskManager.borrow(cs_token).borrow_mut().task_exit())
+}
+
 /// The Kernel releases the tasks in the `task_mask`, these tasks transition from the waiting to the ready state.
 pub fn release(tasks_mask: BooleanVector) {
     #[cfg(feature = "system_logger")] {
@@ -127,15 +493,193 @@ pub fn release(tasks_mask: BooleanVector) {
     critical_section(|cs_token| {TaskManager.borrow(cs_token).borrow_mut().release(tasks_mask)});
 }
 
-/// Enable preemptive scheduling
-pub fn enable_preemption() {
+/// Like `release`, but validates `tasks_mask` and schedules the released tasks. Returns the TaskId of the task
+/// which runs next, i.e. the highest priority ready task after the release (the running task, if none of the
+/// released tasks preempts it). Returns `KernelError::InvalidArgument` if `tasks_mask` refers to a task at or
+/// above `MAX_TASKS`, in which case no task is released.
+pub fn release_tasks(tasks_mask: BooleanVector) -> Result<TaskId, KernelError> {
+    if MAX_TASKS < VECTOR_BITS && tasks_mask >> MAX_TASKS != 0 {
+        return Err(KernelError::InvalidArgument);
+    }
+    release(tasks_mask);
+    let next_tid = critical_section(|cs_token| TaskManager.borrow(cs_token).borrow().get_next_tid());
+    schedule();
+    Ok(next_tid as TaskId)
+}
+
+/// A handle which binds an interrupt handler to the task it wakes up, created with `bind_interrupt`.
+#[derive(Clone, Copy)]
+pub struct IrqBinding {
+    tasks_mask: BooleanVector,
+}
+
+/// Binds the task `task` to an interrupt handler, which wakes it up with `notify_from_isr`. It can be
+/// used in `static` initializers; a TaskId at or above `MAX_TASKS` then fails the compilation.
+pub const fn bind_interrupt(task: TaskId) -> IrqBinding {
+    [()][(task as usize >= MAX_TASKS) as usize];
+    IrqBinding { tasks_mask: 1 << task }
+}
+
+/// Releases the task bound by `binding` and schedules it, if it has a higher priority than the interrupted
+/// task. It is meant to be called from within an interrupt handler.
+pub fn notify_from_isr(binding: IrqBinding) {
+    release(binding.tasks_mask);
+    schedule();
+}
+
+/// The currently running task waits for the `PiResource` held by the task `holder`, which inherits its
+/// priority meanwhile. The caller must call `schedule` afterwards to dispatch the holder.
+pub fn pi_wait(holder: TaskId) {
+    critical_section(|cs_token| {
+        let handler = &mut TaskManager.borrow(cs_token).borrow_mut();
+        let curr_tid = handler.curr_tid;
+        handler.pi_wait(curr_tid, holder as usize);
+    })
+}
+
+/// The tasks in `tasks_mask` stop waiting for a `PiResource`, the priority of its holder is restored.
+pub fn pi_wake(tasks_mask: BooleanVector) {
+    critical_section(|cs_token| TaskManager.borrow(cs_token).borrow_mut().pi_wake(tasks_mask))
+}
+
+/// Registers a hook which the idle task calls on each iteration before putting the CPU to sleep, e.g. to
+/// kick a watchdog or enter a deeper sleep mode. It runs in the context of the idle task, the lowest priority.
+pub fn set_idle_hook(hook: fn()) {
+    critical_section(|cs_token| {
+        TaskManager.borrow(cs_token).borrow_mut().idle_hook = Some(hook);
+    })
+}
+
+/// Calls the idle hook, if one is registered.
+pub fn run_idle_hook() {
+    let idle_hook = critical_section(|cs_token| TaskManager.borrow(cs_token).borrow().idle_hook);
+    if let Some(idle_hook) = idle_hook {
+        idle_hook();
+    }
+}
+
+/// Registers hooks which bracket system idle periods. `on_enter` is called when the scheduler
+/// switches to the idle task, and `on_exit` when it switches away from it to a real task.
+/// Both hooks are called from the PendSV handler with interrupts disabled, hence they must be short.
+pub fn set_system_idle_hooks(on_enter: fn(), on_exit: fn()) {
+    critical_section(|cs_token| {
+        let handler = &mut TaskManager.borrow(cs_token).borrow_mut();
+        handler.idle_enter_hook = Some(on_enter);
+        handler.idle_exit_hook = Some(on_exit);
+    })
+}
+
+/// Fills the unused stack of the task `task` with a paint value, up to `WATERMARK_FILL_LIMIT` words above the
+/// guard word, so that `set_low_stack_hook` can measure how much of it remains unused. Stacks are not painted
+/// on task creation, hence tasks which are not measured cost nothing at boot. Returns `KernelError::NotFound`
+/// if `task` does not exist, and `KernelError::AccessDenied` if it is the running task.
+#[cfg(feature="stack_guard")]
+pub fn paint_stack(task: TaskId) -> Result<(), KernelError> {
+    critical_section(|cs_token| TaskManager.borrow(cs_token).borrow_mut().paint_stack(task as usize))
+}
+
+/// Registers a hook which is called on a context switch if the outgoing task has less than `threshold`
+/// words of its stack unused. The hook is called with the TaskId and the number of unused stack words.
+/// Like the idle hooks, it is called from the PendSV handler with interrupts disabled. Only tasks whose stack
+/// has been painted with `paint_stack` are checked; `threshold` should not exceed `WATERMARK_FILL_LIMIT`.
+#[cfg(feature="stack_guard")]
+pub fn set_low_stack_hook(threshold: usize, hook: fn(TaskId, usize)) {
+    critical_section(|cs_token| {
+        let handler = &mut TaskManager.borrow(cs_token).borrow_mut();
+        handler.low_stack_threshold = threshold;
+        handler.low_stack_hook = Some(hook);
+    })
+}
+
+/// Returns the number of CPU cycles spent executing the task `task`, as of the last context switch.
+/// The count of the idle task (`IDLE_TASK`) against the total gives an estimate of the idle time.
+#[cfg(feature="cpu_stats")]
+pub fn cpu_cycles(task: TaskId) -> u64 {
+    critical_section(|cs_token| {
+        TaskManager.borrow(cs_token).borrow().cpu_cycles.get(task as usize).copied().unwrap_or(0)
+    })
+}
+
+/// Resets the CPU cycle counts of all tasks.
+#[cfg(feature="cpu_stats")]
+pub fn reset_cpu_stats() {
+    critical_section(|cs_token| {
+        let handler = &mut TaskManager.borrow(cs_token).borrow_mut();
+        handler.cpu_cycles = [0; crate::config::MAX_TASKS + 1];
+    })
+}
+
+/// Enables MPU stack guards. On each context switch, an MPU region which faults on any access is placed
+/// over the lowest 32 bytes of the incoming task's stack; hence a stack overflow traps immediately, the
+/// task is halted and `KernelError::StackOverflow` is raised, instead of the overflow corrupting the memory below.
+/// It is to be called after `init`.
+///
+/// The Kernel uses only MPU region 0 of the 8 regions, the rest are left to the application; regions with
+/// a higher number take precedence, hence they must not overlap the task stacks. A region must be aligned to
+/// its size, so the guard starts at the first 32 byte boundary of the stack; align the stacks to 32 bytes so
+/// that no stack space is lost.
+#[cfg(feature="mpu")]
+pub fn enable_stack_guards() {
+    enable_mpu();
+}
+
+/// The currently running task drops its privileges; from then on it runs unprivileged and must use
+/// the `syscall` routines for privileged operations. Other tasks are unaffected, as the privilege level
+/// is switched along with the task context.
+pub fn drop_privileges() {
     critical_section(|cs_token| {
         let handler = &mut TaskManager.borrow(cs_token).borrow_mut();
+        let curr_tid = handler.curr_tid;
+        if let Some(tcb) = handler.tcb_mut(curr_tid) {
+            tcb.meta.unprivileged = true;
+        }
+        set_thread_privileged(false);
+    })
+}
+
+/// Returns the scheduling state of the task `task`.
+pub fn task_state(task: TaskId) -> TaskState {
+    critical_section(|cs_token| TaskManager.borrow(cs_token).borrow().task_state(task as usize))
+}
+
+/// Validates the consistency of the Kernel's task state, and returns the violated invariant if any.
+/// It is meant to be called from a task, a periodic audit task for instance; the running task must be active.
+pub fn check_invariants() -> Result<(), InvariantViolation> {
+    let ceiling = system_ceiling();
+    let ceiling_mask = if ceiling < 0 { 0 } else { get_pi_mask(ceiling as TaskId) };
+    critical_section(|cs_token| TaskManager.borrow(cs_token).borrow().check_invariants(ceiling_mask))
+}
+
+/// Enable preemptive scheduling. Calls nest with `disable_preemption`; preemption is re-enabled only
+/// once every `disable_preemption` has been matched, at which point a reschedule deferred meanwhile is carried out.
+pub fn enable_preemption() {
+    let enabled = critical_section(|cs_token| {
+        let handler = &mut TaskManager.borrow(cs_token).borrow_mut();
+        if handler.preempt_disable_count == 0 {
+            return false;
+        }
         handler.preempt_disable_count -= 1;
         if handler.preempt_disable_count == 0 {
-            handler.is_preemptive = true;
+            handler.is_preemptive = !cfg!(feature="cooperative");
+            return true;
         }
-    })
+        false
+    });
+    if enabled && is_reschedule_needed() {
+        schedule();
+    }
+}
+
+/// Locks the scheduler, so that the running task is not preempted by other tasks until the matching
+/// `scheduler_unlock`; interrupts are still serviced. Locks nest.
+pub fn scheduler_lock() {
+    disable_preemption();
+}
+
+/// Releases a `scheduler_lock`. When the outermost lock is released, the highest priority ready task
+/// is scheduled if it is not the running task.
+pub fn scheduler_unlock() {
+    enable_preemption();
 }
 
 /// Disable preemptive scheduling
@@ -146,3 +690,605 @@ pub fn disable_preemption() {
         handler.is_preemptive = false;
     })
 }
+
+// The tests rely on preemption, which the `cooperative` feature disables.
+#[cfg(all(test, not(feature = "cooperative")))]
+mod tests {
+    use super::*;
+    use crate::utils::host::{boot, create, kernel_test, run_isr, take_trace, trace};
+    #[cfg(feature = "timer")]
+    use crate::utils::host::tick;
+
+    fn exiting_task2() -> ! {
+        loop {
+            trace(2);
+            task_exit();
+        }
+    }
+
+    #[test]
+    fn released_task_preempts_lower_priority() {
+        fn task1() -> ! {
+            loop {
+                trace(1);
+                assert_eq!(release_tasks(1 << 2).unwrap(), 2);
+                trace(11);
+                task_exit();
+            }
+        }
+        let _kernel = kernel_test();
+        boot(&[(1, task1), (2, exiting_task2)]);
+        assert_eq!(current_task(), IDLE_TASK);
+        assert_eq!(release_tasks(1 << 1).unwrap(), 1);
+        assert_eq!(take_trace(), [1, 2, 11]);
+        assert_eq!(current_task(), IDLE_TASK);
+        assert_eq!(task_state(1), TaskState::Waiting);
+        assert_eq!(task_state(2), TaskState::Waiting);
+    }
+
+    #[test]
+    fn blocked_task_runs_once_unblocked() {
+        fn task1() -> ! {
+            loop {
+                trace(1);
+                block_tasks(1 << 2);
+                release_tasks(1 << 2).unwrap();
+                assert_eq!(task_state(2), TaskState::Blocked);
+                trace(11);
+                unblock_tasks(1 << 2);
+                schedule();
+                trace(12);
+                task_exit();
+            }
+        }
+        let _kernel = kernel_test();
+        boot(&[(1, task1), (2, exiting_task2)]);
+        release_tasks(1 << 1).unwrap();
+        assert_eq!(take_trace(), [1, 11, 2, 12]);
+    }
+
+    #[test]
+    fn preemption_is_deferred_until_enabled() {
+        fn task1() -> ! {
+            loop {
+                trace(1);
+                disable_preemption();
+                release_tasks(1 << 2).unwrap();
+                trace(11);
+                enable_preemption();
+                trace(12);
+                task_exit();
+            }
+        }
+        let _kernel = kernel_test();
+        boot(&[(1, task1), (2, exiting_task2)]);
+        release_tasks(1 << 1).unwrap();
+        assert_eq!(take_trace(), [1, 11, 2, 12]);
+    }
+
+    #[test]
+    fn interrupt_preempts_once_it_returns() {
+        fn task1() -> ! {
+            loop {
+                trace(1);
+                run_isr(|| {
+                    notify_from_isr(bind_interrupt(2));
+                    trace(10);
+                });
+                trace(11);
+                task_exit();
+            }
+        }
+        let _kernel = kernel_test();
+        boot(&[(1, task1), (2, exiting_task2)]);
+        release_tasks(1 << 1).unwrap();
+        assert_eq!(take_trace(), [1, 10, 2, 11]);
+    }
+
+    #[test]
+    fn task_at_priority_0_runs_above_idle() {
+        fn task0() -> ! {
+            loop {
+                trace(0);
+                release_tasks(1 << 2).unwrap();
+                trace(10);
+                task_exit();
+            }
+        }
+        let _kernel = kernel_test();
+        boot(&[(0, task0), (2, exiting_task2)]);
+        assert_eq!(task_state(IDLE_TASK), TaskState::Running);
+        release_tasks(1 << 0).unwrap();
+        assert_eq!(take_trace(), [0, 2, 10]);
+        // Nothing is ready, hence the idle task runs again.
+        assert_eq!(current_task(), IDLE_TASK);
+        assert_eq!(task_state(0), TaskState::Waiting);
+        assert!(check_invariants().is_ok());
+    }
+
+    #[test]
+    fn task_moves_past_a_lower_locked_resource() {
+        static RES: crate::system::resource::Resource<u32> = crate::system::resource::Resource::new(0, 1 << 1);
+        fn task1() -> ! {
+            loop {
+                trace(1);
+                RES.acquire(|_| {
+                    assert!(matches!(set_priority(1, 5), Err(KernelError::AccessDenied)));
+                    set_priority(3, 4).unwrap();
+                }).unwrap();
+                trace(11);
+                task_exit();
+            }
+        }
+        fn task3() -> ! {
+            loop {
+                trace(30);
+                task_exit();
+            }
+        }
+        let _kernel = kernel_test();
+        boot(&[(1, task1), (3, task3)]);
+        suspend_task(3).unwrap();
+        release_tasks(1 << 1).unwrap();
+        assert_eq!(take_trace(), [1, 11]);
+        assert_eq!(task_state(3), TaskState::Nonexistent);
+        assert_eq!(task_state(4), TaskState::Suspended);
+        assert!(matches!(set_priority(6, 7), Err(KernelError::DoesNotExist)));
+        resume_task(4).unwrap();
+        release_tasks(1 << 4).unwrap();
+        assert_eq!(take_trace(), [30]);
+        assert_eq!(task_state(4), TaskState::Waiting);
+    }
+
+    #[test]
+    fn resumed_task_preempts() {
+        fn task1() -> ! {
+            loop {
+                trace(1);
+                resume_task(3).unwrap();
+                trace(11);
+                task_exit();
+            }
+        }
+        fn task2() -> ! {
+            loop {
+                drop_privileges();
+                assert!(matches!(suspend_task(1), Err(KernelError::AccessDenied)));
+                assert!(matches!(resume_task(3), Err(KernelError::AccessDenied)));
+                trace(2);
+                task_exit();
+            }
+        }
+        fn task3() -> ! {
+            loop {
+                trace(3);
+                task_exit();
+            }
+        }
+        let _kernel = kernel_test();
+        boot(&[(1, task1), (2, task2), (3, task3)]);
+        suspend_task(3).unwrap();
+        release_tasks(1 << 1 | 1 << 3).unwrap();
+        assert_eq!(take_trace(), [1, 3, 11]);
+        suspend_task(3).unwrap();
+        release_tasks(1 << 2 | 1 << 3).unwrap();
+        assert_eq!(take_trace(), [2]);
+        assert_eq!(task_state(3), TaskState::Suspended);
+    }
+
+    #[test]
+    fn yielded_task_is_skipped_until_the_next_switch() {
+        fn task1() -> ! {
+            loop {
+                trace(1);
+                // The switch to this task ended the yield, hence the task 2 preempts it at once.
+                schedule();
+                trace(11);
+                task_exit();
+            }
+        }
+        fn task2() -> ! {
+            loop {
+                release_tasks(1 << 1).unwrap();
+                trace(2);
+                task_yield();
+                trace(21);
+                task_exit();
+            }
+        }
+        let _kernel = kernel_test();
+        boot(&[(1, task1), (2, task2)]);
+        release_tasks(1 << 2).unwrap();
+        assert_eq!(take_trace(), [2, 1, 21, 11]);
+    }
+
+    #[test]
+    fn svc_schedule_rechecks_preemption() {
+        fn task1() -> ! {
+            loop {
+                drop_privileges();
+                disable_preemption();
+                release_tasks(1 << 2).unwrap();
+                trace(1);
+                // `Syscall::Schedule` checks the scheduling state again, hence it does not switch to the task 2
+                // while preemption is disabled,
+                svc_call();
+                trace(11);
+                // whereas a yield is carried out.
+                task_yield();
+                trace(12);
+                enable_preemption();
+                task_exit();
+            }
+        }
+        let _kernel = kernel_test();
+        boot(&[(1, task1), (2, exiting_task2)]);
+        release_tasks(1 << 1).unwrap();
+        assert_eq!(take_trace(), [1, 11, 2, 12]);
+    }
+
+    #[test]
+    fn deleted_task_frees_its_slot() {
+        fn task1() -> ! {
+            loop {
+                assert!(matches!(delete_task(1), Err(KernelError::AccessDenied)));
+                trace(1);
+                task_exit();
+            }
+        }
+        let _kernel = kernel_test();
+        boot(&[(1, task1), (2, exiting_task2)]);
+        release_tasks(1 << 1 | 1 << 2).unwrap();
+        assert_eq!(take_trace(), [2, 1]);
+        let (stack_base, stack_size) = critical_section(|cs_token| {
+            let meta = TaskManager.borrow(cs_token).borrow().tcb(2).unwrap().meta;
+            (meta.stack_base, meta.stack_size)
+        });
+        delete_task(2).unwrap();
+        assert_eq!(task_state(2), TaskState::Nonexistent);
+        assert_eq!(get_existing_tasks(), 1 << 1);
+        // The stack is leaked by `create`, hence it outlives the task.
+        let stack = unsafe { core::slice::from_raw_parts(stack_base as *const u32, stack_size) };
+        assert!(stack.iter().all(|&word| word == 0));
+        assert!(matches!(delete_task(2), Err(KernelError::NotFound)));
+        create(2, exiting_task2);
+        assert_eq!(task_state(2), TaskState::Waiting);
+        release_tasks(1 << 2).unwrap();
+        assert_eq!(take_trace(), [2]);
+    }
+
+    #[test]
+    // The tickless idle loop reprograms the SysTick, which the host lacks.
+    #[cfg(not(feature = "tickless"))]
+    fn idle_hook_runs_on_each_idle_iteration() {
+        use core::sync::atomic::{AtomicU32, Ordering};
+        static IDLE_RUNS: AtomicU32 = AtomicU32::new(0);
+        fn idle_hook() {
+            // Breaks out of the idle loop once it has gone round three times.
+            if IDLE_RUNS.fetch_add(1, Ordering::SeqCst) == 2 {
+                panic!("the idle loop went round");
+            }
+        }
+        fn task1() -> ! {
+            loop {
+                trace(1);
+                task_exit();
+            }
+        }
+        let _kernel = kernel_test();
+        IDLE_RUNS.store(0, Ordering::SeqCst);
+        boot(&[(1, task1)]);
+        set_idle_hook(idle_hook);
+        release_tasks(1 << 1).unwrap();
+        assert_eq!(take_trace(), [1]);
+        // Nothing is ready, hence the idle task runs; the test thread plays it, so it runs its loop by hand.
+        assert_eq!(current_task(), IDLE_TASK);
+        let idle_loop = critical_section(|cs_token| {
+            TaskManager.borrow(cs_token).borrow().idle_tcb.as_ref().unwrap().meta.handler.unwrap()
+        });
+        assert!(std::panic::catch_unwind(|| idle_loop()).is_err());
+        assert_eq!(IDLE_RUNS.load(Ordering::SeqCst), 3);
+    }
+
+    #[test]
+    fn task_state_follows_the_scheduling() {
+        fn task1() -> ! {
+            loop {
+                assert_eq!(task_state(1), TaskState::Running);
+                disable_preemption();
+                release_tasks(1 << 2).unwrap();
+                assert_eq!(task_state(2), TaskState::Ready);
+                block_tasks(1 << 2);
+                assert_eq!(task_state(2), TaskState::Blocked);
+                unblock_tasks(1 << 2);
+                assert_eq!(task_state(2), TaskState::Ready);
+                trace(1);
+                enable_preemption();
+                task_exit();
+            }
+        }
+        let _kernel = kernel_test();
+        boot(&[(1, task1), (2, exiting_task2), (3, exiting_task2)]);
+        assert_eq!(task_state(1), TaskState::Waiting);
+        suspend_task(3).unwrap();
+        assert_eq!(task_state(3), TaskState::Suspended);
+        assert_eq!(task_state(4), TaskState::Nonexistent);
+        release_tasks(1 << 1).unwrap();
+        assert_eq!(take_trace(), [1, 2]);
+        assert_eq!(task_state(2), TaskState::Waiting);
+    }
+
+    #[test]
+    fn highest_slot_is_scheduled_and_covered_by_the_ceiling() {
+        // With `tasks_64`, the task is in the upper half of the vector.
+        const TOP: TaskId = crate::config::MAX_TASKS as TaskId - 1;
+        static RES: crate::system::resource::Resource<u32> = crate::system::resource::Resource::new(0, 1 << 1 | 1 << TOP);
+        fn task1() -> ! {
+            loop {
+                trace(1);
+                RES.acquire(|_| {
+                    release_tasks(1 << TOP).unwrap();
+                    trace(10);
+                }).unwrap();
+                trace(11);
+                task_exit();
+            }
+        }
+        fn top_task() -> ! {
+            loop {
+                trace(TOP);
+                task_exit();
+            }
+        }
+        let _kernel = kernel_test();
+        boot(&[(1, task1), (TOP, top_task)]);
+        assert_eq!(get_existing_tasks(), 1 << 1 | 1 << TOP);
+        release_tasks(1 << 1).unwrap();
+        assert_eq!(take_trace(), [1, 10, TOP, 11]);
+    }
+
+    #[test]
+    #[cfg(feature = "timer")]
+    fn joiner_wakes_when_the_task_exits_or_times_out() {
+        fn task1() -> ! {
+            loop {
+                trace(1);
+                task_exit();
+            }
+        }
+        fn task2() -> ! {
+            loop {
+                assert!(matches!(join(2, 10), Err(KernelError::AccessDenied)));
+                assert!(matches!(join(5, 10), Err(KernelError::NotFound)));
+                release_tasks(1 << 1 | 1 << 3).unwrap();
+                trace(2);
+                join(1, 10).unwrap();
+                trace(21);
+                // The task 1 has exited, hence there is nothing to wait for.
+                join(1, 0).unwrap();
+                // The task 3 is released but suspended, so it never exits.
+                assert!(matches!(join(3, 2), Err(KernelError::TimedOut)));
+                trace(22);
+                task_exit();
+            }
+        }
+        fn task3() -> ! {
+            loop {
+                trace(3);
+                task_exit();
+            }
+        }
+        let _kernel = kernel_test();
+        boot(&[(1, task1), (2, task2), (3, task3)]);
+        suspend_task(3).unwrap();
+        release_tasks(1 << 2).unwrap();
+        assert_eq!(take_trace(), [2, 1, 21]);
+        tick(1);
+        assert_eq!(take_trace(), []);
+        tick(1);
+        assert_eq!(take_trace(), [22]);
+    }
+
+    #[test]
+    fn spawn_takes_the_lowest_free_slot_until_full() {
+        fn spawn() -> Result<TaskId, KernelError> {
+            let stack = Box::leak(vec![0u32; 64].into_boxed_slice());
+            #[cfg(feature = "task_monitor")]
+            return spawn_task(100, stack, exiting_task2);
+            #[cfg(not(feature = "task_monitor"))]
+            return spawn_task(stack, exiting_task2);
+        }
+        let _kernel = kernel_test();
+        boot(&[(1, exiting_task2)]);
+        assert_eq!(spawn().unwrap(), 0);
+        for tid in 2..MAX_TASKS {
+            assert_eq!(spawn().unwrap(), tid as TaskId);
+        }
+        assert!(matches!(spawn(), Err(KernelError::LimitExceeded)));
+        delete_task(5).unwrap();
+        assert_eq!(spawn().unwrap(), 5);
+    }
+
+    #[test]
+    fn second_task_on_a_slot_is_rejected() {
+        fn task1() -> ! {
+            loop {
+                trace(1);
+                task_exit();
+            }
+        }
+        let _kernel = kernel_test();
+        boot(&[(1, task1)]);
+        let first = critical_section(|cs_token| TaskManager.borrow(cs_token).borrow().tcb(1).unwrap().meta);
+        let stack = Box::leak(vec![0u32; 64].into_boxed_slice());
+        #[cfg(feature = "task_monitor")]
+        let result = create_task(1, 100, stack, exiting_task2);
+        #[cfg(not(feature = "task_monitor"))]
+        let result = create_task(1, stack, exiting_task2);
+        assert!(matches!(result, Err(KernelError::Exists)));
+        // The second stack is left untouched, and the first task still runs its own handler.
+        assert!(stack.iter().all(|&word| word == 0));
+        let meta = critical_section(|cs_token| TaskManager.borrow(cs_token).borrow().tcb(1).unwrap().meta);
+        assert_eq!(meta.stack_base, first.stack_base);
+        assert_eq!(meta.handler.map(|handler| handler as usize), Some(task1 as fn() -> ! as usize));
+        release_tasks(1 << 1).unwrap();
+        assert_eq!(take_trace(), [1]);
+    }
+
+    #[test]
+    fn notify_from_isr_releases_the_bound_task() {
+        static BOUND_HIGH: IrqBinding = bind_interrupt(2);
+        static BOUND_LOW: IrqBinding = bind_interrupt(0);
+        fn task0() -> ! {
+            loop {
+                trace(0);
+                task_exit();
+            }
+        }
+        fn task1() -> ! {
+            loop {
+                trace(1);
+                run_isr(|| {
+                    notify_from_isr(BOUND_LOW);
+                    notify_from_isr(BOUND_HIGH);
+                    assert_eq!(get_active_tasks(), 1 << 0 | 1 << 1 | 1 << 2);
+                    // The switch waits until the handler returns.
+                    assert_eq!(current_task(), 1);
+                    trace(10);
+                });
+                trace(11);
+                task_exit();
+            }
+        }
+        let _kernel = kernel_test();
+        boot(&[(0, task0), (1, task1), (2, exiting_task2)]);
+        release_tasks(1 << 1).unwrap();
+        // The task 2 preempts the interrupted task, the task 0 waits for it.
+        assert_eq!(take_trace(), [1, 10, 2, 11, 0]);
+    }
+
+    #[test]
+    fn yield_to_runs_the_named_lower_task() {
+        fn task1() -> ! {
+            loop {
+                trace(1);
+                // The priority based selection resumes, hence the task 3 preempts this task again.
+                schedule();
+                trace(11);
+                task_exit();
+            }
+        }
+        fn task3() -> ! {
+            loop {
+                release_tasks(1 << 1 | 1 << 2).unwrap();
+                trace(3);
+                assert!(matches!(yield_to(3), Err(KernelError::AccessDenied)));
+                assert!(matches!(yield_to(0), Err(KernelError::NotFound)));
+                block_tasks(1 << 2);
+                assert!(matches!(yield_to(2), Err(KernelError::NotFound)));
+                unblock_tasks(1 << 2);
+                yield_to(1).unwrap();
+                trace(31);
+                task_exit();
+            }
+        }
+        let _kernel = kernel_test();
+        boot(&[(1, task1), (2, exiting_task2), (3, task3)]);
+        release_tasks(1 << 3).unwrap();
+        assert_eq!(take_trace(), [3, 1, 31, 2, 11]);
+    }
+
+    #[test]
+    fn resources_and_messages_agree_on_the_running_task() {
+        static RES: crate::system::resource::Resource<u32> = crate::system::resource::Resource::new(0, 1 << 2);
+        static MSG: crate::system::message::Message<u32> = crate::system::message::Message::new(0, 1 << 2, 0);
+        fn task2() -> ! {
+            loop {
+                RES.acquire(|_| {
+                    assert_eq!(current_task(), 2);
+                    #[allow(deprecated)]
+                    let curr_tid = get_curr_tid();
+                    assert_eq!(curr_tid, 2);
+                    // An interrupt handler runs on behalf of the interrupted task.
+                    assert_eq!(run_isr(current_task), 2);
+                    // The receive flag is that of the sender, since it receives its own broadcast.
+                    MSG.broadcast(Some(7)).unwrap();
+                    trace(MSG.receive().unwrap());
+                }).unwrap();
+                // The resource has been unlocked by its owner.
+                assert_eq!(system_ceiling(), -1);
+                trace(2);
+                task_exit();
+            }
+        }
+        let _kernel = kernel_test();
+        boot(&[(2, task2)]);
+        release_tasks(1 << 2).unwrap();
+        assert_eq!(take_trace(), [7, 2]);
+    }
+
+    #[test]
+    fn release_tasks_returns_the_task_which_runs_next() {
+        fn task0() -> ! {
+            loop {
+                trace(0);
+                task_exit();
+            }
+        }
+        fn task1() -> ! {
+            loop {
+                trace(1);
+                // A lower priority task does not preempt the running task.
+                assert_eq!(release_tasks(1 << 0).unwrap(), 1);
+                assert_eq!(release_tasks(1 << 2 | 1 << 3).unwrap(), 3);
+                trace(11);
+                task_exit();
+            }
+        }
+        fn task3() -> ! {
+            loop {
+                trace(3);
+                task_exit();
+            }
+        }
+        let _kernel = kernel_test();
+        boot(&[(0, task0), (1, task1), (2, exiting_task2), (3, task3)]);
+        assert_eq!(release_tasks(0).unwrap(), IDLE_TASK);
+        assert_eq!(release_tasks(1 << 1).unwrap(), 1);
+        assert_eq!(take_trace(), [1, 3, 2, 11, 0]);
+        // A task beyond `MAX_TASKS` only fits in the vector if it is wider than `MAX_TASKS` bits.
+        if let Some(beyond) = (1 as BooleanVector).checked_shl(MAX_TASKS as u32) {
+            assert!(matches!(release_tasks(beyond | 1 << 1), Err(KernelError::InvalidArgument)));
+            assert_eq!(get_active_tasks(), 0);
+            assert_eq!(take_trace(), []);
+        }
+    }
+
+    #[test]
+    fn nested_scheduler_locks_defer_the_switch_to_the_outermost_unlock() {
+        fn task1() -> ! {
+            loop {
+                scheduler_lock();
+                scheduler_lock();
+                release_tasks(1 << 2).unwrap();
+                trace(1);
+                scheduler_unlock();
+                trace(11);
+                scheduler_unlock();
+                trace(12);
+                // An unmatched unlock does not re-enable preemption early for a later lock.
+                scheduler_unlock();
+                scheduler_lock();
+                release_tasks(1 << 2).unwrap();
+                trace(13);
+                scheduler_unlock();
+                trace(14);
+                task_exit();
+            }
+        }
+        let _kernel = kernel_test();
+        boot(&[(1, task1), (2, exiting_task2)]);
+        release_tasks(1 << 1).unwrap();
+        assert_eq!(take_trace(), [1, 11, 2, 12, 13, 2, 14]);
+    }
+}
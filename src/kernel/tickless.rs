@@ -0,0 +1,90 @@
+//! # Tickless Idle Module
+//!
+//! When the idle task is about to put the CPU to sleep, the SysTick period is stretched up to the nearest
+//! timing deadline (sleeping tasks, software timers, events and task deadlines), so that the CPU isn't woken
+//! on every tick. The elapsed ticks are accounted for when the stretched period ends, or on an earlier
+//! wake up by another interrupt; hence the Kernel time stays accurate.
+
+use core::cell::RefCell;
+
+use cortex_m::peripheral::{SCB, SYST};
+
+use crate::kernel::timer::{get_time, ticks_to_next_wake};
+use crate::kernel::soft_timer::ticks_to_next_expiry;
+use crate::kernel::watchdog;
+use crate::system::tickless::TicklessState;
+use crate::utils::arch::{Mutex, critical_section, wait_for_interrupt};
+
+#[cfg(any(feature = "events_32", feature = "events_16", feature = "events_64"))]
+use crate::kernel::events::ticks_to_next_dispatch;
+
+#[cfg(feature="task_monitor")]
+use crate::kernel::task_monitor::ticks_to_next_deadline;
+
+static Tickless: Mutex<RefCell<TicklessState>> = Mutex::new(RefCell::new(TicklessState::new()));
+
+/// Sets the number of CPU cycles in a tick, i.e. the SysTick period when not stretched.
+pub fn set_tick_cycles(tick_cycles: u32) {
+    critical_section(|cs_token| {
+        Tickless.borrow(cs_token).borrow_mut().tick_cycles = tick_cycles;
+    })
+}
+
+/// Returns the number of ticks until the nearest timing deadline.
+fn ticks_to_next_deadline_any(curr_time: u32) -> Option<u32> {
+    let deadlines = [
+        ticks_to_next_wake(curr_time),
+        ticks_to_next_expiry(),
+        watchdog::ticks_to_next_deadline(),
+        #[cfg(any(feature = "events_32", feature = "events_16", feature = "events_64"))]
+        ticks_to_next_dispatch(curr_time),
+        #[cfg(feature="task_monitor")]
+        ticks_to_next_deadline(curr_time),
+    ];
+    deadlines.iter().filter_map(|deadline| *deadline).min()
+}
+
+/// Called by the idle task in place of `wait_for_interrupt`. Interrupts are disabled while the SysTick
+/// period is adjusted; a pending interrupt still wakes the CPU and is serviced once the function returns.
+pub fn idle_wait() {
+    critical_section(|cs_token| {
+        let state = &mut Tickless.borrow(cs_token).borrow_mut();
+        let syst = unsafe { &*SYST::ptr() };
+        if !state.stretched && !SCB::is_pendst_pending() {
+            let ticks = ticks_to_next_deadline_any(get_time())
+                .unwrap_or(u32::max_value())
+                .min(state.max_ticks());
+            if ticks > 1 {
+                let elapsed_cycles = syst.rvr.read() - syst.cvr.read();
+                let reload = state.stretch(ticks, elapsed_cycles);
+                unsafe {
+                    syst.rvr.write(reload);
+                    syst.cvr.write(0);
+                }
+            }
+        }
+        wait_for_interrupt();
+        // Woken by an interrupt other than SysTick, end the stretched period on the next tick boundary.
+        if state.stretched && !SCB::is_pendst_pending() {
+            let elapsed_cycles = syst.rvr.read() - syst.cvr.read();
+            let reload = state.shorten(elapsed_cycles);
+            unsafe {
+                syst.rvr.write(reload);
+                syst.cvr.write(0);
+            }
+        }
+    })
+}
+
+/// Ends the current SysTick period and returns the number of ticks it spanned.
+/// It is called from the SysTick handler, which restores the regular period if it was stretched.
+pub fn end_period() -> u32 {
+    critical_section(|cs_token| {
+        let state = &mut Tickless.borrow(cs_token).borrow_mut();
+        if state.stretched {
+            let syst = unsafe { &*SYST::ptr() };
+            unsafe { syst.rvr.write(state.tick_cycles - 1) };
+        }
+        state.end_period()
+    })
+}
@@ -1,20 +1,38 @@
 //! Manages the kernel timer.
 use core::cell::RefCell;
 
+use cortex_m::peripheral::SYST;
+
 use crate::KernelError;
 use crate::priv_execute;
 use crate::system::scheduler::*;
 use crate::utils::arch::{svc_call,Mutex,critical_section,SystClkSource,Peripherals};
 use crate::utils::arch::is_privileged;
+use crate::config::MAX_TASKS;
+use crate::kernel::tasks::{TaskManager, schedule, task_yield};
+use crate::system::sleep_table::{SleepTable, WakeReason, tick_reached};
 
 static SystemTimer: Mutex<RefCell<u32>> = Mutex::new(RefCell::new(0));
+
+/// The number of ticks per second, set by `configure_tick`.
+static TickRate: Mutex<RefCell<u32>> = Mutex::new(RefCell::new(DEFAULT_TICK_HZ));
+
+/// The tick rate assumed by `ms_to_ticks` and `ticks_to_ms` if `configure_tick` is not used.
+pub const DEFAULT_TICK_HZ: u32 = 1000;
+
+/// The largest value of the 24-bit SysTick reload register.
+const MAX_RELOAD: u32 = 0x00FF_FFFF;
+
+/// Global instance of the table of sleeping tasks.
+static SleepingTasks: Mutex<RefCell<SleepTable>> = Mutex::new(RefCell::new(SleepTable::new()));
  
 // TODO: on timer expire raise an event or make a log entry
 
-pub fn update_time() {
+/// Advances the Kernel time by `ticks` ticks.
+pub fn update_time(ticks: u32) {
     critical_section(|cs_token| {
         let time = &mut *SystemTimer.borrow(cs_token).borrow_mut();
-        *time += 1;
+        *time = time.wrapping_add(ticks);
     })
 }
 
@@ -30,6 +48,154 @@ pub fn start_timer(peripherals: &mut Peripherals, tick_interval: u32) {
     let syst = &mut peripherals.SYST;
     syst.set_clock_source(SystClkSource::Core);
     syst.set_reload(tick_interval);
+    #[cfg(feature="tickless")]
+    crate::kernel::tickless::set_tick_cycles(tick_interval + 1);
     syst.enable_counter();
     syst.enable_interrupt();
-}
\ No newline at end of file
+}
+
+/// Returns the SysTick reload value for a tick rate of `hz` on a core clocked at `cpu_hz`, or `None`
+/// if it does not fit in the 24-bit reload register.
+pub const fn tick_reload(hz: u32, cpu_hz: u32) -> Option<u32> {
+    if hz == 0 || cpu_hz / hz == 0 || cpu_hz / hz - 1 > MAX_RELOAD {
+        return None;
+    }
+    Some(cpu_hz / hz - 1)
+}
+
+/// Compile-time checks of the reload computation, and of the rejection of reload values which overflow.
+const _: () = [()][match tick_reload(1000, 16_000_000) { Some(15_999) => false, _ => true } as usize];
+const _: () = [()][match tick_reload(1, 168_000_000) { None => false, _ => true } as usize];
+const _: () = [()][match tick_reload(0, 16_000_000) { None => false, _ => true } as usize];
+
+/// Starts the Kernel timer with `hz` ticks per second on a core clocked at `cpu_hz`, in place of
+/// `start_timer`. It must be called before `start_kernel`. Returns `KernelError::InvalidArgument` if the
+/// resulting SysTick reload value does not fit in the 24-bit reload register.
+pub fn configure_tick(hz: u32, cpu_hz: u32) -> Result<(), KernelError> {
+    let reload = tick_reload(hz, cpu_hz).ok_or(KernelError::InvalidArgument)?;
+    critical_section(|cs_token| {
+        TickRate.borrow(cs_token).replace(hz);
+        let syst = unsafe { &*SYST::ptr() };
+        unsafe {
+            syst.rvr.write(reload);
+            syst.cvr.write(0);
+            // Core clock source, SysTick interrupt and counter enabled.
+            syst.csr.modify(|csr| csr | 0b111);
+        }
+    });
+    #[cfg(feature="tickless")]
+    crate::kernel::tickless::set_tick_cycles(reload + 1);
+    Ok(())
+}
+
+/// Converts a duration in milliseconds to timer ticks, rounding up so that a timeout never expires early.
+pub fn ms_to_ticks(ms: u32) -> u32 {
+    let hz = critical_section(|cs_token| *TickRate.borrow(cs_token).borrow()) as u64;
+    ((ms as u64 * hz + 999) / 1000) as u32
+}
+
+/// Converts a number of timer ticks to a duration in milliseconds, rounding down.
+pub fn ticks_to_ms(ticks: u32) -> u32 {
+    let hz = critical_section(|cs_token| *TickRate.borrow(cs_token).borrow()) as u64;
+    (ticks as u64 * 1000 / hz) as u32
+}
+
+/// Blocks the currently running task until the kernel timer reaches the absolute tick `tick`.
+/// Unlike a relative sleep, periodic tasks which sleep until `next += PERIOD` do not accumulate drift.
+/// If `tick` has already been reached, the function returns immediately.
+/// The sleep ends only at `tick`, or earlier through `wake` or `cancel_sleep`; releasing the task does not end it.
+pub fn sleep_until(tick: u32) {
+    critical_section(|cs_token| {
+        if tick_reached(get_time(), tick) {
+            return;
+        }
+        let handler = &mut TaskManager.borrow(cs_token).borrow_mut();
+        let curr_tid = handler.curr_tid;
+        if curr_tid == IDLE_TASK as usize {
+            return;
+        }
+        SleepingTasks.borrow(cs_token).borrow_mut().set_wake_tick(curr_tid as TaskId, tick);
+        // The task leaves the ready state until the timer sweep wakes it.
+        handler.sleep_curr_task();
+    });
+    schedule()
+}
+
+/// Cancels the pending wake up of the task `tid`, and returns why its last sleep ended. It is called by a task
+/// blocked with a timeout once it runs again: `WakeReason::Woken` tells it was handed what it waits for.
+pub fn cancel_sleep(tid: TaskId) -> WakeReason {
+    critical_section(|cs_token| {
+        TaskManager.borrow(cs_token).borrow_mut().wake_tasks(1 << tid);
+        SleepingTasks.borrow(cs_token).borrow_mut().clear_wake_tick(tid)
+    })
+}
+
+/// Ends the sleep of the tasks in `tasks_mask` before their wake tick, when a primitive hands them what they
+/// wait for; their sleep ends with `WakeReason::Woken`. The caller must call `schedule` afterwards.
+pub fn wake(tasks_mask: BooleanVector) {
+    critical_section(|cs_token| {
+        let sleeping_tasks = &mut SleepingTasks.borrow(cs_token).borrow_mut();
+        for tid in 0..MAX_TASKS {
+            if tasks_mask & (1 << tid) != 0 {
+                sleeping_tasks.wake(tid as TaskId);
+            }
+        }
+        TaskManager.borrow(cs_token).borrow_mut().wake_tasks(tasks_mask);
+    })
+}
+
+/// Blocks the currently running task for `ticks` timer ticks. The task is not scheduled until
+/// the delay elapses; `sleep(0)` just yields.
+pub fn sleep(ticks: u32) {
+    if ticks == 0 {
+        return task_yield();
+    }
+    sleep_until(get_time().wrapping_add(ticks))
+}
+
+/// Returns the first tick after the current time which is a multiple of `period`.
+/// Returns `KernelError::InvalidArgument` if `period` is 0.
+pub fn next_period_boundary(period: u32) -> Result<u32, KernelError> {
+    if period == 0 {
+        return Err(KernelError::InvalidArgument);
+    }
+    let time = get_time();
+    Ok(time.wrapping_sub(time % period).wrapping_add(period))
+}
+
+/// Returns the number of ticks until the nearest wake tick of a sleeping task.
+pub fn ticks_to_next_wake(curr_time: u32) -> Option<u32> {
+    critical_section(|cs_token| SleepingTasks.borrow(cs_token).borrow().ticks_to_next_wake(curr_time))
+}
+
+/// Wakes all tasks whose wake tick has been reached.
+pub fn sweep_sleeping() {
+    critical_section(|cs_token| {
+        let tasks_mask = SleepingTasks.borrow(cs_token).borrow_mut().sweep(get_time());
+        TaskManager.borrow(cs_token).borrow_mut().wake_tasks(tasks_mask);
+    })
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reload_must_fit_the_register() {
+        assert_eq!(tick_reload(1000, 16_000_000), Some(15_999));
+        assert_eq!(tick_reload(100, 1_677_721_600), Some(MAX_RELOAD));
+        assert_eq!(tick_reload(99, 1_677_721_600), None);
+        assert_eq!(tick_reload(0, 16_000_000), None);
+        assert_eq!(tick_reload(16_000_001, 16_000_000), None);
+    }
+
+    #[test]
+    fn durations_round_towards_longer_timeouts() {
+        assert_eq!(ms_to_ticks(5), 5);
+        assert_eq!(ticks_to_ms(5), 5);
+        critical_section(|cs_token| TickRate.borrow(cs_token).replace(300));
+        // 10 ms is 3 ticks, which are 10 ms; a timeout of 11 ms takes a fourth tick.
+        let (ticks, long_ticks, ms) = (ms_to_ticks(10), ms_to_ticks(11), ticks_to_ms(4));
+        critical_section(|cs_token| TickRate.borrow(cs_token).replace(DEFAULT_TICK_HZ));
+        assert_eq!((ticks, long_ticks, ms), (3, 4, 13));
+    }
+}
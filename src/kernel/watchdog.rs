@@ -0,0 +1,67 @@
+//! # Watchdog Module
+//!
+//! Liveness monitoring of tasks: a monitored task must call `checkpoint` at least once every period
+//! set with `watchdog_arm`, else the deadline miss handler is called with its TaskId. The countdowns
+//! are advanced by the SysTick handler. This is distinct from the `task_monitor` deadlines, which
+//! bound the completion of a task after it is released.
+
+use core::cell::RefCell;
+
+use crate::KernelError;
+use crate::config::MAX_TASKS;
+use crate::kernel::tasks::current_task;
+use crate::system::scheduler::TaskId;
+use crate::system::watchdog::WatchdogTable;
+use crate::utils::arch::{Mutex, critical_section};
+
+/// Global instance of the watchdog table.
+static Watchdog: Mutex<RefCell<WatchdogTable>> = Mutex::new(RefCell::new(WatchdogTable::new()));
+
+/// Arms the watchdog of the task `task`, it must reach a checkpoint within every `ticks` ticks.
+/// Returns `KernelError::InvalidArgument` if `task` is not below `MAX_TASKS`.
+pub fn watchdog_arm(task: TaskId, ticks: u32) -> Result<(), KernelError> {
+    critical_section(|cs_token| Watchdog.borrow(cs_token).borrow_mut().arm(task, ticks))
+}
+
+/// Disarms the watchdog of the task `task`. Returns `KernelError::InvalidArgument` if `task` is not below `MAX_TASKS`.
+pub fn watchdog_disarm(task: TaskId) -> Result<(), KernelError> {
+    critical_section(|cs_token| Watchdog.borrow(cs_token).borrow_mut().disarm(task))
+}
+
+/// Returns true if the watchdog of the task `task` is armed.
+pub(crate) fn is_armed(task: TaskId) -> bool {
+    critical_section(|cs_token| Watchdog.borrow(cs_token).borrow().is_armed(task))
+}
+
+/// Restarts the watchdog countdown of the currently running task.
+pub fn checkpoint() -> Result<(), KernelError> {
+    critical_section(|cs_token| Watchdog.borrow(cs_token).borrow_mut().checkpoint(current_task()))
+}
+
+/// Registers the handler called with the TaskId of a task which missed its deadline. It is called from
+/// the SysTick handler. If no handler is registered, a deadline miss panics.
+pub fn on_deadline_miss(handler: fn(TaskId)) {
+    critical_section(|cs_token| Watchdog.borrow(cs_token).borrow_mut().miss_handler = Some(handler))
+}
+
+/// Returns the number of ticks until the nearest watchdog deadline.
+pub fn ticks_to_next_deadline() -> Option<u32> {
+    critical_section(|cs_token| Watchdog.borrow(cs_token).borrow().ticks_to_next_deadline())
+}
+
+/// Advances the watchdog countdowns by `ticks` ticks and calls the miss handler for each task which
+/// missed its deadline.
+pub fn sweep_watchdog(ticks: u32) {
+    let (missed, miss_handler) = critical_section(|cs_token| {
+        let watchdog = &mut Watchdog.borrow(cs_token).borrow_mut();
+        (watchdog.sweep(ticks), watchdog.miss_handler)
+    });
+    for tid in 0..MAX_TASKS {
+        if missed & (1 << tid) != 0 {
+            match miss_handler {
+                Some(handler) => handler(tid as TaskId),
+                None => panic!("task {} missed its watchdog deadline", tid),
+            }
+        }
+    }
+}
@@ -10,7 +10,7 @@
 //! harsark = { version = "0.3.5" }
 //! ```
 
-#![no_std]
+#![cfg_attr(not(test), no_std)]
 #![feature(asm)]
 #![feature(const_fn)]
 #![feature(lang_items)]
@@ -18,6 +18,7 @@
 #![feature(const_loop)]
 #![feature(const_generics)]
 #![feature(const_fn_fn_ptr_basics)]
+#![feature(never_type)]
 
 #[cfg(feature = "alloc")]
 pub extern crate alloc;
@@ -40,6 +41,16 @@ use crate::utils::errors::KernelError;
 /// Helper functions.
 pub mod helpers {
     pub use crate::utils::helpers::TaskMask;
+    pub use crate::system::scheduler::BooleanVector;
+    pub use crate::config::MAX_TASKS;
+    pub use crate::config::WATERMARK_FILL_LIMIT;
+    pub use crate::config::NVIC_IRQ_COUNT;
+}
+
+/// Kernel routines which route device interrupts to tasks.
+pub mod interrupts {
+    pub use crate::kernel::interrupts::{on_irq, clear_irq, dispatch_irq, dispatch_active_irq};
+    pub use crate::kernel::tasks::{bind_interrupt, notify_from_isr, IrqBinding};
 }
 
 /// Kernel routines which assist in Event management.
@@ -54,33 +65,138 @@ pub mod events {
 #[cfg(feature = "timer")]
 pub mod timer {
     pub use crate::kernel::timer::start_timer;
+    pub use crate::kernel::timer::{configure_tick, ms_to_ticks, ticks_to_ms};
+    pub use crate::kernel::timer::get_time;
+    pub use crate::kernel::timer::sleep;
+    pub use crate::kernel::timer::sleep_until;
+    pub use crate::kernel::timer::next_period_boundary;
+    pub use crate::kernel::soft_timer::SoftTimer;
+}
+/// Kernel routines which monitor the liveness of tasks.
+#[cfg(feature = "timer")]
+pub mod watchdog {
+    pub use crate::kernel::watchdog::watchdog_arm;
+    pub use crate::kernel::watchdog::watchdog_disarm;
+    pub use crate::kernel::watchdog::checkpoint;
+    pub use crate::kernel::watchdog::on_deadline_miss;
 }
+
+/// Kernel routines which let interrupt handlers defer work to task context.
+pub mod deferred {
+    pub use crate::kernel::deferred::{defer, register_deferred};
+    pub use crate::kernel::deferred::defer_call;
+    pub use crate::system::deferred::DEFERRED_SLOTS;
+    pub use crate::kernel::deferred::process;
+    pub use crate::kernel::deferred::set_worker;
+}
+
+/// Kernel routines which let unprivileged tasks perform privileged operations via the SVC instruction.
+pub mod syscall {
+    pub use crate::kernel::syscall::Syscall;
+    pub use crate::kernel::syscall::release;
+    pub use crate::kernel::syscall::create_resource;
+    pub use crate::kernel::syscall::broadcast;
+    pub use crate::kernel::tasks::drop_privileges;
+}
+
 /// Kernel primitives which assist application development.
 pub mod primitives {
     pub use crate::system::message::Message;
+    #[cfg(feature = "cpu_stats")]
+    pub use crate::system::message::LatencyStats;
+    pub use crate::system::message_ref::MessageRef;
+    pub use crate::system::queue::Queue;
     pub use crate::system::resource::Resource;
+    pub use crate::system::resource::LazyResource;
+    pub use crate::system::resource::ResourceGuard;
+    pub use crate::system::resource::ResourceStats;
+    pub use crate::system::rw_resource::RwResource;
+    pub use crate::system::pi_resource::PiResource;
     pub use crate::system::semaphore::Semaphore;
+    pub use crate::system::counting_semaphore::CountingSemaphore;
+    #[cfg(feature = "timer")]
+    pub use crate::system::binary_semaphore::BinarySemaphore;
+    #[cfg(feature = "timer")]
+    pub use crate::system::event_group::EventGroup;
+    #[cfg(feature = "timer")]
+    pub use crate::system::rpc_channel::RpcChannel;
+    #[cfg(feature = "timer")]
+    pub use crate::system::pool::{Pool, PoolGuard};
+}
+
+/// Kernel routines which assist in Resource management.
+pub mod resources {
+    pub use crate::system::resource::system_ceiling_is_empty;
+    pub use crate::system::resource::lock_depth;
+    pub use crate::system::resource::{system_ceiling, is_locked};
+    pub use crate::system::resource::{acquire_all, LockableResource};
 }
 
 /// Kernel routines which assist in Task management.
 pub mod tasks {
     pub use crate::kernel::tasks::enable_preemption;
     pub use crate::kernel::tasks::disable_preemption;
+    pub use crate::kernel::tasks::scheduler_lock;
+    pub use crate::kernel::tasks::scheduler_unlock;
     pub use crate::kernel::tasks::create_task;
+    pub use crate::kernel::tasks::spawn_task;
+    #[cfg(feature="fpu")]
+    pub use crate::kernel::tasks::create_task_fpu;
+    pub use crate::kernel::tasks::set_priority;
+    pub use crate::kernel::tasks::delete_task;
+    pub use crate::kernel::tasks::restart_task;
+    pub use crate::kernel::tasks::suspend_task;
+    pub use crate::kernel::tasks::resume_task;
     pub use crate::kernel::tasks::init;
+    pub use crate::kernel::tasks::current_task;
+    pub use crate::system::scheduler::IDLE_TASK;
+    #[allow(deprecated)]
     pub use crate::kernel::tasks::get_curr_tid;
     pub use crate::kernel::tasks::release;
+    pub use crate::kernel::tasks::release_tasks;
     pub use crate::kernel::tasks::start_kernel;
     pub use crate::kernel::tasks::task_exit;
+    pub use crate::kernel::tasks::task_yield;
+    pub use crate::kernel::tasks::yield_to;
+    #[cfg(feature = "timer")]
+    pub use crate::kernel::tasks::join;
+    pub use crate::kernel::tasks::check_invariants;
+    pub use crate::kernel::tasks::task_state;
+    pub use crate::system::scheduler::TaskState;
+    pub use crate::system::scheduler::InvariantViolation;
+    pub use crate::kernel::tasks::set_system_idle_hooks;
+    pub use crate::kernel::tasks::set_idle_hook;
+    #[cfg(feature="stack_guard")]
+    pub use crate::kernel::tasks::set_low_stack_hook;
+    #[cfg(feature="stack_guard")]
+    pub use crate::kernel::tasks::paint_stack;
+    #[cfg(feature="mpu")]
+    pub use crate::kernel::tasks::enable_stack_guards;
+    #[cfg(feature="cpu_stats")]
+    pub use crate::kernel::tasks::cpu_cycles;
+    #[cfg(feature="cpu_stats")]
+    pub use crate::kernel::tasks::reset_cpu_stats;
     #[cfg(feature="task_monitor")]
     pub use crate::kernel::task_monitor::set_handler as set_deadline_exceed_handler;
+    #[cfg(feature="panic_task_exit")]
+    pub use crate::kernel::panic::set_panic_hook;
+}
+
+#[cfg(feature="debug_support")]
+/// Kernel state exposed to kernel-aware debuggers.
+pub mod debug {
+    pub use crate::kernel::debug::{KernelDebugDescriptor, KERNEL_DEBUG_DESCRIPTOR, KERNEL_DEBUG_VERSION};
+    pub use crate::kernel::debug::{kernel_ready_mask, kernel_blocked_mask};
 }
 
 #[cfg(feature="system_logger")]
 /// Kernel routines which handle log management.
 pub mod logging {
     pub use crate::kernel::logging::process;
+    pub use crate::kernel::logging::drain_logs;
+    pub use crate::kernel::logging::now_ticks;
     pub use crate::kernel::logging::set_all;
+    pub use crate::kernel::logging::set_task_log_filter;
     pub use crate::kernel::logging::set_release;
     pub use crate::kernel::logging::set_block_tasks;
     pub use crate::kernel::logging::set_unblock_tasks;
@@ -92,7 +208,16 @@ pub mod logging {
     pub use crate::kernel::logging::set_semaphore_signal;
     pub use crate::kernel::logging::set_semaphore_reset;
     pub use crate::kernel::logging::set_timer_event;
-    pub use crate::system::system_logger::LogEvent;
+    pub use crate::system::system_logger::LogRecord;
+    pub use crate::kernel::logging::KernelLogger;
+    pub use crate::kernel::logging::set_text_sink;
+    pub use crate::kernel::logging::TEXT_BUFFER_SIZE;
+    pub use crate::kernel::logging::set_sink;
+    pub use crate::system::system_logger::LogEventType;
+    pub use crate::system::log_sink::LogSink;
+    pub use crate::system::log_sink::SemihostingSink;
+    #[cfg(feature="log_rtt")]
+    pub use crate::system::log_sink::RttSink;
 }
 
 #[cfg(feature = "alloc")]
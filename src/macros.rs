@@ -3,40 +3,94 @@
 
 /// The tasks must be looping infinitely and call `task_exit` whenever a particular task is done.
 /// This makes it complicated to create tasks and also might introduce undefined behavior if task_exit is not called.
-/// The `spawn` macro makes it easier to define tasks. It also defines a static variable of type TaskId,
-/// which corresponds to the task created.
+/// The `spawn` macro makes it easier to define tasks: it creates the task with the given priority and stack,
+/// with a handler which calls `task_exit` after each run of the block.
+///
+/// The priority must be a constant expression; a priority which is not below `MAX_TASKS` fails the compilation.
+/// A handler written as `loop { .. }` never finishes, hence it is used as is, without the call to `task_exit`.
+/// With the `task_monitor` feature, the deadline of the task follows the priority, e.g. `spawn!(sensor, 100, stack1, { .. })`.
 ///
 /// ## Examples
 ///
 /// ```rust
-/// shared = 10;
-/// spawn!(task2, 2, stack1, shared, params, {
-///     hprintln!("{}", params);
-/// });
-/// spawn!(task3, 3, stack2, {
+/// const sensor: u32 = 2;
+/// static mut stack1: [u32; 256] = [0; 256];
+/// static mut stack2: [u32; 256] = [0; 256];
+///
+/// spawn!(sensor, stack1, {
 ///     hprintln!("Hello!");
 /// });
+/// spawn!(3, stack2, loop {
+///     sleep(100);
+/// });
 /// ```
 #[macro_export]
 macro_rules! spawn {
+    ($priority: expr, $stack: expr, loop $handler_fn: block) => {
+        {
+            const _: () = [()][($priority as usize >= $crate::helpers::MAX_TASKS) as usize];
+            $crate::kernel_assert!(Ok(create_task(
+                $priority,
+                unsafe{ &mut $stack },
+                || loop $handler_fn
+            )), "spawn! can't create the task");
+        }
+    };
+    ($priority: expr, $deadline: expr, $stack: expr, loop $handler_fn: block) => {
+        {
+            const _: () = [()][($priority as usize >= $crate::helpers::MAX_TASKS) as usize];
+            $crate::kernel_assert!(Ok(create_task(
+                $priority,
+                $deadline,
+                unsafe{ &mut $stack },
+                || loop $handler_fn
+            )), "spawn! can't create the task");
+        }
+    };
     ($priority: expr, $stack: expr, $handler_fn: block) => {
-        create_task(
-            $priority,
-            unsafe{ &mut $stack },
-            || loop {
-                $handler_fn
-                task_exit();
-        }).unwrap();
+        {
+            const _: () = [()][($priority as usize >= $crate::helpers::MAX_TASKS) as usize];
+            $crate::kernel_assert!(Ok(create_task(
+                $priority,
+                unsafe{ &mut $stack },
+                || loop {
+                    $handler_fn
+                    task_exit();
+            })), "spawn! can't create the task");
+        }
     };
     ($priority: expr, $deadline: expr, $stack: expr, $handler_fn: block) => {
-        create_task(
-            $priority,
-            $deadline,
-            unsafe{ &mut $stack },
-            || loop {
-                $handler_fn
-                task_exit();
-        }).unwrap();
+        {
+            const _: () = [()][($priority as usize >= $crate::helpers::MAX_TASKS) as usize];
+            $crate::kernel_assert!(Ok(create_task(
+                $priority,
+                $deadline,
+                unsafe{ &mut $stack },
+                || loop {
+                    $handler_fn
+                    task_exit();
+            })), "spawn! can't create the task");
+        }
+    };
+}
+
+/// `task_mask!` builds the boolean vector corresponding to the named tasks. It expands to a constant
+/// expression; hence it can be used in `static` declarations of kernel primitives. A TaskId which is not
+/// below `MAX_TASKS` fails the compilation.
+/// ## Example
+/// ```rust
+/// const sensor: u32 = 1;
+/// const logger: u32 = 2;
+/// static res1: Resource<[u32; 3]> = Resource::new([1, 2, 3], task_mask!(sensor, logger));
+/// ```
+#[macro_export]
+macro_rules! task_mask {
+    ($($task: expr),* $(,)?) => {
+        {
+            const MASK: $crate::helpers::BooleanVector = 0 $(| 1 << $task)*;
+            [()][((MASK as u128) >> $crate::helpers::MAX_TASKS != 0) as usize];
+            MASK
+        }
     };
 }
 
@@ -56,3 +110,35 @@ macro_rules! priv_execute {
         }
     };
 }
+
+/// `kernel_assert!` checks an internal invariant of the Kernel, and panics with `msg` if it is broken.
+/// With the `panic_task_exit` feature, the hook registered with `set_panic_hook` is called with the
+/// panic and the TaskId of the running task. The form `kernel_assert!(Some(option), msg)` evaluates
+/// to the content of `option`, it asserts that `option` is not `None`; likewise `kernel_assert!(Ok(result), msg)`
+/// evaluates to the value of `result`, it asserts that `result` is not an error.
+/// ## Example
+/// ```rust
+/// kernel_assert!(tid < MAX_TASKS, "the TaskId is out of range");
+/// let tcb = kernel_assert!(Some(task_control_blocks[tid].as_ref()), "the task has no TCB");
+/// kernel_assert!(Ok(create_task(1, unsafe { &mut stack1 }, handler)), "the task can't be created");
+/// ```
+#[macro_export]
+macro_rules! kernel_assert {
+    (Some($option: expr), $msg: literal) => {
+        match $option {
+            Some(value) => value,
+            None => panic!(concat!("kernel invariant violated: ", $msg)),
+        }
+    };
+    (Ok($result: expr), $msg: literal) => {
+        match $result {
+            Ok(value) => value,
+            Err(_) => panic!(concat!("kernel invariant violated: ", $msg)),
+        }
+    };
+    ($cond: expr, $msg: literal) => {
+        if !($cond) {
+            panic!(concat!("kernel invariant violated: ", $msg));
+        }
+    };
+}
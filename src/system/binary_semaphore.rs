@@ -0,0 +1,150 @@
+//! # Binary Semaphore
+//!
+use core::cell::RefCell;
+use crate::system::scheduler::BooleanVector;
+use crate::KernelError;
+use crate::kernel::tasks::{current_task, release, schedule};
+use crate::kernel::timer::{cancel_sleep, get_time, sleep_until, wake};
+use crate::system::sleep_table::{WakeReason, tick_reached};
+use crate::utils::arch::{critical_section, get_msb};
+
+/// A semaphore which is either available or not, it hands off a signal (typically from an interrupt
+/// handler) to exactly one task. Signals given while it is available are not accumulated.
+pub struct BinarySemaphore {
+    /// True if the semaphore can be taken without waiting.
+    available: RefCell<bool>,
+    /// A boolean vector of the tasks waiting in `take`.
+    waiters: RefCell<BooleanVector>,
+}
+
+impl BinarySemaphore {
+    /// Initializes a new binary semaphore instance.
+    pub const fn new(available: bool) -> Self {
+        Self {
+            available: RefCell::new(available),
+            waiters: RefCell::new(0),
+        }
+    }
+
+    /// Gives the semaphore. If tasks are waiting, it is handed to the highest priority waiter, which is
+    /// woken and released, hence it preempts the caller if it has a higher priority; otherwise it becomes
+    /// available. It is safe to call from interrupt context.
+    pub fn give(&'static self) {
+        critical_section(|_| {
+            let waiters: &mut BooleanVector = &mut self.waiters.borrow_mut();
+            match get_msb(*waiters) {
+                Some(tid) => {
+                    *waiters &= !(1 << tid);
+                    wake(1 << tid);
+                    release(1 << tid);
+                    schedule();
+                },
+                None => {
+                    self.available.replace(true);
+                },
+            }
+        })
+    }
+
+    /// Takes the semaphore. If it is not available, the currently running task is blocked until either
+    /// it is given or `timeout_ticks` timer ticks elapse, in which case `KernelError::TimedOut` is returned.
+    /// With a `timeout_ticks` of 0 it does not block. If the task is woken before the deadline without being
+    /// given the semaphore, it blocks again until the deadline.
+    pub fn take(&'static self, timeout_ticks: u32) -> Result<(), KernelError> {
+        let curr_tid = current_task();
+        let deadline = get_time().wrapping_add(timeout_ticks);
+        loop {
+            let taken = critical_section(|_| {
+                if self.available.replace(false) {
+                    return Some(Ok(()));
+                }
+                if timeout_ticks == 0 || tick_reached(get_time(), deadline) {
+                    return Some(Err(KernelError::TimedOut));
+                }
+                *self.waiters.borrow_mut() |= 1 << curr_tid;
+                // The context switch is carried out once the critical section ends.
+                sleep_until(deadline);
+                None
+            });
+            if let Some(taken) = taken {
+                return taken;
+            }
+            let given = critical_section(|_| {
+                *self.waiters.borrow_mut() &= !(1 << curr_tid);
+                // `give` wakes the waiter it hands the semaphore to.
+                cancel_sleep(curr_tid) == WakeReason::Woken
+            });
+            if given {
+                return Ok(());
+            }
+        }
+    }
+}
+
+unsafe impl Sync for BinarySemaphore {}
+
+// The tests rely on preemption, which the `cooperative` feature disables.
+#[cfg(all(test, not(feature = "cooperative")))]
+mod tests {
+    use super::*;
+    use crate::kernel::tasks::{release_tasks, task_exit};
+    use crate::utils::host::{boot, kernel_test, run_isr, take_trace, tick, trace};
+
+    static HANDOFF: BinarySemaphore = BinarySemaphore::new(false);
+
+    #[test]
+    fn give_hands_off_to_the_waiter() {
+        fn giver() -> ! {
+            loop {
+                trace(1);
+                release_tasks(1 << 2).unwrap();
+                trace(11);
+                HANDOFF.give();
+                trace(12);
+                task_exit();
+            }
+        }
+        fn taker() -> ! {
+            loop {
+                trace(2);
+                HANDOFF.take(10).unwrap();
+                trace(21);
+                task_exit();
+            }
+        }
+        let _kernel = kernel_test();
+        boot(&[(1, giver), (2, taker)]);
+        release_tasks(1 << 1).unwrap();
+        // The waiter is released by the give, and preempts the giver at once.
+        assert_eq!(take_trace(), [1, 2, 11, 21, 12]);
+        // The semaphore was handed off, not left available.
+        assert!(matches!(HANDOFF.take(0), Err(KernelError::TimedOut)));
+    }
+
+    static SIGNAL: BinarySemaphore = BinarySemaphore::new(false);
+
+    #[test]
+    fn take_is_signaled_from_an_interrupt_or_times_out() {
+        fn taker() -> ! {
+            loop {
+                trace(1);
+                trace(SIGNAL.take(2).is_ok() as u32);
+                task_exit();
+            }
+        }
+        let _kernel = kernel_test();
+        boot(&[(1, taker)]);
+        SIGNAL.give();
+        SIGNAL.give();
+        // A given semaphore is taken without blocking, and the signals are not accumulated.
+        release_tasks(1 << 1).unwrap();
+        assert_eq!(take_trace(), [1, 1]);
+        release_tasks(1 << 1).unwrap();
+        assert_eq!(take_trace(), [1]);
+        run_isr(|| SIGNAL.give());
+        assert_eq!(take_trace(), [1]);
+        release_tasks(1 << 1).unwrap();
+        tick(2);
+        assert_eq!(take_trace(), [1, 0]);
+    }
+}
@@ -0,0 +1,197 @@
+//! # Counting Semaphore
+//!
+use core::cell::RefCell;
+use crate::system::scheduler::BooleanVector;
+use crate::kernel::tasks::{deactivate_curr_task, current_task, release, schedule};
+#[cfg(feature="timer")]
+use crate::KernelError;
+#[cfg(feature="timer")]
+use crate::kernel::timer::{cancel_sleep, get_time, sleep_until, wake};
+#[cfg(feature="timer")]
+use crate::system::sleep_table::{WakeReason, tick_reached};
+use crate::utils::arch::{critical_section, get_msb};
+
+/// A semaphore holding a bounded count, it enables producer/consumer patterns between tasks.
+pub struct CountingSemaphore {
+    /// The number of available units.
+    count: RefCell<u32>,
+    /// The maximum number of available units, signals beyond it are dropped.
+    max: u32,
+    /// A boolean vector of the tasks waiting for a unit.
+    waiters: RefCell<BooleanVector>,
+}
+
+impl CountingSemaphore {
+    /// Initializes a new counting semaphore instance.
+    pub const fn new(initial: u32, max: u32) -> Self {
+        Self {
+            count: RefCell::new(initial),
+            max,
+            waiters: RefCell::new(0),
+        }
+    }
+
+    /// Signals the semaphore. If tasks are waiting, the highest priority waiter is handed the unit
+    /// and released; otherwise the count is incremented, saturating at `max`. It is safe to call
+    /// from interrupt context.
+    pub fn signal(&'static self) {
+        critical_section(|_| {
+            let waiters: &mut BooleanVector = &mut self.waiters.borrow_mut();
+            match get_msb(*waiters) {
+                Some(tid) => {
+                    *waiters &= !(1 << tid);
+                    release(1 << tid);
+                    // The waiter sleeps if it waits in `wait_timeout`.
+                    #[cfg(feature="timer")]
+                    wake(1 << tid);
+                    schedule();
+                },
+                None => {
+                    let count: &mut u32 = &mut self.count.borrow_mut();
+                    if *count < self.max {
+                        *count += 1;
+                    }
+                },
+            }
+        })
+    }
+
+    /// Takes a unit from the semaphore. If none is available, the currently running task is blocked
+    /// until a call to `signal` hands it a unit. Blocking requires preemptive scheduling.
+    /// If the task is released by other means meanwhile (e.g. by a message), it blocks again; hence it
+    /// returns only once it has taken a unit.
+    pub fn wait(&'static self) {
+        let curr_tid = current_task();
+        let mut waiting = false;
+        loop {
+            let taken = critical_section(|_| {
+                let waiters: &mut BooleanVector = &mut self.waiters.borrow_mut();
+                // `signal` removes the waiter it hands the unit to.
+                if waiting && *waiters & (1 << curr_tid) == 0 {
+                    return true;
+                }
+                let count: &mut u32 = &mut self.count.borrow_mut();
+                if *count > 0 {
+                    *count -= 1;
+                    *waiters &= !(1 << curr_tid);
+                    return true;
+                }
+                *waiters |= 1 << curr_tid;
+                deactivate_curr_task();
+                // The context switch is carried out once the critical section ends.
+                schedule();
+                false
+            });
+            if taken {
+                return;
+            }
+            waiting = true;
+        }
+    }
+
+    /// Takes a unit from the semaphore. If none is available, the currently running task is blocked until
+    /// either `signal` hands it a unit or `timeout_ticks` timer ticks elapse, in which case
+    /// `KernelError::TimedOut` is returned. With a `timeout_ticks` of 0 it does not block.
+    #[cfg(feature="timer")]
+    pub fn wait_timeout(&'static self, timeout_ticks: u32) -> Result<(), KernelError> {
+        let curr_tid = current_task();
+        let deadline = get_time().wrapping_add(timeout_ticks);
+        loop {
+            let taken = critical_section(|_| {
+                let count: &mut u32 = &mut self.count.borrow_mut();
+                if *count > 0 {
+                    *count -= 1;
+                    return Some(Ok(()));
+                }
+                if timeout_ticks == 0 || tick_reached(get_time(), deadline) {
+                    return Some(Err(KernelError::TimedOut));
+                }
+                *self.waiters.borrow_mut() |= 1 << curr_tid;
+                // The context switch is carried out once the critical section ends.
+                sleep_until(deadline);
+                None
+            });
+            if let Some(taken) = taken {
+                return taken;
+            }
+            let handed = critical_section(|_| {
+                *self.waiters.borrow_mut() &= !(1 << curr_tid);
+                // `signal` wakes the waiter it hands the unit to.
+                cancel_sleep(curr_tid) == WakeReason::Woken
+            });
+            if handed {
+                return Ok(());
+            }
+        }
+    }
+
+    /// Takes a unit from the semaphore if one is available, without blocking.
+    pub fn try_wait(&'static self) -> bool {
+        critical_section(|_| {
+            let count: &mut u32 = &mut self.count.borrow_mut();
+            if *count > 0 {
+                *count -= 1;
+                return true;
+            }
+            false
+        })
+    }
+
+    /// Returns the number of available units.
+    pub fn count(&'static self) -> u32 {
+        critical_section(|_| *self.count.borrow())
+    }
+}
+
+unsafe impl Sync for CountingSemaphore {}
+
+// The tests rely on preemption, which the `cooperative` feature disables.
+#[cfg(all(test, not(feature = "cooperative")))]
+mod tests {
+    use super::*;
+    use crate::kernel::tasks::{release_tasks, task_exit};
+    use crate::utils::host::{boot, kernel_test, take_trace, trace};
+
+    #[test]
+    fn count_saturates_at_max() {
+        static UNITS: CountingSemaphore = CountingSemaphore::new(0, 2);
+        let _kernel = kernel_test();
+        for _ in 0..3 {
+            UNITS.signal();
+        }
+        assert_eq!(UNITS.count(), 2);
+        assert!(UNITS.try_wait() && UNITS.try_wait());
+        assert!(!UNITS.try_wait());
+    }
+
+    static ITEMS: CountingSemaphore = CountingSemaphore::new(1, 4);
+
+    #[test]
+    fn consumer_waits_for_the_producer() {
+        fn producer() -> ! {
+            loop {
+                trace(1);
+                release_tasks(1 << 2).unwrap();
+                trace(11);
+                ITEMS.signal();
+                trace(12);
+                task_exit();
+            }
+        }
+        fn consumer() -> ! {
+            loop {
+                ITEMS.wait();
+                trace(2);
+                ITEMS.wait();
+                trace(21);
+                task_exit();
+            }
+        }
+        let _kernel = kernel_test();
+        boot(&[(1, producer), (2, consumer)]);
+        release_tasks(1 << 1).unwrap();
+        // The consumer takes the initial unit, then waits for the signal, which preempts the producer.
+        assert_eq!(take_trace(), [1, 2, 11, 21, 12]);
+        assert_eq!(ITEMS.count(), 0);
+    }
+}
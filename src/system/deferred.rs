@@ -0,0 +1,158 @@
+//! # Deferred Work Queue
+//!
+//! Defines Data-structures to hold work deferred from interrupt context.
+
+use crate::config::MAX_DEFERRED_WORK;
+use crate::system::scheduler::TaskId;
+use crate::KernelError;
+
+/// A single unit of deferred work, a handler and the argument it is to be called with.
+#[derive(Clone, Copy)]
+pub struct Work {
+    handler: fn(u32),
+    arg: u32,
+}
+
+impl Work {
+    pub const fn new(handler: fn(u32), arg: u32) -> Self {
+        Self { handler, arg }
+    }
+
+    /// Executes the work item.
+    pub fn run(&self) {
+        (self.handler)(self.arg)
+    }
+}
+
+/// The number of work slots, which hold handlers registered by id.
+pub const DEFERRED_SLOTS: usize = 32;
+
+/// A bounded circular queue of work items which are to be executed by the worker task, along with the
+/// work slots: handlers registered by id, which are run once per `set_pending` of their id.
+pub struct WorkQueue {
+    queue: [Option<Work>; MAX_DEFERRED_WORK],
+    /// Points to the oldest work item in the queue.
+    start: usize,
+    /// Number of work items in the queue.
+    len: usize,
+    /// The handler registered for each work slot.
+    slots: [Option<fn()>; DEFERRED_SLOTS],
+    /// A bit vector of the work slots pending to run.
+    pending: u32,
+    /// The task which drains the queue, it is released whenever work is enqueued.
+    pub worker: Option<TaskId>,
+}
+
+impl WorkQueue {
+    pub const fn new() -> Self {
+        Self {
+            queue: [None; MAX_DEFERRED_WORK],
+            start: 0,
+            len: 0,
+            slots: [None; DEFERRED_SLOTS],
+            pending: 0,
+            worker: None,
+        }
+    }
+
+    /// Registers `handler` for the work slot `id`, replacing the handler registered before.
+    /// Returns `KernelError::LimitExceeded` if `id` is not below `DEFERRED_SLOTS`.
+    pub fn register(&mut self, id: usize, handler: fn()) -> Result<(), KernelError> {
+        *self.slots.get_mut(id).ok_or(KernelError::LimitExceeded)? = Some(handler);
+        Ok(())
+    }
+
+    /// Marks the work slot `id` pending. It runs once, however often it is marked before it runs.
+    /// Returns `KernelError::LimitExceeded` if `id` is not below `DEFERRED_SLOTS`, and `KernelError::NotFound`
+    /// if no handler is registered for it.
+    pub fn set_pending(&mut self, id: usize) -> Result<(), KernelError> {
+        self.slots.get(id).ok_or(KernelError::LimitExceeded)?.ok_or(KernelError::NotFound)?;
+        self.pending |= 1 << id;
+        Ok(())
+    }
+
+    /// Clears the pending work slot with the lowest id, and returns its handler.
+    pub fn take_pending(&mut self) -> Option<fn()> {
+        if self.pending == 0 {
+            return None;
+        }
+        let id = self.pending.trailing_zeros() as usize;
+        self.pending &= !(1 << id);
+        self.slots[id]
+    }
+
+    /// Appends a work item to the queue.
+    pub fn push(&mut self, work: Work) -> Result<(), KernelError> {
+        if self.len == MAX_DEFERRED_WORK {
+            return Err(KernelError::LimitExceeded);
+        }
+        self.queue[(self.start + self.len) % MAX_DEFERRED_WORK] = Some(work);
+        self.len += 1;
+        Ok(())
+    }
+
+    /// Removes the oldest work item from the queue.
+    pub fn pop(&mut self) -> Option<Work> {
+        if self.len == 0 {
+            return None;
+        }
+        let work = self.queue[self.start].take();
+        self.start = (self.start + 1) % MAX_DEFERRED_WORK;
+        self.len -= 1;
+        work
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::sync::atomic::{AtomicU32, Ordering};
+
+    static RUNS: AtomicU32 = AtomicU32::new(0);
+    static LAST_ARG: AtomicU32 = AtomicU32::new(0);
+
+    fn record(arg: u32) {
+        LAST_ARG.store(arg, Ordering::SeqCst);
+    }
+
+    fn count() {
+        RUNS.fetch_add(1, Ordering::SeqCst);
+    }
+
+    #[test]
+    fn work_is_run_in_order() {
+        let mut queue = WorkQueue::new();
+        for arg in 0..MAX_DEFERRED_WORK as u32 {
+            queue.push(Work::new(record, arg)).unwrap();
+        }
+        assert!(matches!(queue.push(Work::new(record, 0)), Err(KernelError::LimitExceeded)));
+        for arg in 0..MAX_DEFERRED_WORK as u32 {
+            queue.pop().unwrap().run();
+            assert_eq!(LAST_ARG.load(Ordering::SeqCst), arg);
+        }
+        assert!(queue.pop().is_none());
+        // The queue wraps around once drained.
+        queue.push(Work::new(record, 7)).unwrap();
+        queue.pop().unwrap().run();
+        assert_eq!(LAST_ARG.load(Ordering::SeqCst), 7);
+    }
+
+    #[test]
+    fn pending_slot_runs_once() {
+        let mut queue = WorkQueue::new();
+        assert!(matches!(queue.set_pending(3), Err(KernelError::NotFound)));
+        assert!(matches!(queue.register(DEFERRED_SLOTS, count), Err(KernelError::LimitExceeded)));
+        queue.register(3, count).unwrap();
+        queue.register(1, count).unwrap();
+        // As from an interrupt handler which fires twice before the worker runs.
+        queue.set_pending(3).unwrap();
+        queue.set_pending(3).unwrap();
+        queue.set_pending(1).unwrap();
+        let runs = RUNS.load(Ordering::SeqCst);
+        while let Some(handler) = queue.take_pending() {
+            handler();
+        }
+        assert_eq!(RUNS.load(Ordering::SeqCst), runs + 2);
+        assert!(queue.take_pending().is_none());
+    }
+}
@@ -69,6 +69,15 @@ impl EventTable
         }
     }
 
+    /// Returns the number of ticks from `curr_time` until an enabled event is next dispatched.
+    pub fn ticks_to_next_dispatch(&self, curr_time: u32) -> Option<u32> {
+        self.events[..self.curr].iter()
+            .filter_map(|event| event.as_ref())
+            .filter(|event| event.is_enabled)
+            .map(|event| event.threshold - curr_time % event.threshold)
+            .min()
+    }
+
     /// Enables an Event.
     pub fn enable(&mut self, event_id: EventId) -> Result<(),KernelError> {
         let event = &mut self.events[event_id].as_mut().ok_or(KernelError::NotFound)?;
@@ -0,0 +1,169 @@
+//! # Event Group
+//!
+use core::cell::RefCell;
+use crate::config::MAX_TASKS;
+use crate::system::scheduler::BooleanVector;
+use crate::KernelError;
+use crate::kernel::tasks::{current_task, schedule};
+use crate::kernel::timer::{cancel_sleep, get_time, sleep_until, wake};
+use crate::system::sleep_table::{WakeReason, tick_reached};
+use crate::utils::arch::critical_section;
+
+/// A set of 32 event flags, tasks can wait for any or all of a combination of flags to be set
+/// (e.g. "DMA done AND buffer free"). Flags stay set until they are cleared with `clear_bits`.
+pub struct EventGroup {
+    /// The event flags.
+    flags: RefCell<u32>,
+    /// A boolean vector of the tasks waiting in `wait_bits`.
+    waiters: RefCell<BooleanVector>,
+    /// A boolean vector of the waiting tasks which wait for all of their flags.
+    wait_all: RefCell<BooleanVector>,
+    /// The flags each waiting task waits for, and the flags which satisfied it once it is released.
+    wait_masks: RefCell<[u32; MAX_TASKS]>,
+}
+
+impl EventGroup {
+    /// Initializes a new event group instance with all flags cleared.
+    pub const fn new() -> Self {
+        Self {
+            flags: RefCell::new(0),
+            waiters: RefCell::new(0),
+            wait_all: RefCell::new(0),
+            wait_masks: RefCell::new([0; MAX_TASKS]),
+        }
+    }
+
+    fn is_satisfied(flags: u32, mask: u32, all: bool) -> bool {
+        match all {
+            true => flags & mask == mask,
+            false => flags & mask != 0,
+        }
+    }
+
+    /// Sets the flags in `mask`, and wakes the waiting tasks whose condition is now satisfied.
+    /// It is safe to call from interrupt context.
+    pub fn set_bits(&'static self, mask: u32) {
+        critical_section(|_| {
+            let flags: &mut u32 = &mut self.flags.borrow_mut();
+            *flags |= mask;
+            let waiters: &mut BooleanVector = &mut self.waiters.borrow_mut();
+            let wait_all = *self.wait_all.borrow();
+            let wait_masks = &mut self.wait_masks.borrow_mut();
+            let mut satisfied: BooleanVector = 0;
+            for tid in 0..MAX_TASKS {
+                if *waiters & (1 << tid) != 0 && Self::is_satisfied(*flags, wait_masks[tid], wait_all & (1 << tid) != 0) {
+                    wait_masks[tid] = *flags;
+                    satisfied |= 1 << tid;
+                }
+            }
+            if satisfied != 0 {
+                *waiters &= !satisfied;
+                wake(satisfied);
+                schedule();
+            }
+        })
+    }
+
+    /// Clears the flags in `mask`.
+    pub fn clear_bits(&'static self, mask: u32) {
+        critical_section(|_| *self.flags.borrow_mut() &= !mask)
+    }
+
+    /// Returns the current flags.
+    pub fn get_bits(&'static self) -> u32 {
+        critical_section(|_| *self.flags.borrow())
+    }
+
+    /// Blocks the currently running task until any (`all` is false) or all (`all` is true) of the flags
+    /// in `mask` are set, and returns the flags which satisfied the condition. Returns `KernelError::TimedOut`
+    /// if the condition is not satisfied within `timeout` timer ticks; with a `timeout` of 0 it does not block.
+    /// If the task is woken before the deadline without its condition being satisfied, it blocks again.
+    pub fn wait_bits(&'static self, mask: u32, all: bool, timeout: u32) -> Result<u32, KernelError> {
+        let curr_tid = current_task();
+        let deadline = get_time().wrapping_add(timeout);
+        loop {
+            let flags = critical_section(|_| {
+                let flags = *self.flags.borrow();
+                if Self::is_satisfied(flags, mask, all) {
+                    return Some(Ok(flags));
+                }
+                if timeout == 0 || tick_reached(get_time(), deadline) {
+                    return Some(Err(KernelError::TimedOut));
+                }
+                *self.waiters.borrow_mut() |= 1 << curr_tid;
+                let wait_all: &mut BooleanVector = &mut self.wait_all.borrow_mut();
+                match all {
+                    true => *wait_all |= 1 << curr_tid,
+                    false => *wait_all &= !(1 << curr_tid),
+                }
+                self.wait_masks.borrow_mut()[curr_tid as usize] = mask;
+                // The context switch is carried out once the critical section ends.
+                sleep_until(deadline);
+                None
+            });
+            if let Some(flags) = flags {
+                return flags;
+            }
+            let flags = critical_section(|_| {
+                *self.waiters.borrow_mut() &= !(1 << curr_tid);
+                // `set_bits` wakes the waiters whose condition it satisfies.
+                match cancel_sleep(curr_tid) {
+                    WakeReason::Woken => Some(self.wait_masks.borrow()[curr_tid as usize]),
+                    _ => None,
+                }
+            });
+            if let Some(flags) = flags {
+                return Ok(flags);
+            }
+        }
+    }
+}
+
+unsafe impl Sync for EventGroup {}
+
+// The tests rely on preemption, which the `cooperative` feature disables.
+#[cfg(all(test, not(feature = "cooperative")))]
+mod tests {
+    use super::*;
+    use crate::kernel::tasks::{release_tasks, task_exit};
+    use crate::utils::host::{boot, kernel_test, run_isr, take_trace, tick, trace};
+
+    static EVENTS: EventGroup = EventGroup::new();
+
+    #[test]
+    fn waiters_wake_on_any_or_all_flags() {
+        fn timed() -> ! {
+            loop {
+                assert!(matches!(EVENTS.wait_bits(0b100, true, 2), Err(KernelError::TimedOut)));
+                trace(1);
+                task_exit();
+            }
+        }
+        fn any() -> ! {
+            loop {
+                trace(20 + EVENTS.wait_bits(0b11, false, 10).unwrap());
+                task_exit();
+            }
+        }
+        fn all() -> ! {
+            loop {
+                trace(30 + EVENTS.wait_bits(0b11, true, 10).unwrap());
+                task_exit();
+            }
+        }
+        let _kernel = kernel_test();
+        boot(&[(1, timed), (2, any), (3, all)]);
+        release_tasks(1 << 1 | 1 << 2 | 1 << 3).unwrap();
+        assert_eq!(take_trace(), []);
+        run_isr(|| EVENTS.set_bits(0b01));
+        assert_eq!(take_trace(), [21]);
+        run_isr(|| EVENTS.set_bits(0b10));
+        assert_eq!(take_trace(), [33]);
+        tick(2);
+        assert_eq!(take_trace(), [1]);
+        // The flags stay set until cleared.
+        assert_eq!(EVENTS.get_bits(), 0b11);
+        EVENTS.clear_bits(0b01);
+        assert_eq!(EVENTS.get_bits(), 0b10);
+    }
+}
@@ -0,0 +1,39 @@
+//! # Log Sinks
+//!
+//! Defines the interface through which the Kernel emits log events, along with the sinks shipped with the Kernel.
+
+use core::fmt::Write;
+
+use cortex_m_semihosting::hio;
+
+use crate::system::system_logger::LogRecord;
+
+/// A transport to which log events are written as they are reported, along with their timestamp.
+/// `write_event` is called from within a critical section, hence it should return quickly.
+pub trait LogSink: Sync {
+    fn write_event(&self, record: &LogRecord);
+}
+
+/// Writes log events to the host via semihosting. Semihosting halts the core on every write and
+/// requires a debugger to be attached, hence it is meant only for development.
+pub struct SemihostingSink;
+
+impl LogSink for SemihostingSink {
+    fn write_event(&self, record: &LogRecord) {
+        if let Ok(mut stdout) = hio::hstdout() {
+            writeln!(stdout, "[{}] {:?}", record.tick, record.event).ok();
+        }
+    }
+}
+
+/// Writes log events to an RTT up channel. The application must initialize RTT (e.g. with
+/// `rtt_target::rtt_init_print!()`) before registering this sink.
+#[cfg(feature = "log_rtt")]
+pub struct RttSink;
+
+#[cfg(feature = "log_rtt")]
+impl LogSink for RttSink {
+    fn write_event(&self, record: &LogRecord) {
+        rtt_target::rprintln!("[{}] {:?}", record.tick, record.event);
+    }
+}
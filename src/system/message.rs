@@ -1,12 +1,25 @@
 //! Message primitive
 //!
 
-use core::cell::RefCell;
+use core::alloc::Layout;
+use core::cell::{Cell, RefCell};
 
+use crate::kernel::messages::{self, RegisteredMessage};
 use crate::system::semaphore::Semaphore;
-use crate::system::scheduler::BooleanVector;
-use crate::utils::arch::critical_section;
-use crate::tasks::get_curr_tid;
+use crate::system::scheduler::{BooleanVector, TaskId};
+use crate::KernelError;
+use crate::priv_execute;
+use crate::utils::arch::{critical_section, is_privileged};
+use crate::tasks::current_task;
+use crate::kernel::tasks::get_existing_tasks;
+
+#[cfg(feature = "timer")]
+use crate::kernel::timer::{cancel_sleep, get_time, sleep_until, wake};
+#[cfg(feature = "timer")]
+use crate::system::sleep_table::tick_reached;
+
+#[cfg(feature = "cpu_stats")]
+use crate::utils::arch::read_cycle_counter;
 
 #[cfg(feature = "system_logger")]
 use {
@@ -14,51 +27,264 @@ use {
     crate::kernel::logging,
 };
 
+/// Release-to-run latency of a Message, i.e. the CPU cycles from a broadcast until a receiver receives it.
+#[cfg(feature = "cpu_stats")]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct LatencyStats {
+    /// The latency of the latest receive.
+    pub last: u32,
+    /// The largest latency of any receive.
+    pub max: u32,
+    /// The number of receives measured.
+    pub count: u32,
+}
+
+#[cfg(feature = "cpu_stats")]
+impl LatencyStats {
+    /// Records a receive `cycles` after the broadcast.
+    fn record(&mut self, cycles: u32) {
+        self.last = cycles;
+        self.max = core::cmp::max(self.max, cycles);
+        self.count = self.count.wrapping_add(1);
+    }
+}
+
 /// Holds metadata corresponding to a single message object.
 pub struct Message<T: Sized + Clone> {
     value: RefCell<T>,
-    pub receivers: BooleanVector,
-    semaphore: Semaphore
+    /// The tasks which receive the message.
+    receivers: RefCell<BooleanVector>,
+    /// The tasks which are released on broadcast.
+    released: RefCell<BooleanVector>,
+    semaphore: Semaphore,
+    /// If true, a broadcast overwrites a value not yet received, else it fails.
+    coalescing: bool,
+    /// The number of broadcasts which overwrote a value not yet received by some receiver.
+    dropped: RefCell<u32>,
+    /// The tasks blocked in `receive_timeout`, they are woken on broadcast.
+    #[cfg(feature = "timer")]
+    waiters: RefCell<BooleanVector>,
+    /// The cycle counter value at the latest broadcast.
+    #[cfg(feature = "cpu_stats")]
+    broadcast_at: Cell<u32>,
+    /// The latency of the receives, see `latency_stats`.
+    #[cfg(feature = "cpu_stats")]
+    latency: Cell<LatencyStats>,
+    /// True once the message is registered with the Kernel, see `register`.
+    registered: Cell<bool>,
+    /// The message registered before this one.
+    next: Cell<Option<&'static dyn RegisteredMessage>>,
 }
 
 impl<T: Sized + Clone> Message<T> {
-    /// Create and initialize new message object
+    /// Create and initialize new message object, in coalescing mode.
     pub const fn new(
         tasks_mask: BooleanVector,
         receivers_mask: BooleanVector,
         value: T,
+    ) -> Self {
+        Self::new_with_mode(tasks_mask, receivers_mask, value, true)
+    }
+
+    /// Create and initialize new message object. In coalescing mode, a broadcast overwrites the value even if
+    /// some receivers have not received it yet, e.g. for a sensor topic whose consumers only want the latest
+    /// value; the overwrites are counted by `dropped`. Otherwise such a broadcast fails.
+    pub const fn new_with_mode(
+        tasks_mask: BooleanVector,
+        receivers_mask: BooleanVector,
+        value: T,
+        coalescing: bool,
     ) -> Self {
         Self {
             value: RefCell::new(value),
-            receivers: receivers_mask,
-            semaphore: Semaphore::new(tasks_mask)
+            receivers: RefCell::new(receivers_mask),
+            released: RefCell::new(tasks_mask),
+            semaphore: Semaphore::new(tasks_mask),
+            coalescing,
+            dropped: RefCell::new(0),
+            #[cfg(feature = "timer")]
+            waiters: RefCell::new(0),
+            #[cfg(feature = "cpu_stats")]
+            broadcast_at: Cell::new(0),
+            #[cfg(feature = "cpu_stats")]
+            latency: Cell::new(LatencyStats { last: 0, max: 0, count: 0 }),
+            registered: Cell::new(false),
+            next: Cell::new(None),
         }
     }
 
+    /// Registers the message with the Kernel, if not yet done; `delete_task` then removes the deleted task
+    /// from the masks of the message. A message is registered on its first use, but unprivileged tasks can
+    /// broadcast it via `syscall::broadcast` only once registered; hence such a message is registered up front.
+    pub fn register(&'static self) {
+        critical_section(|_| {
+            if !self.registered.replace(true) {
+                self.next.set(messages::register(self));
+            }
+        })
+    }
+
     /// Broadcast the message to all reciever tasks
-    pub fn broadcast(&'static self,  msg: Option<T>) {
+    ///
+    /// Each receiver has its own pending flag, which stays set until that receiver calls `receive`.
+    /// Neither other receivers nor subsequent broadcasts clear it; hence a low priority receiver is
+    /// never starved of the latest value, though intermediate values may be overwritten. Only the receivers
+    /// which exist at the time of the broadcast are notified; a receiver task created later misses it.
+    ///
+    /// Returns the number of receivers which had not yet received the previous value, i.e. whose copy
+    /// of it has been overwritten. A non-zero count indicates the producer is outpacing its consumers.
+    /// If the message is not coalescing, `KernelError::BufferOverflow` is returned instead, and nothing is sent.
+    pub fn broadcast(&'static self,  msg: Option<T>) -> Result<u32, KernelError> {
+        self.register();
         critical_section(|_| {
+            let receivers = *self.receivers.borrow() & get_existing_tasks();
+            let pending = *self.semaphore.flags.borrow() & receivers;
+            self.check_overwrite(pending)?;
+            if let Some(msg) = msg {
+                self.value.replace(msg);
+            }
+            #[cfg(feature = "timer")] {
+                wake(self.waiters.replace(0) & receivers);
+            }
+            #[cfg(feature = "cpu_stats")]
+            self.broadcast_at.set(read_cycle_counter());
+            self.semaphore.signal_and_release_to(receivers, *self.released.borrow());
+            #[cfg(feature = "system_logger")] {
+                if logging::get_message_broadcast() {
+                    logging::report(LogEventType::MessageBroadcast(receivers));
+                }
+            }
+            Ok(pending.count_ones())
+        })
+    }
+
+    /// Like `broadcast`, but only the receivers in `target_mask` are notified, and of the tasks released by
+    /// the message only those in `target_mask` are released. Returns `KernelError::AccessDenied` if
+    /// `target_mask` includes tasks which are not receivers of the message, in which case nothing is sent.
+    pub fn broadcast_to(&'static self, msg: Option<T>, target_mask: BooleanVector) -> Result<u32, KernelError> {
+        self.register();
+        critical_section(|_| {
+            if target_mask & !*self.receivers.borrow() != 0 {
+                return Err(KernelError::AccessDenied);
+            }
+            let target_mask = target_mask & get_existing_tasks();
+            let pending = *self.semaphore.flags.borrow() & target_mask;
+            self.check_overwrite(pending)?;
             if let Some(msg) = msg {
                 self.value.replace(msg);
             }
-            self.semaphore.signal_and_release(self.receivers);
+            #[cfg(feature = "cpu_stats")]
+            self.broadcast_at.set(read_cycle_counter());
+            #[cfg(feature = "timer")] {
+                let waiters = *self.waiters.borrow() & target_mask;
+                *self.waiters.borrow_mut() &= !waiters;
+                wake(waiters);
+            }
+            self.semaphore.signal_and_release_to(target_mask, *self.released.borrow() & target_mask);
             #[cfg(feature = "system_logger")] {
                 if logging::get_message_broadcast() {
-                    logging::report(LogEventType::MessageBroadcast(self.receivers));
+                    logging::report(LogEventType::MessageBroadcast(target_mask));
                 }
             }
+            Ok(pending.count_ones())
+        })
+    }
+
+    /// Fails with `KernelError::BufferOverflow` if the receivers in `pending` have not received the current
+    /// value and the message is not coalescing; otherwise counts the overwrite, if any.
+    fn check_overwrite(&self, pending: BooleanVector) -> Result<(), KernelError> {
+        if pending == 0 {
+            return Ok(());
+        }
+        if !self.coalescing {
+            return Err(KernelError::BufferOverflow);
+        }
+        let dropped: &mut u32 = &mut self.dropped.borrow_mut();
+        *dropped = dropped.saturating_add(1);
+        Ok(())
+    }
+
+    /// Returns the number of broadcasts which overwrote a value not yet received by some receiver. A receiver
+    /// which compares it across its receives can tell it fell behind.
+    pub fn dropped(&self) -> u32 {
+        critical_section(|_| *self.dropped.borrow())
+    }
+
+    /// Returns the release-to-run latency of the message, measured at each receive from the latest broadcast.
+    /// With the highest priority receiver receiving first, `max` bounds the event-to-response time of the topic.
+    #[cfg(feature = "cpu_stats")]
+    pub fn latency_stats(&self) -> LatencyStats {
+        critical_section(|_| self.latency.get())
+    }
+
+    #[cfg(feature = "cpu_stats")]
+    fn record_latency(&self) {
+        let mut latency = self.latency.get();
+        latency.record(read_cycle_counter().wrapping_sub(self.broadcast_at.get()));
+        self.latency.set(latency);
+    }
+
+    /// Returns the boolean vector of the tasks which receive the message.
+    pub fn receivers(&self) -> BooleanVector {
+        critical_section(|_| *self.receivers.borrow())
+    }
+
+    /// Adds the task `task` to the receivers of the message, and to the tasks it releases. The task receives
+    /// only the broadcasts made after it subscribes. Returns `KernelError::AccessDenied` if called from an
+    /// unprivileged context.
+    pub fn subscribe(&'static self, task: TaskId) -> Result<(), KernelError> {
+        priv_execute!({
+            self.register();
+            critical_section(|_| {
+                *self.semaphore.flags.borrow_mut() &= !(1 << task);
+                *self.receivers.borrow_mut() |= 1 << task;
+                *self.released.borrow_mut() |= 1 << task;
+                Ok(())
+            })
+        })
+    }
+
+    /// Removes the task `task` from the receivers of the message and from the tasks it releases; a broadcast
+    /// pending for the task is dropped. Returns `KernelError::AccessDenied` if called from an unprivileged context.
+    pub fn unsubscribe(&'static self, task: TaskId) -> Result<(), KernelError> {
+        priv_execute!({
+            self.unregister_receiver(task);
+            Ok(())
         })
     }
 
     /// Get a copy of the messsage on recieving a message
+    ///
+    /// Returns the latest broadcast value if a broadcast is pending for the currently running task,
+    /// and clears only that task's pending flag.
+    ///
+    /// Each broadcast is delivered to each receiver at most once: a second `receive` before the next
+    /// broadcast returns `None`. A receiver task created after a broadcast does not receive it, the same as a
+    /// task which subscribes after it; it receives the broadcasts made from then on.
+    ///
+    /// A borrow of the value must not outlive the call: a broadcast which preempts the receiver replaces the
+    /// value in place, and would panic on the conflict with a `Ref` still held. Hence the value is cloned out,
+    /// as `receive_copy` does; a large payload is better handed over with a `MessageRef`.
     pub fn receive (&'static self) -> Option<T>
+    {
+        self.register();
+        self.receive_copy()
+    }
+
+    /// Like `receive`, but the message is not registered with the Kernel; it is registered by its broadcasts.
+    /// The value is cloned out within a critical section, hence no borrow of it is held once it returns.
+    pub fn receive_copy(&self) -> Option<T>
+    where
+        T: Clone,
     {
         critical_section(|_| {
             match self.semaphore.test_and_reset() {
                 Ok(res) if res == true => {
+                    #[cfg(feature = "cpu_stats")]
+                    self.record_latency();
                     #[cfg(feature = "system_logger")] {
                         if logging::get_message_recieve() {
-                            logging::report(LogEventType::MessageRecieve(get_curr_tid() as u32));
+                            logging::report(LogEventType::MessageRecieve(current_task() as u32));
                         }
                     }
                     Some(self.value.borrow().clone())
@@ -67,6 +293,330 @@ impl<T: Sized + Clone> Message<T> {
             }
         })
     }
+
+    /// Like `receive`, but if no message is pending, the currently running task is blocked until either
+    /// the message is broadcast or `ticks` timer ticks elapse. Returns `None` on timeout, never before the deadline.
+    #[cfg(feature = "timer")]
+    pub fn receive_timeout(&'static self, ticks: u32) -> Option<T> {
+        let curr_tid = current_task();
+        let deadline = get_time().wrapping_add(ticks);
+        loop {
+            let value = critical_section(|_| {
+                if let Some(value) = self.receive() {
+                    return Some(Some(value));
+                }
+                if tick_reached(get_time(), deadline) {
+                    return Some(None);
+                }
+                *self.waiters.borrow_mut() |= 1 << curr_tid;
+                // The context switch is carried out once the critical section ends.
+                sleep_until(deadline);
+                None
+            });
+            if let Some(value) = value {
+                return value;
+            }
+            let value = critical_section(|_| {
+                *self.waiters.borrow_mut() &= !(1 << curr_tid);
+                cancel_sleep(curr_tid);
+                self.receive()
+            });
+            if value.is_some() {
+                return value;
+            }
+        }
+    }
+}
+
+impl<T: Sized + Clone> RegisteredMessage for Message<T> {
+    fn unregister_receiver(&'static self, task: TaskId) {
+        critical_section(|_| {
+            let mask = !(1 << task);
+            *self.receivers.borrow_mut() &= mask;
+            *self.released.borrow_mut() &= mask;
+            *self.semaphore.flags.borrow_mut() &= mask;
+            #[cfg(feature = "timer")]
+            { *self.waiters.borrow_mut() &= mask; }
+        })
+    }
+
+    fn move_receiver(&'static self, from: TaskId, to: TaskId) {
+        let move_bit = |vector: &mut BooleanVector| {
+            if *vector & (1 << from) != 0 {
+                *vector = (*vector & !(1 << from)) | (1 << to);
+            }
+        };
+        critical_section(|_| {
+            move_bit(&mut self.receivers.borrow_mut());
+            move_bit(&mut self.released.borrow_mut());
+            move_bit(&mut self.semaphore.flags.borrow_mut());
+            #[cfg(feature = "timer")]
+            move_bit(&mut self.waiters.borrow_mut());
+        })
+    }
+
+    fn next(&self) -> Option<&'static dyn RegisteredMessage> {
+        self.next.get()
+    }
+
+    fn value_layout(&self) -> Layout {
+        Layout::new::<Option<T>>()
+    }
+
+    unsafe fn broadcast_raw(&'static self, value: usize) -> Result<u32, KernelError> {
+        self.broadcast((*(value as *mut Option<T>)).take())
+    }
 }
 
 unsafe impl<T: Sized + Clone> Sync for Message<T> {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(feature = "cpu_stats")]
+    #[test]
+    fn latency_tracks_last_and_max() {
+        let mut latency = LatencyStats::default();
+        latency.record(120);
+        assert_eq!((latency.last, latency.max, latency.count), (120, 120, 1));
+        latency.record(80);
+        assert_eq!((latency.last, latency.max, latency.count), (80, 120, 2));
+        latency.record(300);
+        assert_eq!((latency.last, latency.max, latency.count), (300, 300, 3));
+    }
+
+    // The tests below rely on preemption, which the `cooperative` feature disables.
+    #[cfg(not(feature = "cooperative"))]
+    use crate::kernel::tasks::{delete_task, drop_privileges, release_tasks, task_exit};
+    #[cfg(not(feature = "cooperative"))]
+    use crate::utils::host::{boot, create, kernel_test, run_isr, take_trace, trace};
+    #[cfg(all(feature = "timer", not(feature = "cooperative")))]
+    use crate::utils::host::tick;
+
+    #[cfg(not(feature = "cooperative"))]
+    #[test]
+    fn copy_is_unaffected_by_a_broadcast() {
+        static MSG: Message<u32> = Message::new(0, 1 << 1, 0);
+        fn task1() -> ! {
+            loop {
+                let copy = MSG.receive_copy();
+                trace(copy.unwrap());
+                // A broadcast preempts the receiver while it still uses the copy.
+                run_isr(|| MSG.broadcast(Some(6)).unwrap());
+                trace(copy.unwrap());
+                trace(MSG.receive_copy().unwrap());
+                // Whereas a `Ref` held across the broadcast would conflict with its replace.
+                let value = MSG.value.borrow();
+                assert!(MSG.value.try_borrow_mut().is_err());
+                drop(value);
+                task_exit();
+            }
+        }
+        let _kernel = kernel_test();
+        boot(&[(1, task1)]);
+        MSG.broadcast(Some(5)).unwrap();
+        release_tasks(1 << 1).unwrap();
+        assert_eq!(take_trace(), [5, 5, 6]);
+    }
+
+    #[cfg(not(feature = "cooperative"))]
+    #[test]
+    fn broadcast_is_received_once() {
+        static MSG: Message<u32> = Message::new(0, 1 << 1, 0);
+        fn task1() -> ! {
+            loop {
+                trace(MSG.receive().unwrap_or(0));
+                trace(MSG.receive().unwrap_or(0));
+                task_exit();
+            }
+        }
+        let _kernel = kernel_test();
+        boot(&[(1, task1)]);
+        MSG.broadcast(Some(3)).unwrap();
+        release_tasks(1 << 1).unwrap();
+        assert_eq!(take_trace(), [3, 0]);
+        release_tasks(1 << 1).unwrap();
+        assert_eq!(take_trace(), [0, 0]);
+    }
+
+    #[cfg(not(feature = "cooperative"))]
+    #[test]
+    fn late_receiver_misses_earlier_broadcast() {
+        static MSG: Message<u32> = Message::new(0, 1 << 1 | 1 << 2, 0);
+        fn task1() -> ! {
+            loop {
+                trace(MSG.receive().unwrap_or(0));
+                task_exit();
+            }
+        }
+        fn task2() -> ! {
+            loop {
+                trace(100 + MSG.receive().unwrap_or(0));
+                task_exit();
+            }
+        }
+        let _kernel = kernel_test();
+        boot(&[(1, task1)]);
+        MSG.broadcast(Some(4)).unwrap();
+        create(2, task2);
+        release_tasks(1 << 1 | 1 << 2).unwrap();
+        assert_eq!(take_trace(), [100, 4]);
+        MSG.broadcast(Some(5)).unwrap();
+        release_tasks(1 << 1 | 1 << 2).unwrap();
+        assert_eq!(take_trace(), [105, 5]);
+    }
+
+    #[cfg(not(feature = "cooperative"))]
+    #[test]
+    fn broadcast_counts_the_receivers_left_behind() {
+        static MSG: Message<u32> = Message::new(0, 1 << 1 | 1 << 2, 0);
+        fn receiver() -> ! {
+            loop {
+                trace(MSG.receive().unwrap_or(0));
+                task_exit();
+            }
+        }
+        let _kernel = kernel_test();
+        boot(&[(1, receiver), (2, receiver)]);
+        assert_eq!(MSG.broadcast(Some(1)).unwrap(), 0);
+        // Neither receiver has received the first value.
+        assert_eq!(MSG.broadcast(Some(2)).unwrap(), 2);
+        release_tasks(1 << 1).unwrap();
+        assert_eq!(take_trace(), [2]);
+        assert_eq!(MSG.broadcast(Some(3)).unwrap(), 1);
+    }
+
+    #[cfg(not(feature = "cooperative"))]
+    #[test]
+    fn overwrite_fails_unless_coalescing() {
+        static LATEST: Message<u32> = Message::new(0, 1 << 1, 0);
+        static EVERY: Message<u32> = Message::new_with_mode(0, 1 << 1, 0, false);
+        fn receiver() -> ! {
+            loop {
+                trace(EVERY.receive().unwrap_or(0));
+                trace(LATEST.receive().unwrap_or(0));
+                task_exit();
+            }
+        }
+        let _kernel = kernel_test();
+        boot(&[(1, receiver)]);
+        LATEST.broadcast(Some(1)).unwrap();
+        assert_eq!(LATEST.broadcast(Some(2)).unwrap(), 1);
+        assert_eq!(LATEST.dropped(), 1);
+        EVERY.broadcast(Some(1)).unwrap();
+        assert!(matches!(EVERY.broadcast(Some(2)), Err(KernelError::BufferOverflow)));
+        assert_eq!(EVERY.dropped(), 0);
+        release_tasks(1 << 1).unwrap();
+        assert_eq!(take_trace(), [1, 2]);
+        // Once received, the value can be replaced.
+        assert_eq!(EVERY.broadcast(Some(3)).unwrap(), 0);
+    }
+
+    #[cfg(not(feature = "cooperative"))]
+    #[test]
+    fn broadcast_to_notifies_only_the_targets() {
+        static MSG: Message<u32> = Message::new(0b1110, 0b1110, 0);
+        fn receiver() -> ! {
+            loop {
+                trace(current_task() * 10 + MSG.receive().unwrap_or(0));
+                task_exit();
+            }
+        }
+        let _kernel = kernel_test();
+        boot(&[(1, receiver), (2, receiver), (3, receiver)]);
+        assert!(matches!(MSG.broadcast_to(Some(9), 1 << 2 | 1 << 4), Err(KernelError::AccessDenied)));
+        MSG.broadcast_to(Some(5), 1 << 2).unwrap();
+        assert_eq!(take_trace(), [25]);
+        // The other receivers were neither released nor notified.
+        release_tasks(1 << 1 | 1 << 3).unwrap();
+        assert_eq!(take_trace(), [30, 10]);
+    }
+
+    #[cfg(not(feature = "cooperative"))]
+    #[test]
+    fn deleted_receiver_is_unsubscribed() {
+        static MSG: Message<u32> = Message::new(1 << 1 | 1 << 2, 1 << 1 | 1 << 2, 0);
+        fn receiver() -> ! {
+            loop {
+                trace(current_task() * 10 + MSG.receive().unwrap_or(0));
+                task_exit();
+            }
+        }
+        let _kernel = kernel_test();
+        boot(&[(1, receiver), (2, receiver)]);
+        MSG.register();
+        delete_task(2).unwrap();
+        assert_eq!(MSG.receivers(), 1 << 1);
+        MSG.broadcast(Some(4)).unwrap();
+        assert_eq!(take_trace(), [14]);
+        assert_eq!(*MSG.semaphore.flags.borrow(), 0);
+        // A task created in the slot does not inherit the subscription.
+        create(2, receiver);
+        MSG.broadcast(Some(5)).unwrap();
+        assert_eq!(take_trace(), [15]);
+        release_tasks(1 << 2).unwrap();
+        assert_eq!(take_trace(), [20]);
+    }
+
+    #[cfg(not(feature = "cooperative"))]
+    #[test]
+    fn subscriber_receives_later_broadcasts_only() {
+        static MSG: Message<u32> = Message::new(1 << 1, 1 << 1, 0);
+        fn receiver() -> ! {
+            loop {
+                trace(current_task() * 10 + MSG.receive().unwrap_or(0));
+                task_exit();
+            }
+        }
+        fn unprivileged() -> ! {
+            loop {
+                drop_privileges();
+                assert!(matches!(MSG.subscribe(3), Err(KernelError::AccessDenied)));
+                assert!(matches!(MSG.unsubscribe(1), Err(KernelError::AccessDenied)));
+                task_exit();
+            }
+        }
+        let _kernel = kernel_test();
+        boot(&[(1, receiver), (2, receiver), (3, unprivileged)]);
+        MSG.broadcast(Some(1)).unwrap();
+        assert_eq!(take_trace(), [11]);
+        MSG.subscribe(2).unwrap();
+        release_tasks(1 << 2).unwrap();
+        assert_eq!(take_trace(), [20]);
+        MSG.broadcast(Some(2)).unwrap();
+        assert_eq!(take_trace(), [22, 12]);
+        MSG.unsubscribe(1).unwrap();
+        MSG.broadcast(Some(3)).unwrap();
+        assert_eq!(take_trace(), [23]);
+        release_tasks(1 << 3).unwrap();
+        assert_eq!(MSG.receivers(), 1 << 2);
+    }
+
+    #[cfg(all(feature = "timer", not(feature = "cooperative")))]
+    #[test]
+    fn receive_timeout_is_woken_or_times_out() {
+        static MSG: Message<u32> = Message::new(0, 1 << 1 | 1 << 2, 0);
+        fn impatient() -> ! {
+            loop {
+                trace(MSG.receive_timeout(2).map_or(10, |value| 10 + value));
+                task_exit();
+            }
+        }
+        fn patient() -> ! {
+            loop {
+                trace(MSG.receive_timeout(5).map_or(20, |value| 20 + value));
+                task_exit();
+            }
+        }
+        let _kernel = kernel_test();
+        boot(&[(1, impatient), (2, patient)]);
+        release_tasks(1 << 1 | 1 << 2).unwrap();
+        assert_eq!(take_trace(), []);
+        tick(2);
+        // The deadline of the task 1 passes before the broadcast.
+        assert_eq!(take_trace(), [10]);
+        run_isr(|| MSG.broadcast(Some(7)).unwrap());
+        assert_eq!(take_trace(), [27]);
+    }
+}
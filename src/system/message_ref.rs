@@ -0,0 +1,57 @@
+//! # Zero-copy Message
+//!
+use core::cell::RefCell;
+
+use crate::system::scheduler::{task_bit, BooleanVector};
+use crate::kernel::tasks::{current_task, release, schedule};
+use crate::utils::arch::critical_section;
+
+/// A single-slot channel which transfers ownership of a `&'static mut T` (e.g. a DMA buffer) to exactly
+/// one receiver, instead of copying the value like `Message`.
+///
+/// The handoff protocol: `send` moves the reference into the channel, hence the sender can no longer
+/// access the buffer. The first receiver to call `receive` takes it out; the other receivers get `None`.
+/// Once done, the receiver hands the buffer back to its owner, e.g. through a second `MessageRef` used
+/// as the free list of a buffer pool.
+pub struct MessageRef<T: 'static> {
+    slot: RefCell<Option<&'static mut T>>,
+    /// A boolean vector of the tasks which may receive the buffer, they are released on `send`.
+    pub receivers: BooleanVector,
+}
+
+impl<T: 'static> MessageRef<T> {
+    /// Create and initialize new empty MessageRef object
+    pub const fn new(receivers_mask: BooleanVector) -> Self {
+        Self {
+            slot: RefCell::new(None),
+            receivers: receivers_mask,
+        }
+    }
+
+    /// Moves `buffer` into the channel and releases the receivers. If the channel still holds a buffer
+    /// which has not been received, `buffer` is handed back as the error.
+    pub fn send(&'static self, buffer: &'static mut T) -> Result<(), &'static mut T> {
+        critical_section(|_| {
+            let slot = &mut self.slot.borrow_mut();
+            if slot.is_some() {
+                return Err(buffer);
+            }
+            **slot = Some(buffer);
+            release(self.receivers);
+            schedule();
+            Ok(())
+        })
+    }
+
+    /// Takes the buffer out of the channel, if the currently running task is a receiver and a buffer is pending.
+    pub fn receive(&'static self) -> Option<&'static mut T> {
+        critical_section(|_| {
+            if self.receivers & task_bit(current_task() as usize) == 0 {
+                return None;
+            }
+            self.slot.borrow_mut().take()
+        })
+    }
+}
+
+unsafe impl<T: 'static> Sync for MessageRef<T> {}
@@ -1,9 +1,15 @@
 //! Kernel Data-structures. `Private`
 
 pub mod resource;
+pub mod rw_resource;
+pub mod pi_resource;
 pub mod message;
+pub mod message_ref;
+pub mod queue;
 pub mod semaphore;
+pub mod counting_semaphore;
 pub mod scheduler;
+pub mod deferred;
 mod pi_stack;
 
 #[cfg(any(feature = "events_32", feature = "events_16", feature = "events_64"))]
@@ -12,5 +18,32 @@ pub mod event;
 #[cfg(feature="system_logger")]
 pub mod system_logger;
 
+#[cfg(feature="system_logger")]
+pub mod log_sink;
+
 #[cfg(feature="task_monitor")]
-pub mod task_monitor;
\ No newline at end of file
+pub mod task_monitor;
+
+#[cfg(feature="timer")]
+pub mod sleep_table;
+
+#[cfg(feature="timer")]
+pub mod binary_semaphore;
+
+#[cfg(feature="timer")]
+pub mod event_group;
+
+#[cfg(feature="timer")]
+pub mod rpc_channel;
+
+#[cfg(feature="timer")]
+pub mod pool;
+
+#[cfg(feature="timer")]
+pub mod soft_timer;
+
+#[cfg(feature="timer")]
+pub mod watchdog;
+
+#[cfg(feature="tickless")]
+pub mod tickless;
\ No newline at end of file
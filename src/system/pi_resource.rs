@@ -0,0 +1,137 @@
+//! # Priority Inheritance Resource
+//!
+//! Defines a Resource which bounds priority inversion by priority inheritance instead of the Stack Resource Policy.
+use core::cell::{RefCell, UnsafeCell};
+
+use crate::KernelError;
+use crate::kernel::tasks::{current_task, pi_wait, pi_wake, schedule};
+use crate::system::scheduler::{BooleanVector, TaskId};
+use crate::utils::arch::critical_section;
+
+/// A Resource which does not need to know its tasks in advance. A task which finds it locked waits,
+/// and the task holding it inherits the waiter's priority: the scheduler dispatches the holder in place
+/// of the waiter, hence tasks of an intermediate priority can't preempt the holder. The holder's own
+/// priority is restored when it unlocks the resource.
+/// Unlike `Resource`, it is not deadlock-free; tasks must lock nested `PiResource`s in a consistent order.
+pub struct PiResource<T: Sized> {
+    /// The TaskId of the task holding the resource.
+    owner: RefCell<Option<TaskId>>,
+    /// A boolean vector of the tasks waiting for the resource.
+    waiters: RefCell<BooleanVector>,
+    inner: UnsafeCell<T>,
+}
+
+impl<T: Sized> PiResource<T> {
+
+    /// Create and initialize new PiResource object.
+    pub const fn new(val: T) -> Self {
+        Self {
+            owner: RefCell::new(None),
+            waiters: RefCell::new(0),
+            inner: UnsafeCell::new(val),
+        }
+    }
+
+    /// Locks the resource for the currently running task, waiting while another task holds it.
+    /// Returns `KernelError::AccessDenied` if the currently running task already holds it.
+    fn lock(&self) -> Result<&mut T, KernelError> {
+        let curr_tid = current_task() as TaskId;
+        loop {
+            let locked = critical_section(|_| {
+                let owner: &mut Option<TaskId> = &mut self.owner.borrow_mut();
+                match *owner {
+                    None => {
+                        *owner = Some(curr_tid);
+                        Ok(true)
+                    },
+                    Some(holder) if holder == curr_tid => Err(KernelError::AccessDenied),
+                    Some(holder) => {
+                        *self.waiters.borrow_mut() |= 1 << curr_tid;
+                        pi_wait(holder);
+                        // The context switch is carried out once the critical section ends.
+                        schedule();
+                        Ok(false)
+                    },
+                }
+            })?;
+            if locked {
+                return Ok(unsafe { &mut *self.inner.get() });
+            }
+        }
+    }
+
+    /// Unlocks the resource and hands the CPU back to the waiters, if they have a higher priority.
+    fn unlock(&self) {
+        critical_section(|_| {
+            self.owner.replace(None);
+            pi_wake(self.waiters.replace(0));
+            schedule();
+        })
+    }
+
+    /// Executes `handler` with exclusive access to the resource. If another task holds it, the currently
+    /// running task waits until it is unlocked; the holder runs with the priority of the waiter meanwhile.
+    /// Waiting relies on preemptive scheduling.
+    pub fn acquire<F, R>(&self, handler: F) -> Result<R, KernelError>
+    where
+        F: FnOnce(&mut T) -> R,
+    {
+        let value = self.lock()?;
+        let res = handler(value);
+        self.unlock();
+        Ok(res)
+    }
+
+    /// Returns the TaskId of the task holding the resource, if any.
+    pub fn holder(&self) -> Option<TaskId> {
+        critical_section(|_| *self.owner.borrow())
+    }
+}
+
+unsafe impl<T> Sync for PiResource<T> {}
+
+// The tests rely on preemption, which the `cooperative` feature disables.
+#[cfg(all(test, not(feature = "cooperative")))]
+mod tests {
+    use super::*;
+    use crate::kernel::tasks::{release_tasks, task_exit};
+    use crate::utils::host::{boot, kernel_test, take_trace, trace};
+
+    static SHARED: PiResource<u32> = PiResource::new(0);
+
+    #[test]
+    fn boosted_holder_is_not_preempted_by_a_medium_task() {
+        fn low() -> ! {
+            loop {
+                SHARED.acquire(|value| {
+                    trace(1);
+                    release_tasks(1 << 3).unwrap();
+                    // The high priority task waits for the resource, hence this task inherits its priority.
+                    trace(11);
+                    release_tasks(1 << 2).unwrap();
+                    trace(12);
+                    *value = 3;
+                }).unwrap();
+                trace(13);
+                task_exit();
+            }
+        }
+        fn medium() -> ! {
+            loop {
+                trace(2);
+                task_exit();
+            }
+        }
+        fn high() -> ! {
+            loop {
+                SHARED.acquire(|value| trace(*value)).unwrap();
+                task_exit();
+            }
+        }
+        let _kernel = kernel_test();
+        boot(&[(1, low), (2, medium), (3, high)]);
+        release_tasks(1 << 1).unwrap();
+        assert_eq!(take_trace(), [1, 11, 12, 3, 2, 13]);
+        assert_eq!(SHARED.holder(), None);
+    }
+}
@@ -3,30 +3,55 @@
 //! The Resource manager handles the details of which processes have access to the which resource
 //! and implements the locking and unlocking mechanism.
 
-use crate::config::MAX_RESOURCES;
+use crate::config::MAX_TASKS;
 use crate::KernelError;
 use crate::system::scheduler::TaskId;
 
-const PI: i32 = -1;
-
+/// Stack of the ceilings of the currently locked resources. The stack is empty when no resource
+/// is locked, in which case `system_ceiling` is `PiStack::EMPTY_CEILING`.
+///
+/// As the ceilings on the stack are strictly increasing and a ceiling is a TaskId, at most `MAX_TASKS`
+/// resources can be locked in a nested manner, one per distinct ceiling; the stack is sized to hold all of them.
 pub struct PiStack {
     /// Points the top of the `pi_stack`.
     top: usize,
     /// This stack is used for locking and unlocking of resources.
-    pi_stack: [i32; MAX_RESOURCES],
+    pi_stack: [i32; MAX_TASKS + 1],
     /// Hold the ceiling of the resource with the highest ceiling amongst the currently locked resources.
     pub system_ceiling: i32,
 }
 
 impl PiStack {
+    /// The `system_ceiling` when no resource is locked. It is lower than the ceiling of any resource,
+    /// hence any resource can be locked on an empty stack.
+    pub const EMPTY_CEILING: i32 = -1;
+
+    /// The maximum number of nested locks, one per distinct ceiling. The first slot of the stack holds `EMPTY_CEILING`.
+    pub const CAPACITY: usize = MAX_TASKS;
+
     pub const fn new() -> Self {
         Self {
             top: 0,
-            pi_stack: [PI; MAX_RESOURCES],
-            system_ceiling: PI,
+            pi_stack: [Self::EMPTY_CEILING; MAX_TASKS + 1],
+            system_ceiling: Self::EMPTY_CEILING,
         }
     }
 
+    /// Returns the number of resources currently locked.
+    pub fn depth(&self) -> usize {
+        self.top
+    }
+
+    /// Returns true if a resource with the ceiling `ceiling` is locked.
+    pub fn contains(&self, ceiling: TaskId) -> bool {
+        self.pi_stack[1..=self.top].iter().any(|&locked| locked == ceiling as i32)
+    }
+
+    /// Returns true if no resource is locked.
+    pub fn is_empty(&self) -> bool {
+        self.top == 0
+    }
+
     /// Pops the stack top and assigns the `system_ceiling` to the new stack top.
     pub fn pop_stack(&mut self) -> Result<(),KernelError> {
         if self.top == 0 {
@@ -37,14 +62,55 @@ impl PiStack {
         Ok(())
     }
 
-    /// Pushes the passed ceiling onto the pi_stack.
+    /// Pushes the passed ceiling onto the pi_stack. Ceilings must be pushed in strictly increasing order,
+    /// else `KernelError::CeilingViolation` is returned and the stack is left untouched. Returns
+    /// `KernelError::PiStackOverflow` if `PiStack::CAPACITY` resources are already locked, which only a ceiling
+    /// at or above `MAX_TASKS` can cause.
     pub fn push_stack(&mut self, ceiling: TaskId) -> Result<(),KernelError> {
-        self.top += 1;
-        if self.top >= MAX_RESOURCES {
-            return Err(KernelError::LimitExceeded)
+        if ceiling as i32 <= self.system_ceiling {
+            return Err(KernelError::CeilingViolation)
+        }
+        if self.top >= Self::CAPACITY {
+            return Err(KernelError::PiStackOverflow)
         }
+        self.top += 1;
         self.pi_stack[self.top] = ceiling as i32;
         self.system_ceiling = ceiling as i32;
         Ok(())
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn push_to_capacity_and_pop_back() {
+        let mut stack = PiStack::new();
+        for ceiling in 0..PiStack::CAPACITY {
+            stack.push_stack(ceiling as TaskId).unwrap();
+            assert_eq!(stack.system_ceiling, ceiling as i32);
+        }
+        assert_eq!(stack.depth(), MAX_TASKS);
+        assert!(stack.contains(0) && stack.contains(MAX_TASKS as TaskId - 1));
+        assert!(matches!(stack.push_stack(MAX_TASKS as TaskId), Err(KernelError::PiStackOverflow)));
+        for ceiling in (0..PiStack::CAPACITY).rev() {
+            assert_eq!(stack.system_ceiling, ceiling as i32);
+            stack.pop_stack().unwrap();
+        }
+        assert!(stack.is_empty());
+        assert_eq!(stack.system_ceiling, PiStack::EMPTY_CEILING);
+        assert!(matches!(stack.pop_stack(), Err(KernelError::Empty)));
+    }
+
+    #[test]
+    fn ceilings_must_increase() {
+        let mut stack = PiStack::new();
+        stack.push_stack(3).unwrap();
+        assert!(matches!(stack.push_stack(3), Err(KernelError::CeilingViolation)));
+        assert!(matches!(stack.push_stack(2), Err(KernelError::CeilingViolation)));
+        assert_eq!(stack.depth(), 1);
+        assert_eq!(stack.system_ceiling, 3);
+        assert!(!stack.contains(2));
+    }
+}
@@ -0,0 +1,188 @@
+//! # Pool
+//!
+//! Defines a fixed set of pre-allocated buffers which tasks borrow and return.
+use core::cell::{RefCell, UnsafeCell};
+use core::ops::{Deref, DerefMut};
+
+use crate::KernelError;
+use crate::system::counting_semaphore::CountingSemaphore;
+use crate::utils::arch::critical_section;
+
+/// A pool of `N` pre-allocated buffers of type `T`. The number of free buffers is tracked by a counting
+/// semaphore, hence a task acquiring from an exhausted pool blocks until a buffer is returned rather than
+/// failing. At most 32 buffers are supported.
+pub struct Pool<T: Sized, const N: usize> {
+    /// The buffers, a buffer is only accessed through the guard which acquired it.
+    buffers: UnsafeCell<[T; N]>,
+    /// A bit vector of the buffers not handed out.
+    free: RefCell<u32>,
+    /// Counts the free buffers, tasks wait on it when the pool is exhausted.
+    available: CountingSemaphore,
+}
+
+impl<T: Sized, const N: usize> Pool<T, N> {
+    /// Initializes a new pool holding `buffers`.
+    pub const fn new(buffers: [T; N]) -> Self {
+        [()][(N == 0 || N > 32) as usize];
+        Self {
+            buffers: UnsafeCell::new(buffers),
+            free: RefCell::new(if N == 32 { u32::MAX } else { (1 << N) - 1 }),
+            available: CountingSemaphore::new(N as u32, N as u32),
+        }
+    }
+
+    /// Acquires a buffer from the pool. If none is free, the currently running task is blocked until
+    /// either one is returned or `timeout_ticks` timer ticks elapse, in which case `KernelError::TimedOut`
+    /// is returned. The buffer is returned to the pool when the guard is dropped.
+    pub fn acquire(&'static self, timeout_ticks: u32) -> Result<PoolGuard<T>, KernelError> {
+        self.available.wait_timeout(timeout_ticks)?;
+        Ok(self.take())
+    }
+
+    /// Acquires a buffer from the pool if one is free, without blocking.
+    pub fn try_acquire(&'static self) -> Option<PoolGuard<T>> {
+        if self.available.try_wait() {
+            return Some(self.take());
+        }
+        None
+    }
+
+    /// Returns the number of free buffers.
+    pub fn available(&'static self) -> usize {
+        self.available.count() as usize
+    }
+
+    /// Hands out a free buffer, the caller must hold a unit of `available`.
+    fn take(&'static self) -> PoolGuard<T> {
+        let index = critical_section(|_| {
+            let free: &mut u32 = &mut self.free.borrow_mut();
+            let index = free.trailing_zeros() as usize;
+            *free &= !(1 << index);
+            index
+        });
+        // The buffer was cleared from `free`, hence no other guard refers to it.
+        let buffer = unsafe { &mut *(self.buffers.get() as *mut T).add(index) };
+        PoolGuard {
+            buffer,
+            index,
+            free: &self.free,
+            available: &self.available,
+        }
+    }
+}
+
+unsafe impl<T: Sized + Send, const N: usize> Sync for Pool<T, N> {}
+
+/// A buffer acquired from a Pool, which is returned to the pool when the guard is dropped.
+pub struct PoolGuard<T: Sized + 'static> {
+    buffer: &'static mut T,
+    index: usize,
+    free: &'static RefCell<u32>,
+    available: &'static CountingSemaphore,
+}
+
+impl<T: Sized> Deref for PoolGuard<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        self.buffer
+    }
+}
+
+impl<T: Sized> DerefMut for PoolGuard<T> {
+    fn deref_mut(&mut self) -> &mut T {
+        self.buffer
+    }
+}
+
+impl<T: Sized> Drop for PoolGuard<T> {
+    fn drop(&mut self) {
+        critical_section(|_| {
+            *self.free.borrow_mut() |= 1 << self.index;
+        });
+        // Hands the buffer to the highest priority waiter, if any.
+        self.available.signal();
+    }
+}
+
+// The tests rely on preemption, which the `cooperative` feature disables.
+#[cfg(all(test, not(feature = "cooperative")))]
+mod tests {
+    use super::*;
+    use crate::kernel::tasks::{release_tasks, task_exit};
+    use crate::utils::host::{boot, kernel_test, take_trace, tick, trace};
+
+    #[test]
+    fn buffer_returns_to_pool_on_drop() {
+        static POOL: Pool<u32, 2> = Pool::new([10, 20]);
+        let _kernel = kernel_test();
+        {
+            let mut first = POOL.try_acquire().unwrap();
+            let _second = POOL.try_acquire().unwrap();
+            assert_eq!(POOL.available(), 0);
+            assert!(POOL.try_acquire().is_none());
+            *first += 1;
+            drop(first);
+            assert_eq!(POOL.available(), 1);
+            assert_eq!(*POOL.try_acquire().unwrap(), 11);
+        }
+        assert_eq!(POOL.available(), 2);
+    }
+
+    static SHARED: Pool<u32, 2> = Pool::new([10, 20]);
+
+    #[test]
+    fn exhausted_pool_blocks_until_a_buffer_is_returned() {
+        fn holder() -> ! {
+            loop {
+                let first = SHARED.try_acquire().unwrap();
+                let second = SHARED.try_acquire().unwrap();
+                trace(1);
+                release_tasks(1 << 2).unwrap();
+                trace(11);
+                drop(first);
+                trace(12);
+                drop(second);
+                task_exit();
+            }
+        }
+        fn waiter() -> ! {
+            loop {
+                assert!(SHARED.try_acquire().is_none());
+                trace(2);
+                let buffer = SHARED.acquire(10).unwrap();
+                trace(*buffer);
+                drop(buffer);
+                task_exit();
+            }
+        }
+        let _kernel = kernel_test();
+        boot(&[(1, holder), (2, waiter)]);
+        release_tasks(1 << 1).unwrap();
+        assert_eq!(take_trace(), [1, 2, 11, 10, 12]);
+        assert_eq!(SHARED.available(), 2);
+    }
+
+    static EXHAUSTED: Pool<u32, 1> = Pool::new([10]);
+
+    #[test]
+    fn acquire_times_out_on_exhausted_pool() {
+        fn waiter() -> ! {
+            loop {
+                trace(1);
+                assert!(matches!(EXHAUSTED.acquire(2), Err(KernelError::TimedOut)));
+                trace(2);
+                task_exit();
+            }
+        }
+        let _kernel = kernel_test();
+        boot(&[(1, waiter)]);
+        let _buffer = EXHAUSTED.try_acquire().unwrap();
+        release_tasks(1 << 1).unwrap();
+        assert_eq!(take_trace(), [1]);
+        tick(1);
+        assert_eq!(take_trace(), []);
+        tick(1);
+        assert_eq!(take_trace(), [2]);
+    }
+}
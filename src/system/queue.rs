@@ -0,0 +1,130 @@
+//! Queue primitive
+//!
+
+use core::cell::RefCell;
+
+use crate::KernelError;
+use crate::kernel::tasks::{release, schedule};
+use crate::system::scheduler::BooleanVector;
+use crate::utils::arch::critical_section;
+
+/// Ring buffer holding the items of a queue.
+struct RingBuffer<T: Copy, const N: usize> {
+    items: [Option<T>; N],
+    /// Points to the oldest item.
+    start: usize,
+    /// Number of items in the buffer.
+    len: usize,
+}
+
+impl<T: Copy, const N: usize> RingBuffer<T, N> {
+    const fn new() -> Self {
+        Self {
+            items: [None; N],
+            start: 0,
+            len: 0,
+        }
+    }
+
+    /// Appends an item after the newest one, returns `KernelError::BufferOverflow` if the buffer is full.
+    fn push(&mut self, item: T) -> Result<(), KernelError> {
+        if self.len == N {
+            return Err(KernelError::BufferOverflow);
+        }
+        let end = (self.start + self.len) % N;
+        self.items[end] = Some(item);
+        self.len += 1;
+        Ok(())
+    }
+
+    /// Removes and returns the oldest item.
+    fn pop(&mut self) -> Option<T> {
+        if self.len == 0 {
+            return None;
+        }
+        let item = self.items[self.start].take();
+        self.start = (self.start + 1) % N;
+        self.len -= 1;
+        item
+    }
+}
+
+/// A bounded FIFO queue of `N` items. Unlike `Message`, which holds only the latest value,
+/// items are not overwritten until they have been received.
+pub struct Queue<T: Copy, const N: usize> {
+    buffer: RefCell<RingBuffer<T, N>>,
+    /// The tasks released whenever an item is sent.
+    pub receivers: BooleanVector,
+}
+
+impl<T: Copy, const N: usize> Queue<T, N> {
+    /// Create and initialize new queue object
+    pub const fn new(receivers_mask: BooleanVector) -> Self {
+        Self {
+            buffer: RefCell::new(RingBuffer::new()),
+            receivers: receivers_mask,
+        }
+    }
+
+    /// Appends an item to the queue and releases the receivers.
+    /// Returns `KernelError::BufferOverflow` if the queue is full.
+    pub fn send(&'static self, item: T) -> Result<(), KernelError> {
+        critical_section(|_| self.buffer.borrow_mut().push(item))?;
+        release(self.receivers);
+        schedule();
+        Ok(())
+    }
+
+    /// Removes and returns the oldest item in the queue.
+    pub fn recv(&'static self) -> Option<T> {
+        critical_section(|_| self.buffer.borrow_mut().pop())
+    }
+
+    /// Returns the number of items in the queue.
+    pub fn len(&'static self) -> usize {
+        critical_section(|_| self.buffer.borrow().len)
+    }
+}
+
+unsafe impl<T: Copy, const N: usize> Sync for Queue<T, N> {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fifo_until_full_and_empty() {
+        let mut buffer: RingBuffer<u32, 3> = RingBuffer::new();
+        assert_eq!(buffer.pop(), None);
+        for item in 1..=3 {
+            buffer.push(item).unwrap();
+        }
+        assert!(matches!(buffer.push(4), Err(KernelError::BufferOverflow)));
+        assert_eq!(buffer.len, 3);
+        for item in 1..=3 {
+            assert_eq!(buffer.pop(), Some(item));
+        }
+        assert_eq!(buffer.pop(), None);
+        assert_eq!(buffer.len, 0);
+    }
+
+    #[test]
+    fn wraps_around() {
+        let mut buffer: RingBuffer<u32, 3> = RingBuffer::new();
+        for item in 0..10 {
+            buffer.push(item).unwrap();
+            buffer.push(item + 100).unwrap();
+            assert_eq!(buffer.pop(), Some(item));
+            assert_eq!(buffer.pop(), Some(item + 100));
+        }
+        buffer.push(1).unwrap();
+        buffer.push(2).unwrap();
+        assert_eq!(buffer.pop(), Some(1));
+        buffer.push(3).unwrap();
+        buffer.push(4).unwrap();
+        assert_eq!(buffer.len, 3);
+        assert_eq!(buffer.pop(), Some(2));
+        assert_eq!(buffer.pop(), Some(3));
+        assert_eq!(buffer.pop(), Some(4));
+    }
+}
@@ -1,14 +1,22 @@
 //! # Resource Management Module
 //!
 //! Defines the Kernel routines and primitives for resource management.
-use core::cell::{RefCell};
+use core::any::Any;
+use core::cell::{Cell, UnsafeCell};
+use core::ops::{Deref, DerefMut};
 
-use crate::utils::arch::{Mutex, critical_section};
+use crate::config::MAX_TASKS;
+use crate::utils::arch::{KernelCell, critical_section, in_interrupt};
 use crate::utils::helpers::get_msb_const;
 use crate::system::pi_stack::PiStack;
 use crate::KernelError;
-use crate::kernel::tasks::{block_tasks, get_curr_tid, schedule, unblock_tasks};
-use crate::system::scheduler::{TaskId, BooleanVector};
+use crate::kernel::tasks::{block_tasks, current_task, is_reschedule_needed, schedule, unblock_tasks, task_yield};
+
+#[cfg(feature = "cpu_stats")]
+use crate::kernel::tasks::get_active_tasks;
+#[cfg(feature = "cpu_stats")]
+use crate::utils::arch::read_cycle_counter;
+use crate::system::scheduler::{task_bit, TaskId, BooleanVector, VECTOR_BITS};
 
 #[cfg(feature = "system_logger")]
 use {
@@ -17,10 +25,115 @@ use {
 };
 
 /// Global instance of Resource manager
-static PiStackGlobal: Mutex<RefCell<PiStack>> = Mutex::new(RefCell::new(PiStack::new()));
+static PiStackGlobal: KernelCell<PiStack> = KernelCell::new(PiStack::new());
+
+/// Returns true if no resource is currently locked, i.e. the system ceiling is `PiStack::EMPTY_CEILING`.
+pub fn system_ceiling_is_empty() -> bool {
+    PiStackGlobal.with_ref(|pi_stack| pi_stack.is_empty()).unwrap_or(false)
+}
+
+/// Returns the current system ceiling, i.e. the ceiling of the most recently locked resource, or
+/// `PiStack::EMPTY_CEILING` (-1) if no resource is locked.
+pub fn system_ceiling() -> i32 {
+    PiStackGlobal.with_ref(|pi_stack| pi_stack.system_ceiling).unwrap_or(PiStack::EMPTY_CEILING)
+}
+
+/// Returns true if a resource with the ceiling `ceiling` is currently locked.
+pub fn is_locked(ceiling: TaskId) -> bool {
+    PiStackGlobal.with_ref(|pi_stack| pi_stack.contains(ceiling)).unwrap_or(false)
+}
+
+/// Returns the number of resources currently locked, i.e. the depth of the `PiStack`.
+pub fn lock_depth() -> usize {
+    PiStackGlobal.with_ref(|pi_stack| pi_stack.depth()).unwrap_or(0)
+}
+
+/// Returns the ceiling of a resource accessed by the tasks in `tasks_mask`, i.e. the priority of the highest
+/// priority task among them. The ceiling is never below 0, which is still above `PiStack::EMPTY_CEILING`.
+/// The idle task has no bit in a task mask, hence it never locks a resource.
+pub(crate) const fn ceiling_of(tasks_mask: BooleanVector) -> TaskId {
+    match get_msb_const(tasks_mask) {
+        Some(msb) => msb as TaskId,
+        None => 0,
+    }
+}
+
+/// Compile-time checks of the ceilings of a resource accessed by no task, by the task 0 alone, and of a shared resource.
+const _: () = [()][(ceiling_of(0) != 0 || ceiling_of(1) != 0 || ceiling_of(0b10_0110) != 5) as usize];
+
+/// Returns the `Pi_mask`, which is just a boolean vector with all bits up to ceiling (including) set to 1.
+pub(crate) fn get_pi_mask(ceiling: TaskId) -> BooleanVector {
+    let mask;
+    if (ceiling as usize) < VECTOR_BITS - 1 {
+            mask = (1 << (ceiling + 1)) - 1;
+    } else {
+        mask = !0
+    }
+    mask
+}
+
+/// Raises the system ceiling to `ceiling` and blocks the tasks up to it, except the currently running task.
+pub(crate) fn lock_ceiling(ceiling: TaskId) -> Result<(), KernelError> {
+    if in_interrupt() {
+        return Err(KernelError::NotInTaskContext);
+    }
+    critical_section(|_| {
+        PiStackGlobal.with(|pi_stack| pi_stack.push_stack(ceiling))??;
+        let mask = get_pi_mask(ceiling) & !task_bit(current_task() as usize);
+        block_tasks(mask);
+        Ok(())
+    })
+}
+
+/// Restores the system ceiling if it is `ceiling`, unblocks the tasks up to it, and preempts the
+/// currently running task if an unblocked task has a higher priority.
+pub(crate) fn unlock_ceiling(ceiling: TaskId) -> Result<(), KernelError> {
+    PiStackGlobal.with(|pi_stack| {
+        if ceiling as i32 == pi_stack.system_ceiling {
+            pi_stack.pop_stack()?;
+            unblock_tasks(get_pi_mask(ceiling));
+            // Only pend a context switch if an unblocked task now preempts the current task.
+            if is_reschedule_needed() {
+                schedule();
+            }
+        }
+        Ok(())
+    })?
+}
+
+/// Contention statistics of a Resource.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ResourceStats {
+    /// The number of times the Resource has been locked (nested locks by the owner are not counted).
+    pub lock_count: u32,
+    /// CPU cycles for which the Resource was held while a higher priority task which accesses it was
+    /// waiting on it. A lock contributes its whole duration if such a task is waiting when it is unlocked.
+    #[cfg(feature = "cpu_stats")]
+    pub blocked_cycles: u64,
+}
 
 /// A Safe Container to store a resource, it can hold resource of any Generic Type
 /// and allow safe access to it without ending up in Data races or Deadlocks.
+///
+/// Resources can be locked in a nested manner only in strictly increasing order of their ceilings;
+/// locking a resource whose ceiling is not above the current system ceiling returns `KernelError::CeilingViolation`.
+/// The exception is the task which holds the resource, it can lock it again (e.g. from a shared helper);
+/// the resource is unlocked once it has been unlocked as many times as it was locked. It can't lock it again
+/// while it has mutable access to it through a `ResourceGuard` (`KernelError::CeilingViolation`).
+///
+/// ## Sharing a peripheral
+/// A peripheral driver whose methods take `&mut self` (e.g. a SPI bus shared by several tasks) is kept in a
+/// `Resource<Option<Driver>>`, and used through `acquire_mut` or `lock_guard`; no `RefCell` is needed, as the
+/// ceiling lock gives the task exclusive access. The resource starts out empty, since the peripheral is only
+/// taken at runtime, and `main` moves the driver into it before the Kernel starts:
+/// ```ignore
+/// static spi_bus: Resource<Option<SpiBus>> = Resource::new(None, task_mask!(sensor, display));
+///
+/// spi_bus.acquire_mut(|bus| *bus = Some(SpiBus::new(peripherals.SPI1)))?;
+/// // ... then, from the tasks `sensor` and `display`:
+/// spi_bus.acquire_mut(|bus| bus.as_mut().map(|bus| bus.transfer(0x9F)))?;
+/// ```
+/// The value is accessed from several tasks, hence a Resource is only shared if `T` is `Send`.
 #[derive(Debug)]
 pub struct Resource<T: Sized> 
 {
@@ -29,86 +142,654 @@ pub struct Resource<T: Sized>
     /// It holds the priority of the highest priority task that can access that resource.
     tasks_mask: BooleanVector,
     /// This field holds the actual resource that has to be locked.
-    inner: T,
+    inner: UnsafeCell<T>,
+    /// The task which holds the lock on the resource.
+    owner: Cell<Option<TaskId>>,
+    /// The number of times the owner has locked the resource.
+    depth: Cell<u32>,
+    /// True while a `ResourceGuard` gives mutable access to the resource.
+    mut_borrowed: Cell<bool>,
+    /// The number of times the resource has been locked.
+    lock_count: Cell<u32>,
+    /// The cycle counter value when the resource was last locked.
+    #[cfg(feature = "cpu_stats")]
+    locked_at: Cell<u32>,
+    /// CPU cycles for which the resource was held while contended.
+    #[cfg(feature = "cpu_stats")]
+    blocked_cycles: Cell<u64>,
 }
 
 impl<T: Sized> Resource<T> {
     
-    /// Create and initialize new Resource object
+    /// Create and initialize new Resource object. It can be used in `static` initializers; a `tasks_mask` which
+    /// refers to a task at or above `MAX_TASKS` then fails the compilation (and panics if evaluated at runtime,
+    /// use `try_new` to check it at runtime instead).
     pub const fn new(val: T, tasks_mask: BooleanVector) -> Self {
-        let tasks_mask = tasks_mask | 1;
+        [()][(MAX_TASKS < VECTOR_BITS && tasks_mask >> MAX_TASKS != 0) as usize];
         Self { 
-            inner: val,
+            inner: UnsafeCell::new(val),
             tasks_mask: tasks_mask,
-            ceiling: get_msb_const(tasks_mask) as TaskId,
+            ceiling: ceiling_of(tasks_mask),
+            owner: Cell::new(None),
+            depth: Cell::new(0),
+            mut_borrowed: Cell::new(false),
+            lock_count: Cell::new(0),
+            #[cfg(feature = "cpu_stats")]
+            locked_at: Cell::new(0),
+            #[cfg(feature = "cpu_stats")]
+            blocked_cycles: Cell::new(0),
         }
     }
 
-    /// Returns the `Pi_mask`, which is just a boolean vector with all bits up to ceiling (including) set to 1.
-    fn get_pi_mask(ceiling: TaskId) -> u32 {
-        let mask;
-        if ceiling < 31 {
-                mask = (1 << (ceiling + 1)) - 1;
-        } else {
-            mask = 0xffffffff
+    /// Create and initialize new Resource object, validating that every task in `tasks_mask` is below `MAX_TASKS`.
+    /// Returns `KernelError::DoesNotExist` if the mask refers to a nonexistent task slot.
+    pub fn try_new(val: T, tasks_mask: BooleanVector) -> Result<Self, KernelError> {
+        if MAX_TASKS < VECTOR_BITS && tasks_mask >> MAX_TASKS != 0 {
+            return Err(KernelError::DoesNotExist);
         }
-        mask
+        Ok(Self::new(val, tasks_mask))
     }
-    
+
     /// Lock the Resource for the currently running task and blocks the competing tasks 
+    /// Returns `KernelError::NotInTaskContext` if called from an interrupt handler, which would lock the
+    /// resource on behalf of the interrupted task.
     fn lock(&self) -> Result<&T,KernelError> {
-        critical_section(|cs_token| {
-            let pi_stack = &mut PiStackGlobal.borrow(cs_token).borrow_mut();
-            let curr_tid = get_curr_tid() as u32;
+        if in_interrupt() {
+            return Err(KernelError::NotInTaskContext);
+        }
+        critical_section(|_| {
+            let curr_tid = current_task() as u32;
             
-            let ceiling = self.ceiling;
-            let pid_mask = 1 << curr_tid;
-            if self.tasks_mask & pid_mask != pid_mask {
+            let pid_mask = task_bit(curr_tid as usize);
+            if pid_mask == 0 || self.tasks_mask & pid_mask != pid_mask {
                 return Err(KernelError::AccessDenied);
             }
-            if ceiling as i32 > pi_stack.system_ceiling {
-                pi_stack.push_stack(ceiling)?;
-                let mask = Self::get_pi_mask(ceiling) & !(1 << curr_tid);
-                block_tasks(mask);
-                #[cfg(feature = "system_logger")] {
-                    if logging::get_resource_lock() {
-                        logging::report(LogEventType::ResourceLock(curr_tid));
-                    }
+            if self.owner.get() == Some(curr_tid) {
+                // A shared reference can't be handed out while the guard gives mutable access.
+                if self.mut_borrowed.get() {
+                    return Err(KernelError::CeilingViolation);
                 }
-                return Ok(&self.inner);
+                self.depth.set(self.depth.get() + 1);
+                return Ok(unsafe { &*self.inner.get() });
             }
-            return Err(KernelError::AccessDenied);
+            lock_ceiling(self.ceiling)?;
+            self.owner.set(Some(curr_tid));
+            self.depth.set(1);
+            self.lock_count.set(self.lock_count.get().wrapping_add(1));
+            #[cfg(feature = "cpu_stats")]
+            self.locked_at.set(read_cycle_counter());
+            #[cfg(feature = "system_logger")] {
+                if logging::get_resource_lock() {
+                    logging::report(LogEventType::ResourceLock(curr_tid));
+                }
+            }
+            return Ok(unsafe { &*self.inner.get() });
         })
     }
 
+    /// Attempts to lock the Resource for the currently running task without blocking.
+    /// Returns `Ok(None)` if the resource is contended (the system ceiling is not below the resource ceiling),
+    /// in which case the `PiStack` is left untouched, and `KernelError::AccessDenied` if the currently
+    /// running task is not in the tasks mask. On success, the caller must call `unlock` once done.
+    pub fn try_lock(&self) -> Result<Option<&T>,KernelError> {
+        match self.lock() {
+            Ok(value) => Ok(Some(value)),
+            Err(KernelError::CeilingViolation) => Ok(None),
+            Err(err) => Err(err),
+        }
+    }
+
+    /// Locks the Resource for the currently running task and returns a guard which gives access to it.
+    /// The Resource is unlocked when the guard is dropped.
+    /// As the guard gives mutable access, it can't be taken by the task which already holds the Resource
+    /// (`KernelError::CeilingViolation`).
+    pub fn lock_guard(&self) -> Result<ResourceGuard<'_, T>,KernelError> {
+        if self.owner.get() == Some(current_task() as TaskId) {
+            return Err(KernelError::CeilingViolation);
+        }
+        self.lock()?;
+        self.mut_borrowed.set(true);
+        Ok(ResourceGuard { resource: self })
+    }
+
     /// Unlocks the Resource and unblocks the tasks which were blocked during the call to lock
-    fn unlock(&self) -> Result<(),KernelError> {
-        critical_section(|cs_token| {
-            let pi_stack = &mut PiStackGlobal.borrow(cs_token).borrow_mut();
-            if self.ceiling as i32 == pi_stack.system_ceiling {
-                pi_stack.pop_stack()?;
-                let mask = Self::get_pi_mask(self.ceiling);
-                unblock_tasks(mask);
-                schedule();
+    /// Returns `KernelError::NotInTaskContext` if called from an interrupt handler, and `KernelError::AccessDenied`
+    /// unless the currently running task holds the Resource, or while a `ResourceGuard` on it is alive (the guard
+    /// unlocks it when dropped).
+    pub fn unlock(&self) -> Result<(),KernelError> {
+        if in_interrupt() {
+            return Err(KernelError::NotInTaskContext);
+        }
+        critical_section(|_| {
+            if self.owner.get() != Some(current_task() as TaskId) || self.mut_borrowed.get() {
+                return Err(KernelError::AccessDenied);
+            }
+            if self.depth.get() > 1 {
+                self.depth.set(self.depth.get() - 1);
+                return Ok(());
             }
+            #[cfg(feature = "cpu_stats")] {
+                // Tasks above the current task and up to the ceiling only wait because of the lock.
+                let curr_tid = current_task();
+                let contenders = get_active_tasks() & self.tasks_mask & get_pi_mask(self.ceiling) & !get_pi_mask(curr_tid);
+                if contenders != 0 {
+                    let held_cycles = read_cycle_counter().wrapping_sub(self.locked_at.get());
+                    self.blocked_cycles.set(self.blocked_cycles.get() + held_cycles as u64);
+                }
+            }
+            unlock_ceiling(self.ceiling)?;
+            self.owner.set(None);
+            self.depth.set(0);
             #[cfg(feature = "system_logger")] {
                 if logging::get_resource_unlock() {
-                    logging::report(LogEventType::ResourceUnlock(get_curr_tid() as u32));
+                    logging::report(LogEventType::ResourceUnlock(current_task() as u32));
                 }
             }
             Ok(())
         })
     }
+    /// Returns the contention statistics of the Resource.
+    pub fn stats(&self) -> ResourceStats {
+        critical_section(|_| ResourceStats {
+            lock_count: self.lock_count.get(),
+            #[cfg(feature = "cpu_stats")]
+            blocked_cycles: self.blocked_cycles.get(),
+        })
+    }
+
     /// A helper function that ensures that if a resource is locked, it is unlocked.
+    /// The handler is called exactly once; hence it may mutate or move out of its captured state.
     pub fn acquire<F,R>(&self, handler: F) -> Result<R,KernelError>
     where
-        F: Fn(&T) -> R,
+        F: FnOnce(&T) -> R,
     {
         let value = self.lock()?;
         let res = handler(value);
         self.unlock()?;
         return Ok(res);
     }
+
+    /// Like `acquire`, but the handler is given mutable access to the resource. The handler is called exactly
+    /// once; hence it may move out of its captured state, e.g. to move a peripheral into the resource.
+    /// As with `lock_guard`, it can't be called by the task which already holds the Resource
+    /// (`KernelError::CeilingViolation`).
+    pub fn acquire_mut<F,R>(&self, handler: F) -> Result<R,KernelError>
+    where
+        F: FnOnce(&mut T) -> R,
+    {
+        let mut guard = self.lock_guard()?;
+        Ok(handler(&mut *guard))
+    }
+
+    /// Like `acquire`, but while the resource is held by another task, it yields and retries, up to `max_attempts`
+    /// times. Returns `KernelError::TimedOut` if all the attempts fail. Other errors are returned immediately,
+    /// among them `KernelError::CeilingViolation` if the currently running task itself keeps the system ceiling
+    /// at or above the resource ceiling, e.g. by holding a resource with a higher ceiling; no retry would succeed.
+    pub fn acquire_retry<F,R>(&self, max_attempts: usize, handler: F) -> Result<R,KernelError>
+    where
+        F: FnOnce(&T) -> R,
+    {
+        for _ in 0..max_attempts {
+            match self.lock() {
+                Err(KernelError::CeilingViolation) if self.is_held_by_other() => task_yield(),
+                Err(err) => return Err(err),
+                Ok(value) => {
+                    let res = handler(value);
+                    self.unlock()?;
+                    return Ok(res);
+                },
+            }
+        }
+        Err(KernelError::TimedOut)
+    }
+
+    /// Returns true if the resource is held by a task other than the currently running one.
+    fn is_held_by_other(&self) -> bool {
+        critical_section(|_| self.owner.get().map_or(false, |owner| owner != current_task() as TaskId))
+    }
+}
+
+unsafe impl<T: Send> Sync for Resource<T> {}
+
+/// A Resource which can be locked along with others by `acquire_all`.
+pub trait LockableResource {
+    /// The priority of the highest priority task which accesses the resource.
+    fn ceiling(&self) -> TaskId;
+    /// Locks the resource for the currently running task.
+    fn lock_resource(&self) -> Result<(), KernelError>;
+    /// Unlocks the resource.
+    fn unlock_resource(&self) -> Result<(), KernelError>;
+    /// Returns the value of the resource, which can be downcast to its type. Returns
+    /// `KernelError::AccessDenied` unless the currently running task holds the resource, and has no
+    /// `ResourceGuard` on it.
+    fn value(&self) -> Result<&dyn Any, KernelError>;
+}
+
+impl<T: Any> LockableResource for Resource<T> {
+    fn ceiling(&self) -> TaskId {
+        self.ceiling
+    }
+
+    fn lock_resource(&self) -> Result<(), KernelError> {
+        self.lock().map(|_| ())
+    }
+
+    fn unlock_resource(&self) -> Result<(), KernelError> {
+        self.unlock()
+    }
+
+    fn value(&self) -> Result<&dyn Any, KernelError> {
+        if self.owner.get() != Some(current_task() as TaskId) || self.mut_borrowed.get() {
+            return Err(KernelError::AccessDenied);
+        }
+        Ok(unsafe { &*self.inner.get() })
+    }
 }
 
-unsafe impl<T> Sync for Resource<T> {}
\ No newline at end of file
+/// Returns the index of the resource with the lowest (or highest, if `highest`) ceiling among those
+/// whose bit in `candidates` is set.
+fn pick_by_ceiling(resources: &[&dyn LockableResource], candidates: u64, highest: bool) -> Option<usize> {
+    (0..resources.len())
+        .filter(|&i| candidates & (1 << i) != 0)
+        .fold(None, |best: Option<usize>, i| match best {
+            Some(b) if (resources[i].ceiling() > resources[b].ceiling()) != highest => Some(b),
+            _ => Some(i),
+        })
+}
+
+/// Unlocks the resources whose bit in `locked` is set, in decreasing order of their ceilings.
+fn unlock_all(resources: &[&dyn LockableResource], mut locked: u64) -> Result<(), KernelError> {
+    while let Some(i) = pick_by_ceiling(resources, locked, true) {
+        locked &= !(1 << i);
+        resources[i].unlock_resource()?;
+    }
+    Ok(())
+}
+
+/// Locks all the `resources` in increasing order of their ceilings, executes `handler`, which accesses
+/// them through `LockableResource::value`, and then unlocks them. The resources are locked within a
+/// single critical section; if any of them can't be locked, those already locked are unlocked and the
+/// error is returned, hence no resource is left locked. As ceilings are pushed in strictly increasing
+/// order, the resources must have distinct ceilings (`KernelError::CeilingViolation`); at most 64
+/// resources can be locked at once (`KernelError::LimitExceeded`).
+pub fn acquire_all<F, R>(resources: &[&dyn LockableResource], handler: F) -> Result<R, KernelError>
+where
+    F: FnOnce(&[&dyn LockableResource]) -> R,
+{
+    if resources.len() > 64 {
+        return Err(KernelError::LimitExceeded);
+    }
+    let all: u64 = if resources.len() == 64 { !0 } else { (1 << resources.len()) - 1 };
+    critical_section(|_| {
+        let mut locked: u64 = 0;
+        while let Some(i) = pick_by_ceiling(resources, all & !locked, false) {
+            if let Err(err) = resources[i].lock_resource() {
+                unlock_all(resources, locked)?;
+                return Err(err);
+            }
+            locked |= 1 << i;
+        }
+        Ok(())
+    })?;
+    let res = handler(resources);
+    critical_section(|_| unlock_all(resources, all))?;
+    Ok(res)
+}
+
+/// Gives access to a locked Resource, and unlocks it when dropped.
+pub struct ResourceGuard<'a, T: Sized> {
+    resource: &'a Resource<T>,
+}
+
+impl<'a, T: Sized> Deref for ResourceGuard<'a, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        // The ceiling lock is held for the lifetime of the guard.
+        unsafe { &*self.resource.inner.get() }
+    }
+}
+
+impl<'a, T: Sized> DerefMut for ResourceGuard<'a, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        // The ceiling lock is held for the lifetime of the guard, and `mut_borrowed` refuses locking it again meanwhile.
+        unsafe { &mut *self.resource.inner.get() }
+    }
+}
+
+impl<'a, T: Sized> Drop for ResourceGuard<'a, T> {
+    fn drop(&mut self) {
+        self.resource.mut_borrowed.set(false);
+        self.resource.unlock().ok();
+    }
+}
+
+/// A Resource which is initialized lazily, exactly once, by whichever task accesses it first.
+/// The initialization runs under the ceiling lock of the resource, hence it is safe against preemption
+/// by competing tasks. Once initialized, the value is shared read-only; hence `T` must be `Sync`.
+pub struct LazyResource<T: Sized> {
+    resource: Resource<Option<T>>,
+}
+
+impl<T: Sized> LazyResource<T> {
+
+    /// Create and initialize new uninitialized LazyResource object
+    pub const fn new(tasks_mask: BooleanVector) -> Self {
+        Self {
+            resource: Resource::new(None, tasks_mask),
+        }
+    }
+
+    /// Returns a reference to the value, initializing it with `init` if this is the first access.
+    pub fn get_or_init<F>(&self, init: F) -> Result<&T, KernelError>
+    where
+        F: FnOnce() -> T,
+    {
+        self.resource.acquire_mut(|value| {
+            if value.is_none() {
+                *value = Some(init());
+            }
+        })?;
+        // The value is never written again once initialized, hence sharing it is safe.
+        let value = unsafe { &*self.resource.inner.get() };
+        value.as_ref().ok_or(KernelError::NotFound)
+    }
+}
+
+/// The value is created by one task and then shared by reference with the others.
+unsafe impl<T: Send + Sync> Sync for LazyResource<T> {}
+
+// The tests rely on preemption, which the `cooperative` feature disables.
+#[cfg(all(test, not(feature = "cooperative")))]
+mod tests {
+    use super::*;
+    use crate::kernel::tasks::{release_tasks, task_exit};
+    use crate::utils::host::{boot, kernel_test, run_isr, take_trace, trace};
+
+    static CONFIG: LazyResource<u32> = LazyResource::new(0b110);
+
+    #[test]
+    fn lazy_resource_is_initialized_once() {
+        fn first() -> ! {
+            loop {
+                let value = CONFIG.get_or_init(|| {
+                    trace(100);
+                    7
+                }).unwrap();
+                trace(*value);
+                task_exit();
+            }
+        }
+        fn second() -> ! {
+            loop {
+                let value = CONFIG.get_or_init(|| unreachable!()).unwrap();
+                trace(*value);
+                task_exit();
+            }
+        }
+        let _kernel = kernel_test();
+        boot(&[(1, second), (2, first)]);
+        release_tasks(1 << 2).unwrap();
+        release_tasks(1 << 1).unwrap();
+        assert_eq!(take_trace(), [100, 7, 7]);
+    }
+
+    #[test]
+    fn retry_fails_at_once_on_own_ceiling() {
+        static OUTER: Resource<u32> = Resource::new(0, 0b110);
+        static INNER: Resource<u32> = Resource::new(0, 0b010);
+        fn task1() -> ! {
+            loop {
+                OUTER.acquire(|_| {
+                    let res = INNER.acquire_retry(3, |_| trace(100));
+                    assert!(matches!(res, Err(KernelError::CeilingViolation)));
+                    trace(1);
+                }).unwrap();
+                task_exit();
+            }
+        }
+        let _kernel = kernel_test();
+        boot(&[(1, task1)]);
+        release_tasks(1 << 1).unwrap();
+        assert_eq!(take_trace(), [1]);
+    }
+
+    #[test]
+    fn guard_unlocks_when_dropped() {
+        static SHARED: Resource<u32> = Resource::new(5, 0b110);
+        fn task1() -> ! {
+            loop {
+                let mut guard = SHARED.lock_guard().unwrap();
+                *guard += 1;
+                release_tasks(1 << 2).unwrap();
+                assert_eq!(system_ceiling(), 2);
+                trace(1);
+                // The task 2 preempts once the ceiling is restored.
+                drop(guard);
+                trace(11);
+                task_exit();
+            }
+        }
+        fn task2() -> ! {
+            loop {
+                SHARED.acquire(|value| trace(*value)).unwrap();
+                task_exit();
+            }
+        }
+        let _kernel = kernel_test();
+        boot(&[(1, task1), (2, task2)]);
+        release_tasks(1 << 1).unwrap();
+        assert_eq!(take_trace(), [1, 6, 11]);
+        assert_eq!(system_ceiling(), PiStack::EMPTY_CEILING);
+    }
+
+    #[test]
+    fn checked_constructor_rejects_a_task_out_of_range() {
+        let resource = Resource::try_new(0, 0b1010).unwrap();
+        assert_eq!(resource.ceiling, 3);
+        // Only a mask wider than `MAX_TASKS` (e.g. with the `tasks_16` feature) can refer to a task out of range.
+        if let Some(mask) = (1 as BooleanVector).checked_shl(MAX_TASKS as u32) {
+            assert!(matches!(Resource::try_new(0, mask | 1), Err(KernelError::DoesNotExist)));
+        }
+    }
+
+    #[test]
+    fn nested_locks_must_raise_the_ceiling() {
+        static LOW: Resource<u32> = Resource::new(1, 0b0110);
+        static HIGH: Resource<u32> = Resource::new(2, 0b1010);
+        fn task1() -> ! {
+            loop {
+                LOW.acquire(|low| HIGH.acquire(|high| trace(low + high)).unwrap()).unwrap();
+                let inverted = HIGH.acquire(|_| LOW.acquire(|_| trace(100))).unwrap();
+                assert!(matches!(inverted, Err(KernelError::CeilingViolation)));
+                assert_eq!(lock_depth(), 0);
+                trace(1);
+                task_exit();
+            }
+        }
+        let _kernel = kernel_test();
+        boot(&[(1, task1)]);
+        release_tasks(1 << 1).unwrap();
+        assert_eq!(take_trace(), [3, 1]);
+    }
+
+    #[test]
+    fn owner_locks_again() {
+        static SHARED: Resource<u32> = Resource::new(4, 0b110);
+        fn task1() -> ! {
+            loop {
+                SHARED.acquire(|outer| {
+                    SHARED.acquire(|inner| trace(outer + inner)).unwrap();
+                    // The inner unlock leaves the resource locked.
+                    assert_eq!(lock_depth(), 1);
+                    assert!(matches!(SHARED.lock_guard(), Err(KernelError::CeilingViolation)));
+                }).unwrap();
+                assert_eq!(lock_depth(), 0);
+                trace(1);
+                task_exit();
+            }
+        }
+        let _kernel = kernel_test();
+        boot(&[(1, task1)]);
+        release_tasks(1 << 1).unwrap();
+        assert_eq!(take_trace(), [8, 1]);
+    }
+
+    #[test]
+    fn handlers_mutate_and_move_their_captures() {
+        static SHARED: Resource<Option<Vec<u32>>> = Resource::new(None, 0b10);
+        fn task1() -> ! {
+            loop {
+                let values = vec![1, 2, 3];
+                // The handler moves the vector into the resource, hence it can only be called once.
+                SHARED.acquire_mut(move |shared| *shared = Some(values)).unwrap();
+                let mut count = 0;
+                let sum = SHARED.acquire(|shared| {
+                    shared.iter().flatten().for_each(|_| count += 1);
+                    shared.iter().flatten().sum::<u32>()
+                }).unwrap();
+                trace(count);
+                trace(sum);
+                task_exit();
+            }
+        }
+        let _kernel = kernel_test();
+        boot(&[(1, task1)]);
+        release_tasks(1 << 1).unwrap();
+        assert_eq!(take_trace(), [3, 6]);
+    }
+
+    #[test]
+    fn stats_count_locks_and_contention() {
+        static SHARED: Resource<u32> = Resource::new(0, 0b110);
+        fn task1() -> ! {
+            loop {
+                for _ in 0..3 {
+                    SHARED.acquire(|_| SHARED.acquire(|_| ()).unwrap()).unwrap();
+                }
+                #[cfg(feature = "cpu_stats")]
+                assert_eq!(SHARED.stats().blocked_cycles, 0);
+                SHARED.acquire(|_| {
+                    // The task 2 waits on the resource while it is held.
+                    release_tasks(1 << 2).unwrap();
+                    crate::utils::host::advance_cycle_counter(100);
+                }).unwrap();
+                task_exit();
+            }
+        }
+        fn task2() -> ! {
+            loop {
+                trace(SHARED.stats().lock_count);
+                task_exit();
+            }
+        }
+        let _kernel = kernel_test();
+        boot(&[(1, task1), (2, task2)]);
+        release_tasks(1 << 1).unwrap();
+        // Nested locks by the owner are not counted.
+        assert_eq!(take_trace(), [4]);
+        #[cfg(feature = "cpu_stats")]
+        assert!(SHARED.stats().blocked_cycles >= 100);
+    }
+
+    #[test]
+    fn acquire_all_leaves_no_partial_lock() {
+        static FIRST: Resource<u32> = Resource::new(1, 0b0110);
+        static SECOND: Resource<u32> = Resource::new(2, 0b1010);
+        static DENIED: Resource<u32> = Resource::new(3, 0b1000);
+        fn task1() -> ! {
+            loop {
+                let sum = acquire_all(&[&SECOND, &FIRST], |values| {
+                    assert_eq!(lock_depth(), 2);
+                    values.iter().map(|value| value.value().unwrap().downcast_ref::<u32>().unwrap()).sum::<u32>()
+                }).unwrap();
+                trace(sum);
+                // The first resource is locked, then the second one is denied.
+                let res = acquire_all(&[&FIRST, &DENIED], |_| trace(100));
+                assert!(matches!(res, Err(KernelError::AccessDenied)));
+                assert_eq!(lock_depth(), 0);
+                assert!(matches!(FIRST.value(), Err(KernelError::AccessDenied)));
+                trace(1);
+                task_exit();
+            }
+        }
+        let _kernel = kernel_test();
+        boot(&[(1, task1)]);
+        release_tasks(1 << 1).unwrap();
+        assert_eq!(take_trace(), [3, 1]);
+    }
+
+    #[test]
+    fn system_ceiling_follows_the_locks() {
+        static SHARED: Resource<u32> = Resource::new(0, 0b1010);
+        fn task1() -> ! {
+            loop {
+                SHARED.acquire(|_| {
+                    trace(system_ceiling() as u32);
+                    assert!(is_locked(3) && !is_locked(1));
+                    assert!(!system_ceiling_is_empty());
+                }).unwrap();
+                assert_eq!(system_ceiling(), PiStack::EMPTY_CEILING);
+                assert!(!is_locked(3));
+                task_exit();
+            }
+        }
+        let _kernel = kernel_test();
+        boot(&[(1, task1)]);
+        assert!(system_ceiling_is_empty());
+        release_tasks(1 << 1).unwrap();
+        assert_eq!(take_trace(), [3]);
+    }
+
+    #[test]
+    fn interrupt_handler_does_not_lock() {
+        static SHARED: Resource<u32> = Resource::new(0, 0b10);
+        fn task1() -> ! {
+            loop {
+                SHARED.acquire(|_| {
+                    // The interrupt can't unlock the resource on behalf of the task either.
+                    assert!(matches!(run_isr(|| SHARED.unlock()), Err(KernelError::NotInTaskContext)));
+                    trace(1);
+                }).unwrap();
+                assert!(matches!(run_isr(|| SHARED.try_lock()), Err(KernelError::NotInTaskContext)));
+                task_exit();
+            }
+        }
+        let _kernel = kernel_test();
+        boot(&[(1, task1)]);
+        release_tasks(1 << 1).unwrap();
+        assert_eq!(take_trace(), [1]);
+        assert!(matches!(run_isr(|| SHARED.acquire(|_| ())), Err(KernelError::NotInTaskContext)));
+        assert_eq!(lock_depth(), 0);
+    }
+
+    #[test]
+    fn unlock_dispatches_the_longest_waiting_task_first() {
+        static SHARED: Resource<u32> = Resource::new(0, 1 << 2 | 1 << 3 | 1 << 4);
+        fn task2() -> ! {
+            loop {
+                SHARED.acquire(|_| {
+                    release_tasks(1 << 3 | 1 << 4).unwrap();
+                    trace(20);
+                }).unwrap();
+                trace(21);
+                task_exit();
+            }
+        }
+        fn contender() -> ! {
+            loop {
+                SHARED.acquire(|_| trace(current_task())).unwrap();
+                task_exit();
+            }
+        }
+        let _kernel = kernel_test();
+        boot(&[(2, task2), (3, contender), (4, contender)]);
+        release_tasks(1 << 2).unwrap();
+        // Both contenders have waited since the lock, the task 3 was recorded first; strict priority would
+        // dispatch the task 4 first.
+        #[cfg(feature = "fair_resources")]
+        assert_eq!(take_trace(), [20, 3, 4, 21]);
+        #[cfg(not(feature = "fair_resources"))]
+        assert_eq!(take_trace(), [20, 4, 3, 21]);
+    }
+}
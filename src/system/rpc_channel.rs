@@ -0,0 +1,149 @@
+//! # RPC Channel
+//!
+//! Defines a request/response channel between client tasks and a server task.
+use core::cell::RefCell;
+
+use crate::config::MAX_TASKS;
+use crate::KernelError;
+use crate::kernel::tasks::{current_task, release, schedule};
+use crate::kernel::timer::{cancel_sleep, get_time, sleep_until, wake};
+use crate::system::sleep_table::tick_reached;
+use crate::system::scheduler::{BooleanVector, TaskId};
+use crate::utils::arch::{critical_section, get_msb};
+
+/// A channel on which client tasks send requests to a server task and wait for its responses. Each client
+/// has its own request and response slot, indexed by its TaskId; hence concurrent callers never receive
+/// each other's responses. A client has at most one request outstanding, as `call` blocks until it is answered.
+pub struct RpcChannel<Req: Copy, Resp: Copy> {
+    /// The task which serves the requests, it is released when a request is sent.
+    server: TaskId,
+    /// The pending request of each client.
+    requests: RefCell<[Option<Req>; MAX_TASKS]>,
+    /// The response to each client, until it is picked up.
+    responses: RefCell<[Option<Resp>; MAX_TASKS]>,
+    /// A boolean vector of the clients whose request has not been taken by the server yet.
+    pending: RefCell<BooleanVector>,
+    /// A boolean vector of the clients waiting for a response.
+    waiters: RefCell<BooleanVector>,
+}
+
+impl<Req: Copy, Resp: Copy> RpcChannel<Req, Resp> {
+    /// Initializes a new channel, served by the task `server`.
+    pub const fn new(server: TaskId) -> Self {
+        Self {
+            server,
+            requests: RefCell::new([None; MAX_TASKS]),
+            responses: RefCell::new([None; MAX_TASKS]),
+            pending: RefCell::new(0),
+            waiters: RefCell::new(0),
+        }
+    }
+
+    /// Sends `req` to the server and blocks the currently running task until the server responds, or
+    /// `timeout` timer ticks elapse, in which case `KernelError::TimedOut` is returned and the request
+    /// is withdrawn if the server has not taken it yet. The server can't call itself (`KernelError::AccessDenied`).
+    /// If the task is woken before the deadline without a response, it blocks again.
+    pub fn call(&'static self, req: Req, timeout: u32) -> Result<Resp, KernelError> {
+        let curr_tid = current_task();
+        if curr_tid == self.server {
+            return Err(KernelError::AccessDenied);
+        }
+        let deadline = get_time().wrapping_add(timeout);
+        critical_section(|_| {
+            self.requests.borrow_mut()[curr_tid as usize] = Some(req);
+            self.responses.borrow_mut()[curr_tid as usize] = None;
+            *self.pending.borrow_mut() |= 1 << curr_tid;
+            *self.waiters.borrow_mut() |= 1 << curr_tid;
+            release(1 << self.server);
+        });
+        loop {
+            let responded = critical_section(|_| {
+                if *self.waiters.borrow() & (1 << curr_tid) == 0 || tick_reached(get_time(), deadline) {
+                    return true;
+                }
+                // The context switch is carried out once the critical section ends.
+                sleep_until(deadline);
+                false
+            });
+            if responded {
+                break;
+            }
+        }
+        critical_section(|_| {
+            cancel_sleep(curr_tid);
+            *self.pending.borrow_mut() &= !(1 << curr_tid);
+            *self.waiters.borrow_mut() &= !(1 << curr_tid);
+            self.requests.borrow_mut()[curr_tid as usize] = None;
+            self.responses.borrow_mut()[curr_tid as usize].take().ok_or(KernelError::TimedOut)
+        })
+    }
+
+    /// Serves the pending requests, highest priority client first, by calling `handler` on each of them, and
+    /// sends each response to the client which made the request. It is called by the server task, and
+    /// returns the number of requests served. A response to a client which has timed out is dropped.
+    pub fn serve<F>(&'static self, mut handler: F) -> usize
+    where
+        F: FnMut(Req) -> Resp,
+    {
+        let mut served = 0;
+        loop {
+            let next = critical_section(|_| {
+                let pending: &mut BooleanVector = &mut self.pending.borrow_mut();
+                let client = get_msb(*pending)?;
+                *pending &= !(1 << client);
+                let req = self.requests.borrow_mut()[client].take()?;
+                Some((client, req))
+            });
+            let (client, req) = match next {
+                Some(next) => next,
+                None => return served,
+            };
+            // The handler runs outside of the critical section, hence it may take long or block.
+            let resp = handler(req);
+            served += 1;
+            critical_section(|_| {
+                let waiters: &mut BooleanVector = &mut self.waiters.borrow_mut();
+                if *waiters & (1 << client) != 0 {
+                    *waiters &= !(1 << client);
+                    self.responses.borrow_mut()[client] = Some(resp);
+                    wake(1 << client);
+                    schedule();
+                }
+            });
+        }
+    }
+}
+
+unsafe impl<Req: Copy, Resp: Copy> Sync for RpcChannel<Req, Resp> {}
+
+// The tests rely on preemption, which the `cooperative` feature disables.
+#[cfg(all(test, not(feature = "cooperative")))]
+mod tests {
+    use super::*;
+    use crate::kernel::tasks::{release_tasks, task_exit};
+    use crate::utils::host::{boot, kernel_test, take_trace, trace};
+
+    static DOUBLER: RpcChannel<u32, u32> = RpcChannel::new(1);
+
+    #[test]
+    fn concurrent_clients_get_their_own_responses() {
+        fn server() -> ! {
+            loop {
+                assert!(matches!(DOUBLER.call(0, 10), Err(KernelError::AccessDenied)));
+                trace(DOUBLER.serve(|req| req * 2) as u32);
+                task_exit();
+            }
+        }
+        fn client() -> ! {
+            loop {
+                trace(DOUBLER.call(current_task() * 10, 10).unwrap());
+                task_exit();
+            }
+        }
+        let _kernel = kernel_test();
+        boot(&[(1, server), (2, client), (3, client)]);
+        release_tasks(1 << 2 | 1 << 3).unwrap();
+        // The server answers the higher priority client first, which then preempts it.
+        assert_eq!(take_trace(), [60, 40, 2]);
+    }
+}
@@ -0,0 +1,139 @@
+//! # Reader-Writer Resource
+//!
+//! Defines a Resource which can be read concurrently by multiple tasks, but is written exclusively.
+use core::cell::{RefCell, UnsafeCell};
+
+use crate::KernelError;
+use crate::kernel::tasks::current_task;
+use crate::system::resource::{ceiling_of, lock_ceiling, unlock_ceiling};
+use crate::system::scheduler::{task_bit, BooleanVector, TaskId};
+use crate::utils::arch::critical_section;
+
+/// A Resource which distinguishes read-only access from write access. Under the Stack Resource Policy,
+/// a writer locks the resource with the ceiling of all the tasks which access it, while a reader only
+/// locks it with the ceiling of the writers; hence a reader blocks the writers, but not the readers of
+/// a higher priority, which preempt it and read concurrently. It remains deadlock-free like `Resource`.
+pub struct RwResource<T: Sized> {
+    /// A boolean vector of the tasks which only read the resource.
+    readers_mask: BooleanVector,
+    /// A boolean vector of the tasks which write the resource.
+    writers_mask: BooleanVector,
+    /// The priority of the highest priority writer.
+    read_ceiling: TaskId,
+    /// The priority of the highest priority task which accesses the resource.
+    write_ceiling: TaskId,
+    /// The number of tasks which are currently reading the resource.
+    readers: RefCell<u32>,
+    inner: UnsafeCell<T>,
+}
+
+impl<T: Sized> RwResource<T> {
+
+    /// Create and initialize new RwResource object. A task in `writers_mask` can both read and write.
+    pub const fn new(val: T, readers_mask: BooleanVector, writers_mask: BooleanVector) -> Self {
+        Self {
+            readers_mask: readers_mask | writers_mask,
+            writers_mask,
+            read_ceiling: ceiling_of(writers_mask),
+            write_ceiling: ceiling_of(readers_mask | writers_mask),
+            readers: RefCell::new(0),
+            inner: UnsafeCell::new(val),
+        }
+    }
+
+    /// The ceiling with which the currently running task locks the resource for reading. A reader which
+    /// has a higher priority than every writer locks it with its own priority, so that the ceilings on the
+    /// `PiStack` stay strictly increasing when it preempts another reader.
+    fn effective_read_ceiling(&self) -> TaskId {
+        let curr_tid = current_task() as TaskId;
+        if curr_tid > self.read_ceiling { curr_tid } else { self.read_ceiling }
+    }
+
+    /// Executes `handler` with shared access to the resource. Returns `KernelError::AccessDenied` if the
+    /// currently running task is not a reader or writer.
+    pub fn read<F, R>(&self, handler: F) -> Result<R, KernelError>
+    where
+        F: FnOnce(&T) -> R,
+    {
+        if self.readers_mask & task_bit(current_task() as usize) == 0 {
+            return Err(KernelError::AccessDenied);
+        }
+        let ceiling = self.effective_read_ceiling();
+        lock_ceiling(ceiling)?;
+        critical_section(|_| *self.readers.borrow_mut() += 1);
+        // Writers are blocked by the ceiling, hence the value is not mutated meanwhile.
+        let res = handler(unsafe { &*self.inner.get() });
+        critical_section(|_| *self.readers.borrow_mut() -= 1);
+        unlock_ceiling(ceiling)?;
+        Ok(res)
+    }
+
+    /// Executes `handler` with exclusive access to the resource. Returns `KernelError::AccessDenied` if the
+    /// currently running task is not a writer.
+    pub fn write<F, R>(&self, handler: F) -> Result<R, KernelError>
+    where
+        F: FnOnce(&mut T) -> R,
+    {
+        if self.writers_mask & task_bit(current_task() as usize) == 0 {
+            return Err(KernelError::AccessDenied);
+        }
+        lock_ceiling(self.write_ceiling)?;
+        // All readers and writers are blocked by the ceiling; a reader can't be preempted midway by a writer.
+        if critical_section(|_| *self.readers.borrow()) != 0 {
+            unlock_ceiling(self.write_ceiling)?;
+            return Err(KernelError::CeilingViolation);
+        }
+        let res = handler(unsafe { &mut *self.inner.get() });
+        unlock_ceiling(self.write_ceiling)?;
+        Ok(res)
+    }
+
+    /// Returns the number of tasks which are currently reading the resource.
+    pub fn reader_count(&self) -> u32 {
+        critical_section(|_| *self.readers.borrow())
+    }
+}
+
+unsafe impl<T> Sync for RwResource<T> {}
+
+// The tests rely on preemption, which the `cooperative` feature disables.
+#[cfg(all(test, not(feature = "cooperative")))]
+mod tests {
+    use super::*;
+    use crate::kernel::tasks::{release_tasks, task_exit};
+    use crate::utils::host::{boot, kernel_test, take_trace, trace};
+
+    static SHARED: RwResource<u32> = RwResource::new(5, 0b1100, 0b0010);
+
+    #[test]
+    fn readers_share_and_writer_waits() {
+        fn writer() -> ! {
+            loop {
+                SHARED.write(|value| *value = 9).unwrap();
+                SHARED.read(|value| trace(*value)).unwrap();
+                task_exit();
+            }
+        }
+        fn low_reader() -> ! {
+            loop {
+                SHARED.read(|value| {
+                    // The higher priority reader preempts, whereas the writer waits until the read ends.
+                    release_tasks(1 << 1 | 1 << 3).unwrap();
+                    trace(20 + *value);
+                }).unwrap();
+                task_exit();
+            }
+        }
+        fn high_reader() -> ! {
+            loop {
+                SHARED.read(|_| trace(SHARED.reader_count())).unwrap();
+                assert!(matches!(SHARED.write(|_| ()), Err(KernelError::AccessDenied)));
+                task_exit();
+            }
+        }
+        let _kernel = kernel_test();
+        boot(&[(1, writer), (2, low_reader), (3, high_reader)]);
+        release_tasks(1 << 2).unwrap();
+        assert_eq!(take_trace(), [2, 25, 9]);
+    }
+}
@@ -1,52 +1,241 @@
 //! # Task Manager
 //! The Definition of Data-structures required for task management.
 //!
+//! The scheduling decisions (`get_next_tid`, and the manipulation of the task state vectors) only operate
+//! on `BooleanVector`s and do not touch the hardware; saving and loading the task context is confined to `utils::arch`, and
+//! the critical sections and the PendSV trigger to the `Port` of `utils::port`, which the tests replace by a
+//! host mock.
+//!
+//! The idle task lies outside the task numbering: it has no bit in the task state vectors and its TCB is kept
+//! apart in `idle_tcb`. `get_next_tid` falls back to it (`IDLE_TASK`) when no task is ready; hence every
+//! priority from 0 up to `MAX_TASKS - 1` is available to application tasks.
+//!
 use crate::config::MAX_TASKS;
-use crate::utils::arch::{get_msb, save_context, load_context, wait_for_interrupt};
+#[cfg(feature="stack_guard")]
+use crate::config::WATERMARK_FILL_LIMIT;
+use crate::utils::arch::{save_context, load_context, wait_for_interrupt, set_thread_privileged};
+#[cfg(feature="cpu_stats")]
+use crate::utils::arch::read_cycle_counter;
+use crate::utils::helpers::get_msb;
 use crate::KernelError;
+use crate::kernel::tasks::{run_idle_hook, task_return};
+
+#[cfg(feature = "tickless")]
+use crate::kernel::tickless::idle_wait;
 
 #[cfg(feature = "task_monitor")]
 use crate::kernel::task_monitor::{clear_deadline, set_deadline};
 
 pub type TaskId = u32;
+
+/// A bit vector with a bit per task, the bit at position `i` corresponds to the task with TaskId `i`.
+/// It is 64 bits wide with the `tasks_64` feature, else 32 bits wide.
+#[cfg(not(feature = "tasks_64"))]
 pub type BooleanVector = u32;
+#[cfg(feature = "tasks_64")]
+pub type BooleanVector = u64;
+
+/// The number of bits in a `BooleanVector`.
+pub const VECTOR_BITS: usize = core::mem::size_of::<BooleanVector>() * 8;
+
+/// The TaskId of the idle task, which runs when no other task is ready. It is not a priority: it has no bit
+/// in a `BooleanVector`, hence it never appears in a task mask and can't be released, blocked or deleted.
+pub const IDLE_TASK: TaskId = MAX_TASKS as TaskId;
+
+/// Returns the bit of the task `tid` in a `BooleanVector`, or 0 for the idle task.
+pub(crate) const fn task_bit(tid: usize) -> BooleanVector {
+    if tid < MAX_TASKS { 1 << tid } else { 0 }
+}
+
+/// Compile-time assertion that a `BooleanVector` has a bit for every task.
+const _: () = [()][(MAX_TASKS > VECTOR_BITS) as usize];
+
+/// Lifecycle of the Kernel. The Kernel must be initialized with `init` before tasks are created,
+/// and `start_kernel` must be called only once, after `init`.
+#[derive(Clone, Copy, PartialEq)]
+pub enum KernelState {
+    Uninit,
+    Initialized,
+    Running,
+}
+
+/// The scheduling state of a task.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TaskState {
+    /// The task is currently executing on the CPU.
+    Running,
+    /// The task is released and can be scheduled.
+    Ready,
+    /// The task is released, but blocked by a locked resource.
+    Blocked,
+    /// The task sleeps until a timer tick, or until it is woken by the primitive it waits on.
+    Sleeping,
+    /// The task has finished (or has not been released yet) and waits to be released.
+    Waiting,
+    /// The task has been suspended (or halted due to a fault) and is not scheduled even if released.
+    Suspended,
+    /// No task has been created with this TaskId.
+    Nonexistent,
+}
+
+/// Describes which invariant of the scheduler state has been violated.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum InvariantViolation {
+    /// The task is marked active, but has no task control block.
+    ActiveWithoutTcb(TaskId),
+    /// The idle task has not been created.
+    IdleTaskMissing,
+    /// The currently running task is not a valid task.
+    InvalidRunningTask(TaskId),
+    /// The currently running task is not marked active.
+    RunningTaskInactive(TaskId),
+    /// The task is blocked, but not active, and no locked resource blocks it.
+    BlockedWhileInactive(TaskId),
+    /// The task is both sleeping and suspended.
+    SleepingWhileSuspended(TaskId),
+    /// The task has been halted after a fault, but is still marked active.
+    FaultedWhileActive(TaskId),
+    /// A state vector has a bit set for the task, which has no task control block.
+    StateWithoutTcb(TaskId),
+}
 
 /// Maintains state of all tasks in the Kernel
 #[repr(C)]
 pub struct Scheduler {
-    /// The Task id of the currently running task.
+    /// The Task id of the currently running task, `IDLE_TASK` while the idle task runs.
     pub curr_tid: usize,
+    /// The lifecycle state of the Kernel.
+    pub state: KernelState,
     /// True if the scheduler has started scheduling tasks on the CPU.
     pub started: bool,
     /// An Array of task control blocks corresponding to each task (created only if task exists).
     pub task_control_blocks: [Option<TaskControlBlock>; MAX_TASKS],
+    /// The task control block of the idle task, created by `init`.
+    pub idle_tcb: Option<TaskControlBlock>,
     /// A boolean vector in which, if a bit at a position is true, it implies that the task is active and to be scheduled.
     pub blocked_tasks: BooleanVector,
     /// A boolean vector in which, if a bit at a position is true, it implies that the task is blocked and cannot be scheduled even if it’s active.
     pub active_tasks: BooleanVector,
+    /// A boolean vector of the tasks which have been halted due to a fault, they are never scheduled again.
+    pub faulted_tasks: BooleanVector,
+    /// A boolean vector of the tasks which have been suspended by the application, they are not scheduled until resumed.
+    /// It is kept separate from `blocked_tasks`, hence unlocking resources never resumes a suspended task.
+    pub suspended_tasks: BooleanVector,
+    /// A boolean vector of the tasks which have yielded, they are not scheduled until the next context switch.
+    pub yielded_tasks: BooleanVector,
+    /// A boolean vector of the tasks sleeping until a timer tick, they are not scheduled until the timer sweep
+    /// or `wake_tasks` clears them. It is kept separate from `active_tasks`, hence releasing a sleeping task
+    /// does not end its sleep.
+    pub sleeping_tasks: BooleanVector,
+    /// The task to which the running task has handed the CPU with `yield_to`, it is dispatched at the next
+    /// context switch regardless of its priority.
+    pub yield_target: Option<usize>,
+    /// True if the running task has yielded the CPU with `task_yield` or `yield_to`, it is switched out at the
+    /// next scheduling point even if preemption is disabled.
+    pub switch_requested: bool,
+    /// For each task, the sequence number of the moment it started waiting on a resource.
+    #[cfg(feature="fair_resources")]
+    pub wait_seq: [u32; MAX_TASKS],
+    /// The sequence number given to the next task which starts waiting on a resource.
+    #[cfg(feature="fair_resources")]
+    pub next_wait_seq: u32,
+    /// For each task, a boolean vector of the tasks waiting for it to exit.
+    pub joiners: [BooleanVector; MAX_TASKS],
+    /// A boolean vector of the tasks waiting for a `PiResource`, they lend their priority to its holder.
+    pub pi_waiting: BooleanVector,
+    /// For each task in `pi_waiting`, the TaskId of the task holding the resource it waits for.
+    pub pi_holder: [usize; MAX_TASKS],
     /// A variable which decided if the scheduler should preemptively schedule tasks or not.
     pub is_preemptive: bool,
     pub preempt_disable_count: u32,
+    /// Called by the idle task on each iteration, before the CPU is put to sleep.
+    pub idle_hook: Option<fn()>,
+    /// Called when the scheduler switches to the idle task from a real task.
+    pub idle_enter_hook: Option<fn()>,
+    /// Called when the scheduler switches away from the idle task to a real task.
+    pub idle_exit_hook: Option<fn()>,
+    /// Minimum number of unused stack words a task must have when it is switched out.
+    #[cfg(feature="stack_guard")]
+    pub low_stack_threshold: usize,
+    /// Called with the TaskId and free stack words of a task which is switched out below `low_stack_threshold`.
+    #[cfg(feature="stack_guard")]
+    pub low_stack_hook: Option<fn(TaskId, usize)>,
+    /// CPU cycles spent executing each task, and the idle task (at `IDLE_TASK`).
+    #[cfg(feature="cpu_stats")]
+    pub cpu_cycles: [u64; MAX_TASKS + 1],
+    /// Value of the cycle counter at the last context switch.
+    #[cfg(feature="cpu_stats")]
+    pub last_switch_cycles: u32,
 }
 
 /// A single tasks's state
-#[cfg(not(feature="task_monitor"))]
 #[derive(Clone, Copy)]
 #[repr(C)]
 pub struct TaskControlBlock {
-    /// Holds a reference to the stack pointer for the task.
+    /// Holds a reference to the stack pointer for the task. It must remain the first field,
+    /// the context switch assembly accesses it at offset 0.
     stack_pointer: usize, // current stack pointer of this thread
+    /// Scheduling metadata and per-task diagnostics.
+    pub meta: SchedMeta,
 }
 
-#[cfg(feature="task_monitor")]
+/// Per-task bookkeeping which is not accessed by the context switch assembly.
 #[derive(Clone, Copy)]
-#[repr(C)]
-pub struct TaskControlBlock {
-    /// Holds a reference to the stack pointer for the task.
-    stack_pointer: usize, // current stack pointer of this thread
-    deadline: u32,
+pub struct SchedMeta {
+    #[cfg(feature="task_monitor")]
+    pub deadline: u32,
+    /// Address of the lowest word of the task stack, which holds `STACK_GUARD`.
+    pub stack_base: usize,
+    /// Size of the task stack in words.
+    pub stack_size: usize,
+    /// The task handler, kept so that the task can be restarted.
+    pub handler: Option<fn() -> !>,
+    /// True if the task has dropped its privileges, the thread mode privilege is restored on each switch to it.
+    pub unprivileged: bool,
+    /// True if the task uses the FPU, its floating point registers are saved and restored on context switches.
+    #[cfg(feature="fpu")]
+    pub uses_fpu: bool,
+    /// The number of words above the guard word which have been painted by `paint_stack`.
+    #[cfg(feature="stack_guard")]
+    pub painted_words: usize,
+}
+
+impl SchedMeta {
+    pub const fn new() -> Self {
+        Self {
+            #[cfg(feature="task_monitor")]
+            deadline: 0,
+            stack_base: 0,
+            stack_size: 0,
+            handler: None,
+            unprivileged: false,
+            #[cfg(feature="fpu")]
+            uses_fpu: false,
+            #[cfg(feature="stack_guard")]
+            painted_words: 0,
+        }
+    }
 }
 
+/// Compile-time assertion that `stack_pointer` is at offset 0 of `TaskControlBlock`.
+const _: () = {
+    union Probe {
+        tcb: TaskControlBlock,
+        first_word: usize,
+    }
+    const MARKER: usize = 0x5A5A_5A5A;
+    let probe = Probe { tcb: TaskControlBlock { stack_pointer: MARKER, meta: SchedMeta::new() } };
+    let first_word = unsafe { probe.first_word };
+    [()][(first_word != MARKER) as usize]
+};
+
+/// The value written to the lowest word of each task stack, it is overwritten only if the stack overflows.
+pub const STACK_GUARD: u32 = 0xCAFEBABE;
+
+/// The value the unused words of a task stack are painted with by `paint_stack`.
+#[cfg(feature="stack_guard")]
+pub const STACK_PAINT: u32 = 0xDEADBEEF;
+
 
 impl TaskControlBlock {
     pub fn save_context(&self) {
@@ -55,6 +244,32 @@ impl TaskControlBlock {
     pub fn load_context(&self) {
         load_context(self)
     }
+
+    /// Returns true if the guard word at the bottom of the task stack is intact.
+    pub fn is_stack_intact(&self) -> bool {
+        unsafe { *(self.meta.stack_base as *const u32) == STACK_GUARD }
+    }
+
+    /// Returns the number of words above the guard word of the task stack which still hold the paint value,
+    /// i.e. the stack which has never been used by the task. Only the painted words are measured.
+    #[cfg(feature="stack_guard")]
+    pub fn free_stack(&self) -> usize {
+        let stack = unsafe { core::slice::from_raw_parts(self.meta.stack_base as *const u32, self.meta.stack_size) };
+        stack.iter().skip(1).take(self.meta.painted_words).take_while(|&&word| word == STACK_PAINT).count()
+    }
+
+    /// Paints the words between the guard word and the saved stack pointer, up to `WATERMARK_FILL_LIMIT` of
+    /// them. These words are unused while the task is switched out.
+    #[cfg(feature="stack_guard")]
+    fn paint_stack(&mut self) {
+        let unused_words = (self.stack_pointer - self.meta.stack_base) / core::mem::size_of::<u32>() - 1;
+        let words = core::cmp::min(unused_words, WATERMARK_FILL_LIMIT);
+        let stack = unsafe { core::slice::from_raw_parts_mut(self.meta.stack_base as *mut u32, self.meta.stack_size) };
+        for word in stack[1..=words].iter_mut() {
+            *word = STACK_PAINT;
+        }
+        self.meta.painted_words = words;
+    }
 }
 
 impl Scheduler {
@@ -62,26 +277,59 @@ impl Scheduler {
     /// Returns a new instance of `Scheduler`
     pub const fn new() -> Self {
         Self {
-            curr_tid: 0,
+            curr_tid: IDLE_TASK as usize,
+            state: KernelState::Uninit,
             started: false,
             task_control_blocks: [None; MAX_TASKS],
-            active_tasks: 1,
+            idle_tcb: None,
+            active_tasks: 0,
             blocked_tasks: 0,
+            faulted_tasks: 0,
+            suspended_tasks: 0,
+            yielded_tasks: 0,
+            sleeping_tasks: 0,
+            yield_target: None,
+            switch_requested: false,
+            #[cfg(feature="fair_resources")]
+            wait_seq: [0; MAX_TASKS],
+            #[cfg(feature="fair_resources")]
+            next_wait_seq: 0,
+            joiners: [0; MAX_TASKS],
+            pi_waiting: 0,
+            pi_holder: [0; MAX_TASKS],
             is_preemptive: false,
             preempt_disable_count: 0,
+            idle_hook: None,
+            idle_enter_hook: None,
+            idle_exit_hook: None,
+            #[cfg(feature="stack_guard")]
+            low_stack_threshold: 0,
+            #[cfg(feature="stack_guard")]
+            low_stack_hook: None,
+            #[cfg(feature="cpu_stats")]
+            cpu_cycles: [0; MAX_TASKS + 1],
+            #[cfg(feature="cpu_stats")]
+            last_switch_cycles: 0,
         }
     }
     
     #[cfg(feature="task_monitor")]
     pub fn init(&mut self) -> Result<(),KernelError>{
-        self.is_preemptive = true;
+        match self.state {
+            KernelState::Uninit => self.state = KernelState::Initialized,
+            KernelState::Initialized => return Err(KernelError::Exists),
+            KernelState::Running => return Err(KernelError::AlreadyRunning),
+        }
+        self.is_preemptive = !cfg!(feature="cooperative");
         
         static mut stack0: [u32; 64] = [0; 64];
-        self.create_task(
-            0,
-            100,
+        self.create_idle_task(
             unsafe { &mut stack0 },
             || loop {
+                run_idle_hook();
+                #[cfg(feature="tickless")]
+                idle_wait();
+                #[cfg(not(feature="tickless"))]
                 wait_for_interrupt();
             }
         )
@@ -89,13 +337,21 @@ impl Scheduler {
 
     #[cfg(not(feature="task_monitor"))]
     pub fn init(&mut self) -> Result<(),KernelError>{
-        self.is_preemptive = true;
+        match self.state {
+            KernelState::Uninit => self.state = KernelState::Initialized,
+            KernelState::Initialized => return Err(KernelError::Exists),
+            KernelState::Running => return Err(KernelError::AlreadyRunning),
+        }
+        self.is_preemptive = !cfg!(feature="cooperative");
         
         static mut stack0: [u32; 64] = [0; 64];
-        self.create_task(
-            0,
+        self.create_idle_task(
             unsafe { &mut stack0 },
             || loop {
+                run_idle_hook();
+                #[cfg(feature="tickless")]
+                idle_wait();
+                #[cfg(not(feature="tickless"))]
                 wait_for_interrupt();
             }
         )
@@ -115,7 +371,8 @@ impl Scheduler {
         handler_fn: fn() -> !,
     ) -> Result<(), KernelError>
     {
-        let tcb = self.create_tcb(stack, handler_fn)?;
+        self.check_slot(priority)?;
+        let tcb = self.create_tcb(SchedMeta::new(), stack, handler_fn)?;
         self.insert_tcb(priority, tcb)
     }
     
@@ -128,100 +385,756 @@ impl Scheduler {
         handler_fn: fn() -> !,
     ) -> Result<(), KernelError>
     {
-        let tcb = self.create_tcb(deadline, stack, handler_fn)?;
+        self.check_slot(priority)?;
+        let mut meta = SchedMeta::new();
+        meta.deadline = deadline;
+        let tcb = self.create_tcb(meta, stack, handler_fn)?;
         self.insert_tcb(priority, tcb)
     }
 
-    /// Creates a TCB corresponding to the tasks details passed onto this method.
-    #[cfg(not(feature="task_monitor"))]
-    fn create_tcb(
-        &self,
+    /// Creates a task whose TCB is built from `meta`, e.g. a task which uses the FPU.
+    #[cfg(feature="fpu")]
+    pub fn create_task_with_meta(
+        &mut self,
+        priority: usize,
+        meta: SchedMeta,
         stack: &mut [u32],
-        handler: fn() -> !,
-    ) -> Result<TaskControlBlock, KernelError>
+        handler_fn: fn() -> !,
+    ) -> Result<(), KernelError>
     {
-        if stack.len() < 32 {
-            return Err(KernelError::StackTooSmall);
-        }
+        self.check_slot(priority)?;
+        let tcb = self.create_tcb(meta, stack, handler_fn)?;
+        self.insert_tcb(priority, tcb)
+    }
 
-        let pos = stack.len() - 1;
-        let pc: usize = handler as usize;
+    /// Creates the idle task, which runs `handler_fn` on `stack` whenever no other task is ready.
+    fn create_idle_task(&mut self, stack: &mut [u32], handler_fn: fn() -> !) -> Result<(), KernelError> {
+        if self.idle_tcb.is_some() {
+            return Err(KernelError::Exists);
+        }
+        self.idle_tcb = Some(self.create_tcb(SchedMeta::new(), stack, handler_fn)?);
+        Ok(())
+    }
 
-        stack[pos] = 1 << 24; // xPSR
-        stack[pos - 1] = pc as u32; // PC
+    /// Returns the TCB of the task `tid`, or of the idle task if `tid` is `IDLE_TASK`.
+    pub fn tcb(&self, tid: usize) -> Option<&TaskControlBlock> {
+        if tid == IDLE_TASK as usize {
+            return self.idle_tcb.as_ref();
+        }
+        self.task_control_blocks.get(tid)?.as_ref()
+    }
 
-        let stack_pointer: usize = unsafe { core::intrinsics::transmute(&stack[stack.len() - 16]) };
-        let tcb = TaskControlBlock {
-            stack_pointer: stack_pointer as usize 
-        };
+    /// Returns the mutable TCB of the task `tid`, or of the idle task if `tid` is `IDLE_TASK`.
+    pub fn tcb_mut(&mut self, tid: usize) -> Option<&mut TaskControlBlock> {
+        if tid == IDLE_TASK as usize {
+            return self.idle_tcb.as_mut();
+        }
+        self.task_control_blocks.get_mut(tid)?.as_mut()
+    }
 
-        Ok(tcb)
+    /// Transitions the Kernel to the running state.
+    pub fn start(&mut self) -> Result<(), KernelError> {
+        match self.state {
+            KernelState::Uninit => Err(KernelError::NotInitialized),
+            KernelState::Initialized => {
+                self.state = KernelState::Running;
+                Ok(())
+            },
+            KernelState::Running => Err(KernelError::AlreadyRunning),
+        }
     }
-    
-    #[cfg(feature="task_monitor")]
+
+    /// Creates a TCB corresponding to the tasks details passed onto this method.
     fn create_tcb(
         &self,
-        deadline: u32,
+        mut meta: SchedMeta,
         stack: &mut [u32],
         handler: fn() -> !,
     ) -> Result<TaskControlBlock, KernelError>
     {
-        if stack.len() < 32 {
+        if self.state == KernelState::Uninit {
+            return Err(KernelError::NotInitialized);
+        }
+        #[cfg(feature="fpu")]
+        let min_stack = if meta.uses_fpu { 64 } else { 32 };
+        #[cfg(not(feature="fpu"))]
+        let min_stack = 32;
+        if stack.len() < min_stack {
             return Err(KernelError::StackTooSmall);
         }
 
-        let pos = stack.len() - 1;
+        stack[0] = STACK_GUARD;
+        // The stack is painted on request only, see `paint_stack`.
+        #[cfg(feature="stack_guard")]
+        { meta.painted_words = 0; }
+        meta.stack_base = stack.as_ptr() as usize;
+        meta.stack_size = stack.len();
+        meta.handler = Some(handler);
+
+        // An FPU task starts with the extended exception frame (S0-S15, FPSCR and a reserved word above the
+        // integer frame), and saves S16-S31 along with R4-R11.
+        #[cfg(feature="fpu")]
+        let (fp_frame_words, fp_context_words) = if meta.uses_fpu { (18, 16) } else { (0, 0) };
+        #[cfg(not(feature="fpu"))]
+        let (fp_frame_words, fp_context_words) = (0, 0);
+
+        let pos = stack.len() - 1 - fp_frame_words;
         let pc: usize = handler as usize;
 
         stack[pos] = 1 << 24; // xPSR
         stack[pos - 1] = pc as u32; // PC
+        stack[pos - 2] = task_return as usize as u32; // LR
 
-        let stack_pointer: usize = unsafe { core::intrinsics::transmute(&stack[stack.len() - 16]) };
+        // The initial stack pointer addresses the saved R4, 16 words (plus the FPU words) below the top of the
+        // stack. Only the address is taken, the stack is accessed through it by the context switch alone.
+        let stack_pointer = stack.as_ptr() as usize + (stack.len() - 16 - fp_frame_words - fp_context_words) * core::mem::size_of::<u32>();
         let tcb = TaskControlBlock {
-            deadline, 
-            stack_pointer: stack_pointer as usize 
+            stack_pointer: stack_pointer as usize,
+            meta,
         };
 
         Ok(tcb)
     }
 
-    /// Inserts the `TCB` into `task_control_blocks` at position `id`.
-    fn insert_tcb(&mut self, id: usize, tcb: TaskControlBlock) -> Result<(), KernelError> {
+    /// Returns `KernelError::NotFound` if `id` is out of range and `KernelError::Exists` if a task already
+    /// occupies the slot `id`.
+    fn check_slot(&self, id: usize) -> Result<(), KernelError> {
         if id >= MAX_TASKS {
             return Err(KernelError::NotFound);
         }
+        if self.task_control_blocks[id].is_some() {
+            return Err(KernelError::Exists);
+        }
+        Ok(())
+    }
+
+    /// Inserts the `TCB` into `task_control_blocks` at position `id`. The slot must be free, an existing
+    /// task is never overwritten.
+    fn insert_tcb(&mut self, id: usize, tcb: TaskControlBlock) -> Result<(), KernelError> {
+        self.check_slot(id)?;
         self.task_control_blocks[id] = Some(tcb);
         return Ok(());
     }
 
+    /// Returns the lowest free slot, or `KernelError::LimitExceeded` if all are taken.
+    pub fn free_slot(&self) -> Result<usize, KernelError> {
+        (0..MAX_TASKS).find(|&tid| self.task_control_blocks[tid].is_none()).ok_or(KernelError::LimitExceeded)
+    }
+
+    /// Moves the task at priority `tid` to the free slot `new_priority`, carrying over its state.
+    /// Returns `KernelError::DoesNotExist` if there is no task `tid`, and `KernelError::NotFound` if `new_priority`
+    /// is out of range.
+    /// Returns `KernelError::AccessDenied` while the task is in a relation recorded by its TaskId which can't be
+    /// carried over: it sleeps, joins or is joined, or waits for or holds a `PiResource`, or is a `yield_to` target.
+    pub fn set_priority(&mut self, tid: usize, new_priority: usize) -> Result<(), KernelError> {
+        if tid >= MAX_TASKS || self.task_control_blocks[tid].is_none() {
+            return Err(KernelError::DoesNotExist);
+        }
+        if new_priority >= MAX_TASKS {
+            return Err(KernelError::NotFound);
+        }
+        if self.is_related(tid) {
+            return Err(KernelError::AccessDenied);
+        }
+        if self.task_control_blocks[new_priority].is_some() {
+            return Err(KernelError::Exists);
+        }
+        self.task_control_blocks[new_priority] = self.task_control_blocks[tid].take();
+        for vector in [&mut self.active_tasks, &mut self.blocked_tasks, &mut self.suspended_tasks, &mut self.yielded_tasks, &mut self.faulted_tasks].iter_mut() {
+            if **vector & (1 << tid) != 0 {
+                **vector = (**vector & !(1 << tid)) | (1 << new_priority);
+            }
+        }
+        #[cfg(feature="fair_resources")]
+        { self.wait_seq[new_priority] = self.wait_seq[tid]; }
+        #[cfg(feature="cpu_stats")]
+        { self.cpu_cycles[new_priority] = core::mem::replace(&mut self.cpu_cycles[tid], 0); }
+        if self.curr_tid == tid {
+            self.curr_tid = new_priority;
+        }
+        Ok(())
+    }
+
+    /// Returns true if the task `tid` sleeps, joins or is joined, waits for or holds a `PiResource`, or is the
+    /// `yield_to` target, i.e. if other state refers to it by its TaskId.
+    fn is_related(&self, tid: usize) -> bool {
+        let mask = 1 << tid;
+        self.sleeping_tasks & mask != 0
+            || self.pi_waiting & mask != 0
+            || self.joiners[tid] != 0
+            || self.joiners.iter().any(|&joiners| joiners & mask != 0)
+            || (0..MAX_TASKS).any(|waiter| self.pi_waiting & (1 << waiter) != 0 && self.pi_holder[waiter] == tid)
+            || self.yield_target == Some(tid)
+    }
+
+    /// Removes the task `tid` from the Kernel and zeroes its stack, so that its slot can be reused.
+    /// The currently running task can't be deleted.
+    pub fn delete_task(&mut self, tid: usize) -> Result<(), KernelError> {
+        if tid >= MAX_TASKS {
+            return Err(KernelError::NotFound);
+        }
+        if tid == self.curr_tid {
+            return Err(KernelError::AccessDenied);
+        }
+        let tcb = self.task_control_blocks[tid].take().ok_or(KernelError::NotFound)?;
+        let mask = !(1 << tid);
+        self.active_tasks &= mask;
+        self.blocked_tasks &= mask;
+        self.suspended_tasks &= mask;
+        self.yielded_tasks &= mask;
+        self.faulted_tasks &= mask;
+        self.sleeping_tasks &= mask;
+        self.pi_waiting &= mask;
+        let stack = unsafe { core::slice::from_raw_parts_mut(tcb.meta.stack_base as *mut u32, tcb.meta.stack_size) };
+        for word in stack.iter_mut() {
+            *word = 0;
+        }
+        Ok(())
+    }
+
+    /// Re-initializes the stack frame of the task `tid` so that it starts again from the top of its handler,
+    /// and clears its blocked, suspended, yielded and faulted state. The task keeps its slot, stack and metadata.
+    /// The currently running task can't be restarted (`KernelError::AccessDenied`).
+    pub fn restart_task(&mut self, tid: usize) -> Result<(), KernelError> {
+        if tid >= MAX_TASKS {
+            return Err(KernelError::NotFound);
+        }
+        if tid == self.curr_tid {
+            return Err(KernelError::AccessDenied);
+        }
+        let meta = self.task_control_blocks[tid].as_ref().ok_or(KernelError::NotFound)?.meta;
+        let handler = meta.handler.ok_or(KernelError::NotFound)?;
+        let stack = unsafe { core::slice::from_raw_parts_mut(meta.stack_base as *mut u32, meta.stack_size) };
+        self.task_control_blocks[tid] = Some(self.create_tcb(meta, stack, handler)?);
+        let mask = !(1 << tid);
+        self.active_tasks &= mask;
+        self.blocked_tasks &= mask;
+        self.suspended_tasks &= mask;
+        self.yielded_tasks &= mask;
+        self.faulted_tasks &= mask;
+        self.sleeping_tasks &= mask;
+        self.pi_waiting &= mask;
+        Ok(())
+    }
+
+    /// Paints the unused stack of the task `tid`, so that its unused stack can be measured. The currently running
+    /// task can't be painted once the scheduler has started, as its stack pointer is not known.
+    #[cfg(feature="stack_guard")]
+    pub fn paint_stack(&mut self, tid: usize) -> Result<(), KernelError> {
+        if tid >= MAX_TASKS {
+            return Err(KernelError::NotFound);
+        }
+        if self.started && tid == self.curr_tid {
+            return Err(KernelError::AccessDenied);
+        }
+        self.task_control_blocks[tid].as_mut().ok_or(KernelError::NotFound)?.paint_stack();
+        Ok(())
+    }
+
+    /// Returns the tasks waiting for the task `tid` to exit, and clears them.
+    pub fn take_joiners(&mut self, tid: usize) -> BooleanVector {
+        match self.joiners.get_mut(tid) {
+            Some(joiners) => core::mem::replace(joiners, 0),
+            None => 0,
+        }
+    }
+
+    /// Suspends the task `tid`, it is not scheduled until resumed. A sleeping task can't be suspended
+    /// (`KernelError::AccessDenied`), as its sleep would end while suspended.
+    pub fn suspend_task(&mut self, tid: usize) -> Result<(), KernelError> {
+        if tid >= MAX_TASKS || self.task_control_blocks[tid].is_none() {
+            return Err(KernelError::NotFound);
+        }
+        if self.sleeping_tasks & (1 << tid) != 0 {
+            return Err(KernelError::AccessDenied);
+        }
+        self.suspended_tasks |= 1 << tid;
+        Ok(())
+    }
+
+    /// Resumes the suspended task `tid`.
+    pub fn resume_task(&mut self, tid: usize) -> Result<(), KernelError> {
+        if tid >= MAX_TASKS || self.task_control_blocks[tid].is_none() {
+            return Err(KernelError::NotFound);
+        }
+        self.suspended_tasks &= !(1 << tid);
+        Ok(())
+    }
+
     /// Appends `tasks_mask` onto `blocked_tasks`.
     pub fn block_tasks(&mut self, tasks_mask: BooleanVector) {
+        #[cfg(feature="fair_resources")]
+        self.stamp_waiters(tasks_mask & !self.blocked_tasks);
         self.blocked_tasks |= tasks_mask;
     }
 
-    /// Removes `tasks_mask` from `blocked_tasks`.
+    /// Removes `tasks_mask` from `blocked_tasks`. With the `fair_resources` feature, of the unblocked tasks
+    /// which preempt the running task, the one which has waited the longest is dispatched first, for one
+    /// scheduling round; otherwise the highest priority one is.
     pub fn unblock_tasks(&mut self, tasks_mask: BooleanVector) {
+        #[cfg(feature="fair_resources")] {
+            let ready = self.active_tasks & !self.suspended_tasks & !self.faulted_tasks & !self.sleeping_tasks & !self.pi_waiting;
+            let above_curr: BooleanVector = if self.curr_tid == IDLE_TASK as usize { !0 } else { !0 << self.curr_tid << 1 };
+            let waiters = tasks_mask & self.blocked_tasks & ready & above_curr;
+            let oldest = (0..MAX_TASKS)
+                .filter(|&tid| waiters & (1 << tid) != 0)
+                .max_by_key(|&tid| self.next_wait_seq.wrapping_sub(self.wait_seq[tid]));
+            if let Some(tid) = oldest {
+                self.yield_target = Some(tid);
+            }
+        }
         self.blocked_tasks &= !tasks_mask;
     }
 
+    /// Records the tasks in `tasks_mask` as starting to wait on a resource now.
+    #[cfg(feature="fair_resources")]
+    fn stamp_waiters(&mut self, tasks_mask: BooleanVector) {
+        for tid in 0..MAX_TASKS {
+            if tasks_mask & (1 << tid) != 0 {
+                self.wait_seq[tid] = self.next_wait_seq;
+                self.next_wait_seq = self.next_wait_seq.wrapping_add(1);
+            }
+        }
+    }
+
     /// Returns the TaskId currently high priority task, which is in ready state.
     /// The highest priority is determined by calculating the most significant bit of boolean vector
     /// corresponding to the tasks in the ready state. The tasks in the ready state can be identified
-    /// by the boolean and of `active_tasks` and boolean not(`blocked_tasks`).
+    /// by the boolean and of `active_tasks` and boolean not(`blocked_tasks`). Tasks which have yielded
+    /// are skipped until the next context switch, and sleeping tasks until they are woken.
+    /// If no task is in the ready state, the idle task (`IDLE_TASK`) is returned.
+    /// If the selected task waits for a `PiResource`, the holder of the resource is dispatched in its place,
+    /// i.e. the holder inherits its priority. If the holder can't run, the waiting tasks are skipped.
     pub fn get_next_tid(&self) -> usize {
-        let mask = self.active_tasks & !self.blocked_tasks;
-        return get_msb(mask).unwrap();
+        let mask = self.active_tasks & !self.blocked_tasks & !self.suspended_tasks & !self.yielded_tasks & !self.faulted_tasks & !self.sleeping_tasks;
+        if let Some(target) = self.yield_target {
+            if mask & !self.pi_waiting & (1 << target) != 0 {
+                return target;
+            }
+        }
+        let mut tid = match get_msb(mask) {
+            Some(tid) => tid,
+            None => return IDLE_TASK as usize,
+        };
+        // Every task waits for at most one holder, hence the chain has fewer than MAX_TASKS links.
+        for _ in 0..MAX_TASKS {
+            if self.pi_waiting & (1 << tid) == 0 {
+                if mask & (1 << tid) != 0 {
+                    return tid;
+                }
+                break;
+            }
+            tid = self.pi_holder[tid];
+        }
+        return get_msb(mask & !self.pi_waiting).unwrap_or(IDLE_TASK as usize);
+    }
+
+    /// Marks the task `tid` as waiting for a `PiResource` held by the task `holder`.
+    pub fn pi_wait(&mut self, tid: usize, holder: usize) {
+        self.pi_holder[tid] = holder;
+        self.pi_waiting |= 1 << tid;
+    }
+
+    /// The tasks in `tasks_mask` stop waiting for a `PiResource`, the priority lent to its holder is restored.
+    pub fn pi_wake(&mut self, tasks_mask: BooleanVector) {
+        self.pi_waiting &= !tasks_mask;
     }
 
-    /// Updates `active_tasks` with `task_mask`.
+    /// Verifies the stack guard word of the currently running task. If it has been overwritten, the task
+    /// is halted so that it is never scheduled again, and `KernelError::StackOverflow` is returned.
+    pub fn check_stack_overflow(&mut self) -> Result<(), KernelError> {
+        let curr_tid = self.curr_tid;
+        let tcb = self.tcb(curr_tid).ok_or(KernelError::NotFound)?;
+        if tcb.is_stack_intact() {
+            return Ok(());
+        }
+        self.halt_curr_task()
+    }
+
+    /// Halts the currently running task after its stack has overflowed, so that it is never scheduled
+    /// again, and returns `KernelError::StackOverflow`.
+    pub fn halt_curr_task(&mut self) -> Result<(), KernelError> {
+        let curr_mask = task_bit(self.curr_tid);
+        self.faulted_tasks |= curr_mask;
+        self.active_tasks &= !curr_mask;
+        Err(KernelError::StackOverflow)
+    }
+
+    /// Returns true if the currently running task is still in the ready state, i.e. it has not blocked,
+    /// exited, gone to sleep or been suspended. The idle task is always ready.
+    pub fn is_curr_ready(&self) -> bool {
+        if self.curr_tid == IDLE_TASK as usize {
+            return true;
+        }
+        let mask = self.active_tasks & !self.blocked_tasks & !self.suspended_tasks & !self.faulted_tasks & !self.sleeping_tasks & !self.pi_waiting;
+        mask & task_bit(self.curr_tid) != 0
+    }
+
+    /// Excludes the currently running task from scheduling until the next context switch.
+    pub fn yield_curr_task(&mut self) {
+        self.yielded_tasks |= task_bit(self.curr_tid);
+        self.switch_requested = true;
+    }
+
+    /// Hands the CPU to the task `tid` at the next context switch, bypassing the priority based selection once.
+    /// Returns `KernelError::NotFound` if the task is not ready, and `KernelError::AccessDenied` if its priority is
+    /// not below that of the currently running task.
+    pub fn yield_to(&mut self, tid: usize) -> Result<(), KernelError> {
+        if tid >= MAX_TASKS {
+            return Err(KernelError::NotFound);
+        }
+        if self.curr_tid == IDLE_TASK as usize || tid >= self.curr_tid {
+            return Err(KernelError::AccessDenied);
+        }
+        let mask = self.active_tasks & !self.blocked_tasks & !self.suspended_tasks & !self.faulted_tasks & !self.sleeping_tasks & !self.pi_waiting;
+        if self.task_control_blocks[tid].is_none() || mask & (1 << tid) == 0 {
+            return Err(KernelError::NotFound);
+        }
+        self.yield_target = Some(tid);
+        self.switch_requested = true;
+        Ok(())
+    }
+
+    /// Puts the currently running task to sleep, it is not scheduled until `wake_tasks` is called for it.
+    /// The idle task never sleeps.
+    pub fn sleep_curr_task(&mut self) {
+        self.sleeping_tasks |= task_bit(self.curr_tid);
+    }
+
+    /// Ends the sleep of the tasks in `tasks_mask`.
+    pub fn wake_tasks(&mut self, tasks_mask: BooleanVector) {
+        self.sleeping_tasks &= !tasks_mask;
+    }
+
+    /// Marks the currently running task as finished. The idle task is never deactivated.
+    pub fn task_exit(&mut self) {
+        self.active_tasks &= !task_bit(self.curr_tid);
+    }
+
+    /// Switches to the task returned by `get_next_tid`, saving the context of the running task and loading that
+    /// of the next one, and ends the yields. On the first call the context of the running task is not saved, as
+    /// the scheduler has not started yet. Returns true if the context has been switched.
+    pub fn switch_to_next(&mut self) -> bool {
+        let curr_tid = self.curr_tid;
+        let next_tid = self.get_next_tid();
+        self.switch_requested = false;
+        if curr_tid == next_tid && self.started {
+            return false;
+        }
+        #[cfg(feature="cpu_stats")]
+        self.account_cycles(read_cycle_counter());
+        if self.started {
+            // The context of a halted task is never restored, hence its overflown stack is not written to.
+            if self.faulted_tasks & task_bit(curr_tid) == 0 {
+                let curr_task = crate::kernel_assert!(Some(self.tcb(curr_tid)), "the running task has no TCB");
+                curr_task.save_context();
+            }
+        } else {
+            self.started = true;
+        }
+        let next_task = crate::kernel_assert!(Some(self.tcb(next_tid)), "the scheduled task has no TCB");
+        set_thread_privileged(!next_task.meta.unprivileged);
+        next_task.load_context();
+
+        self.curr_tid = next_tid;
+        self.yielded_tasks = 0;
+        self.yield_target = None;
+        true
+    }
+
+    /// Returns the idle hook to be called before the upcoming context switch, if the switch
+    /// brackets a system idle period (i.e. it enters or leaves the idle task).
+    pub fn get_idle_hook(&self) -> Option<fn()> {
+        const IDLE: usize = IDLE_TASK as usize;
+        let next_tid = self.get_next_tid();
+        if !self.started {
+            return if next_tid == IDLE { self.idle_enter_hook } else { None };
+        }
+        match (self.curr_tid, next_tid) {
+            (IDLE, IDLE) => None,
+            (_, IDLE) => self.idle_enter_hook,
+            (IDLE, _) => self.idle_exit_hook,
+            _ => None,
+        }
+    }
+
+    /// Returns the low stack hook along with the TaskId and free stack words of the currently running task,
+    /// if it is about to be switched out with less than `low_stack_threshold` words of unused stack.
+    /// Tasks whose stack has not been painted are not checked.
+    #[cfg(feature="stack_guard")]
+    pub fn check_low_stack(&self) -> Option<(fn(TaskId, usize), TaskId, usize)> {
+        let hook = self.low_stack_hook?;
+        if !self.started || self.curr_tid == self.get_next_tid() {
+            return None;
+        }
+        let tcb = self.tcb(self.curr_tid)?;
+        if tcb.meta.painted_words == 0 {
+            return None;
+        }
+        let free_words = tcb.free_stack();
+        if free_words < self.low_stack_threshold {
+            return Some((hook, self.curr_tid as TaskId, free_words));
+        }
+        None
+    }
+
+    /// Attributes the cycles elapsed since the last context switch to the currently running task.
+    /// `now` is the current value of the free-running cycle counter, which is allowed to wrap.
+    #[cfg(feature="cpu_stats")]
+    pub fn account_cycles(&mut self, now: u32) {
+        if self.started {
+            self.cpu_cycles[self.curr_tid] += now.wrapping_sub(self.last_switch_cycles) as u64;
+        }
+        self.last_switch_cycles = now;
+    }
+
+    /// Returns the boolean vector of the tasks which have been created and not deleted.
+    pub fn existing_tasks(&self) -> BooleanVector {
+        (0..MAX_TASKS)
+            .filter(|&tid| self.task_control_blocks[tid].is_some())
+            .fold(0, |mask, tid| mask | 1 << tid)
+    }
+
+    /// Returns the scheduling state of the task `tid`. The idle task is either running or ready.
+    pub fn task_state(&self, tid: usize) -> TaskState {
+        if tid == IDLE_TASK as usize && self.idle_tcb.is_some() {
+            return if self.started && self.curr_tid == tid { TaskState::Running } else { TaskState::Ready };
+        }
+        if tid >= MAX_TASKS || self.task_control_blocks[tid].is_none() {
+            return TaskState::Nonexistent;
+        }
+        let mask = 1 << tid;
+        if (self.faulted_tasks | self.suspended_tasks) & mask != 0 {
+            TaskState::Suspended
+        } else if self.started && self.curr_tid == tid {
+            TaskState::Running
+        } else if self.active_tasks & mask == 0 {
+            TaskState::Waiting
+        } else if self.blocked_tasks & mask != 0 {
+            TaskState::Blocked
+        } else if self.sleeping_tasks & mask != 0 {
+            TaskState::Sleeping
+        } else {
+            TaskState::Ready
+        }
+    }
+
+    /// Verifies the consistency of the task state vectors and the task control blocks. `ceiling_mask` holds the
+    /// tasks up to the system ceiling, which a locked resource blocks whether they exist and are active or not.
+    pub fn check_invariants(&self, ceiling_mask: BooleanVector) -> Result<(), InvariantViolation> {
+        let blocked = self.blocked_tasks & !ceiling_mask;
+        for tid in 0..MAX_TASKS {
+            let mask = 1 << tid;
+            if self.task_control_blocks[tid].is_none() {
+                if self.active_tasks & mask != 0 {
+                    return Err(InvariantViolation::ActiveWithoutTcb(tid as TaskId));
+                }
+                let state = blocked | self.suspended_tasks | self.yielded_tasks | self.faulted_tasks | self.sleeping_tasks | self.pi_waiting;
+                if state & mask != 0 {
+                    return Err(InvariantViolation::StateWithoutTcb(tid as TaskId));
+                }
+            }
+            if blocked & !self.active_tasks & mask != 0 {
+                return Err(InvariantViolation::BlockedWhileInactive(tid as TaskId));
+            }
+            if self.sleeping_tasks & self.suspended_tasks & mask != 0 {
+                return Err(InvariantViolation::SleepingWhileSuspended(tid as TaskId));
+            }
+            if self.faulted_tasks & self.active_tasks & mask != 0 {
+                return Err(InvariantViolation::FaultedWhileActive(tid as TaskId));
+            }
+        }
+        if self.state != KernelState::Uninit && self.idle_tcb.is_none() {
+            return Err(InvariantViolation::IdleTaskMissing);
+        }
+        if self.started && self.curr_tid != IDLE_TASK as usize {
+            if self.curr_tid >= MAX_TASKS || self.task_control_blocks[self.curr_tid].is_none() {
+                return Err(InvariantViolation::InvalidRunningTask(self.curr_tid as TaskId));
+            }
+            if self.active_tasks & (1 << self.curr_tid) == 0 {
+                return Err(InvariantViolation::RunningTaskInactive(self.curr_tid as TaskId));
+            }
+        }
+        Ok(())
+    }
+
+    /// Updates `active_tasks` with `task_mask`. Tasks which don't exist (e.g. deleted tasks which are still
+    /// in the task masks of primitives) are ignored, hence they are never dispatched; so are halted tasks, until
+    /// they are restarted.
     pub fn release(&mut self, tasks_mask: BooleanVector) {
         #[cfg(feature = "task_monitor")] {
-            for i in 0..32 {
+            for i in 0..MAX_TASKS {
                 if (tasks_mask & 1<<i) > 0 {
-                    set_deadline(i as TaskId, self.task_control_blocks[i].unwrap().deadline)
+                    if let Some(tcb) = self.task_control_blocks[i] {
+                        set_deadline(i as TaskId, tcb.meta.deadline)
+                    }
                 }
             }
         }
-        self.active_tasks |= tasks_mask;
+        #[cfg(feature="fair_resources")]
+        self.stamp_waiters(tasks_mask & !self.active_tasks & self.blocked_tasks);
+        self.active_tasks |= tasks_mask & self.existing_tasks() & !self.faulted_tasks;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::system::pi_stack::PiStack;
+    use crate::system::resource::get_pi_mask;
+
+    fn task() -> ! {
+        loop {}
+    }
+
+    /// Returns an initialized Scheduler holding the idle task and the tasks `tasks`.
+    fn scheduler_with(tasks: &[usize]) -> Scheduler {
+        let mut scheduler = Scheduler::new();
+        scheduler.init().unwrap();
+        for &tid in tasks {
+            let stack = Box::leak(vec![0u32; 64].into_boxed_slice());
+            #[cfg(feature="task_monitor")]
+            scheduler.create_task(tid, 100, stack, task).unwrap();
+            #[cfg(not(feature="task_monitor"))]
+            scheduler.create_task(tid, stack, task).unwrap();
+        }
+        scheduler
+    }
+
+    #[test]
+    fn resource_lock_defers_competing_task() {
+        let mut scheduler = scheduler_with(&[1, 2]);
+        let mut pi_stack = PiStack::new();
+        scheduler.release(1 << 1);
+        scheduler.curr_tid = scheduler.get_next_tid();
+        assert_eq!(scheduler.curr_tid, 1);
+
+        // Task 1 locks a resource shared with task 2, as `Resource::lock` does.
+        pi_stack.push_stack(2).unwrap();
+        scheduler.block_tasks(get_pi_mask(2) & !(1 << 1));
+        scheduler.release(1 << 2);
+        assert_eq!(scheduler.get_next_tid(), 1);
+
+        // Unlocking lets task 2 preempt the holder.
+        pi_stack.pop_stack().unwrap();
+        scheduler.unblock_tasks(get_pi_mask(2));
+        assert!(pi_stack.is_empty());
+        assert_eq!(scheduler.get_next_tid(), 2);
+    }
+
+    #[test]
+    fn user_task_occupies_former_idle_slot() {
+        let mut scheduler = scheduler_with(&[0]);
+        assert_eq!(scheduler.free_slot().unwrap(), 1);
+        scheduler.release(1 << 0);
+        assert_eq!(scheduler.get_next_tid(), 0);
+        assert!(scheduler.check_invariants(0).is_ok());
+    }
+
+    #[test]
+    fn idle_runs_when_no_task_is_ready() {
+        let mut scheduler = scheduler_with(&[0, 1]);
+        assert_eq!(scheduler.get_next_tid(), IDLE_TASK as usize);
+        scheduler.release(1 << 0 | 1 << 1);
+        scheduler.block_tasks(1 << 1);
+        scheduler.suspend_task(0).unwrap();
+        assert_eq!(scheduler.get_next_tid(), IDLE_TASK as usize);
+        assert_eq!(scheduler.task_state(IDLE_TASK as usize), TaskState::Ready);
+    }
+    #[test]
+    fn blocked_task_must_be_active_unless_below_the_ceiling() {
+        let mut scheduler = scheduler_with(&[1]);
+        scheduler.block_tasks(1 << 1);
+        assert_eq!(scheduler.check_invariants(0), Err(InvariantViolation::BlockedWhileInactive(1)));
+        assert_eq!(scheduler.check_invariants(get_pi_mask(1)), Ok(()));
+        scheduler.release(1 << 1);
+        assert_eq!(scheduler.check_invariants(0), Ok(()));
+    }
+
+    #[test]
+    fn sleeping_task_is_not_suspended() {
+        let mut scheduler = scheduler_with(&[1]);
+        scheduler.release(1 << 1);
+        scheduler.curr_tid = 1;
+        scheduler.sleep_curr_task();
+        assert!(matches!(scheduler.suspend_task(1), Err(KernelError::AccessDenied)));
+        assert_eq!(scheduler.check_invariants(0), Ok(()));
+        scheduler.suspended_tasks |= 1 << 1;
+        assert_eq!(scheduler.check_invariants(0), Err(InvariantViolation::SleepingWhileSuspended(1)));
+    }
+
+    #[test]
+    fn halted_task_stays_inactive() {
+        let mut scheduler = scheduler_with(&[1]);
+        scheduler.release(1 << 1);
+        scheduler.curr_tid = 1;
+        assert!(matches!(scheduler.halt_curr_task(), Err(KernelError::StackOverflow)));
+        scheduler.curr_tid = IDLE_TASK as usize;
+        scheduler.release(1 << 1);
+        assert_eq!(scheduler.check_invariants(0), Ok(()));
+        scheduler.active_tasks |= 1 << 1;
+        assert_eq!(scheduler.check_invariants(0), Err(InvariantViolation::FaultedWhileActive(1)));
+    }
+
+    #[test]
+    fn state_needs_a_tcb() {
+        let mut scheduler = scheduler_with(&[1]);
+        scheduler.block_tasks(get_pi_mask(3));
+        scheduler.release(1 << 1);
+        assert_eq!(scheduler.check_invariants(get_pi_mask(3)), Ok(()));
+        scheduler.suspended_tasks |= 1 << 3;
+        assert_eq!(scheduler.check_invariants(get_pi_mask(3)), Err(InvariantViolation::StateWithoutTcb(3)));
+        scheduler.suspended_tasks &= !(1 << 3);
+        scheduler.active_tasks |= 1 << 3;
+        assert_eq!(scheduler.check_invariants(get_pi_mask(3)), Err(InvariantViolation::ActiveWithoutTcb(3)));
+    }
+
+    #[test]
+    fn restarted_task_starts_from_its_handler() {
+        let mut scheduler = scheduler_with(&[1]);
+        let initial_stack_pointer = scheduler.tcb(1).unwrap().stack_pointer;
+        let meta = scheduler.tcb(1).unwrap().meta;
+        let stack = unsafe { core::slice::from_raw_parts_mut(meta.stack_base as *mut u32, meta.stack_size) };
+        scheduler.release(1 << 1);
+        scheduler.curr_tid = scheduler.get_next_tid();
+        assert!(matches!(scheduler.restart_task(1), Err(KernelError::AccessDenied)));
+
+        // The task runs, clobbering its initial frame, and then faults.
+        stack[1..].fill(0xdead_beef);
+        scheduler.task_control_blocks[1].as_mut().unwrap().stack_pointer = meta.stack_base;
+        scheduler.halt_curr_task().unwrap_err();
+        scheduler.curr_tid = scheduler.get_next_tid();
+        assert_eq!(scheduler.curr_tid, IDLE_TASK as usize);
+        assert!(matches!(scheduler.restart_task(2), Err(KernelError::NotFound)));
+
+        scheduler.restart_task(1).unwrap();
+        assert_eq!(scheduler.task_state(1), TaskState::Waiting);
+        assert_eq!(scheduler.tcb(1).unwrap().stack_pointer, initial_stack_pointer);
+        assert_eq!(stack[stack.len() - 2], task as fn() -> ! as usize as u32);
+        assert_eq!(scheduler.tcb(1).unwrap().meta.handler.map(|handler| handler as usize), Some(task as fn() -> ! as usize));
+        scheduler.release(1 << 1);
+        assert_eq!(scheduler.get_next_tid(), 1);
+    }
+
+    #[test]
+    fn initial_stack_pointer_addresses_the_saved_registers() {
+        let scheduler = scheduler_with(&[1]);
+        let tcb = scheduler.tcb(1).unwrap();
+        assert_eq!(tcb.stack_pointer, tcb.meta.stack_base + (tcb.meta.stack_size - 16) * 4);
+        let idle = scheduler.tcb(IDLE_TASK as usize).unwrap();
+        assert_eq!(idle.stack_pointer, idle.meta.stack_base + (idle.meta.stack_size - 16) * 4);
+    }
+
+    #[test]
+    #[cfg(feature="fpu")]
+    fn fpu_frame_lies_above_the_initial_stack_pointer() {
+        let mut scheduler = scheduler_with(&[]);
+        let mut meta = SchedMeta::new();
+        meta.uses_fpu = true;
+        let stack = Box::leak(vec![0u32; 128].into_boxed_slice());
+        scheduler.create_task_with_meta(1, meta, stack, task).unwrap();
+        let tcb = scheduler.tcb(1).unwrap();
+        // 18 words of the extended exception frame, and S16-S31 saved along with R4-R11.
+        assert_eq!(tcb.stack_pointer, tcb.meta.stack_base + (128 - 16 - 18 - 16) * 4);
     }
 }
@@ -1,9 +1,9 @@
 //! # Software synchronization bus definition
 //!
 use core::cell::RefCell;
-use crate::system::scheduler::BooleanVector;
+use crate::system::scheduler::{task_bit, BooleanVector};
 use crate::KernelError;
-use crate::kernel::tasks::{get_curr_tid, release, schedule};
+use crate::kernel::tasks::{current_task, release, schedule};
 use crate::utils::arch::critical_section;
 
 #[cfg(feature = "system_logger")]
@@ -28,26 +28,31 @@ impl Semaphore {
 
     /// Signals the semaphore, all tasks specified in semaphore::flags can test for it and all tasks in semaphore::tasks are released
     pub fn signal_and_release(&'static self, tasks_mask: BooleanVector) {
+        self.signal_and_release_to(tasks_mask, self.tasks)
+    }
+
+    /// Like `signal_and_release`, but only the tasks in `release_mask` are released.
+    pub fn signal_and_release_to(&'static self, tasks_mask: BooleanVector, release_mask: BooleanVector) {
         critical_section(|_| {
             let flags: &mut BooleanVector = &mut self.flags.borrow_mut();
             *flags |= tasks_mask;
-            release(self.tasks);
+            release(release_mask);
             #[cfg(feature = "system_logger")] {
                 if logging::get_semaphore_signal() {
-                    logging::report(LogEventType::SemaphoreSignal(*flags, self.tasks));
+                    logging::report(LogEventType::SemaphoreSignal(*flags, release_mask));
                 }
             }
             schedule();
         })
     }
 
-    /// Checks if the flag was enabled for the currently running task.
-    pub fn test_and_reset(&'static self) -> Result<bool, KernelError> {
+    /// Checks if the flag was enabled for the currently running task. The idle task has no flag.
+    pub fn test_and_reset(&self) -> Result<bool, KernelError> {
         critical_section(|_| {
-            let curr_tid = get_curr_tid() as u32;
-            let curr_tid_mask = 1 << curr_tid;
+            let curr_tid = current_task() as u32;
+            let curr_tid_mask = task_bit(curr_tid as usize);
             let flags: &mut BooleanVector = &mut self.flags.borrow_mut();
-            if *flags & curr_tid_mask == curr_tid_mask {
+            if curr_tid_mask != 0 && *flags & curr_tid_mask == curr_tid_mask {
                 *flags &= !curr_tid_mask;
                 #[cfg(feature = "system_logger")] {
                     if logging::get_semaphore_reset() {
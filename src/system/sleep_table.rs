@@ -0,0 +1,167 @@
+//! # Sleep Table
+//!
+//! Defines Data-structures to track tasks sleeping until an absolute tick.
+//!
+//! It is the single timeout list of the Kernel: `sleep`, and every call blocking with a timeout (e.g.
+//! `receive_timeout`, `BinarySemaphore::take`, `join`), sleeps until its deadline in it. Hence the SysTick
+//! handler sweeps one table of `MAX_TASKS` entries per tick, whichever features are enabled.
+
+use crate::config::MAX_TASKS;
+use crate::system::scheduler::{BooleanVector, TaskId};
+
+/// Why the last sleep of a task ended.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum WakeReason {
+    /// The sleep has not ended yet, or it has been cancelled.
+    Pending,
+    /// The wake tick was reached.
+    TimedOut,
+    /// A primitive handed the task what it waits for, with `wake`.
+    Woken,
+}
+
+/// The sleep of a single task.
+#[derive(Clone, Copy)]
+struct SleepEntry {
+    /// The tick until which the task sleeps, `None` once the sleep has ended.
+    wake_tick: Option<u32>,
+    /// Why the sleep ended, it is set by whoever ends it.
+    reason: WakeReason,
+}
+
+/// Holds the tick until which each task is sleeping, and why its sleep ended. The entries are indexed by TaskId.
+pub struct SleepTable {
+    entries: [SleepEntry; MAX_TASKS],
+}
+
+impl SleepTable {
+    pub const fn new() -> Self {
+        Self {
+            entries: [SleepEntry { wake_tick: None, reason: WakeReason::Pending }; MAX_TASKS],
+        }
+    }
+
+    /// Marks the task `tid` as sleeping until the absolute tick `wake_tick`.
+    pub fn set_wake_tick(&mut self, tid: TaskId, wake_tick: u32) {
+        self.entries[tid as usize] = SleepEntry { wake_tick: Some(wake_tick), reason: WakeReason::Pending };
+    }
+
+    /// Ends the sleep of the task `tid` because it was handed what it waits for. The reason is recorded even
+    /// if its wake tick has been reached meanwhile, as the task has been handed it anyway. A task which has not
+    /// slept since its entry was last cleared is left untouched.
+    pub fn wake(&mut self, tid: TaskId) {
+        let entry = &mut self.entries[tid as usize];
+        if entry.wake_tick.is_some() || entry.reason == WakeReason::TimedOut {
+            *entry = SleepEntry { wake_tick: None, reason: WakeReason::Woken };
+        }
+    }
+
+    /// Removes the entry of the task `tid`, and returns why its last sleep ended.
+    pub fn clear_wake_tick(&mut self, tid: TaskId) -> WakeReason {
+        let entry = &mut self.entries[tid as usize];
+        let reason = entry.reason;
+        *entry = SleepEntry { wake_tick: None, reason: WakeReason::Pending };
+        reason
+    }
+
+    /// Returns the number of ticks from `curr_time` until the nearest wake tick.
+    pub fn ticks_to_next_wake(&self, curr_time: u32) -> Option<u32> {
+        self.entries.iter()
+            .filter_map(|entry| entry.wake_tick.map(|wake_tick| wake_tick.wrapping_sub(curr_time)))
+            .min()
+    }
+
+    /// Returns the boolean vector of tasks whose wake tick has been reached at `curr_time`
+    /// and ends their sleep as timed out. The comparison uses wrapping arithmetic, hence it stays
+    /// correct across timer counter overflow.
+    pub fn sweep(&mut self, curr_time: u32) -> BooleanVector {
+        let mut tasks_mask: BooleanVector = 0;
+        for (tid, entry) in self.entries.iter_mut().enumerate() {
+            if let Some(wake_tick) = entry.wake_tick {
+                if tick_reached(curr_time, wake_tick) {
+                    *entry = SleepEntry { wake_tick: None, reason: WakeReason::TimedOut };
+                    tasks_mask |= 1 << tid;
+                }
+            }
+        }
+        tasks_mask
+    }
+}
+
+/// Returns true if `curr_time` is at or past `tick`, accounting for counter overflow.
+pub fn tick_reached(curr_time: u32, tick: u32) -> bool {
+    curr_time.wrapping_sub(tick) as i32 >= 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn concurrent_timeouts_expire_in_order() {
+        let mut table = SleepTable::new();
+        table.set_wake_tick(3, 30);
+        table.set_wake_tick(1, 10);
+        table.set_wake_tick(2, 20);
+        assert_eq!(table.ticks_to_next_wake(0), Some(10));
+        assert_eq!(table.sweep(9), 0);
+        assert_eq!(table.sweep(10), 1 << 1);
+        assert_eq!(table.ticks_to_next_wake(10), Some(10));
+        assert_eq!(table.sweep(29), 1 << 2);
+        assert_eq!(table.sweep(30), 1 << 3);
+        assert_eq!(table.ticks_to_next_wake(30), None);
+        for tid in 1..4 {
+            assert_eq!(table.clear_wake_tick(tid), WakeReason::TimedOut);
+        }
+    }
+
+    #[test]
+    fn timeouts_expiring_on_the_same_tick_are_swept_together() {
+        let mut table = SleepTable::new();
+        table.set_wake_tick(1, 5);
+        table.set_wake_tick(4, 5);
+        assert_eq!(table.sweep(5), (1 << 1) | (1 << 4));
+        assert_eq!(table.sweep(6), 0);
+    }
+
+    #[test]
+    fn wake_is_recorded_and_not_swept() {
+        let mut table = SleepTable::new();
+        table.set_wake_tick(1, 10);
+        table.set_wake_tick(2, 10);
+        table.wake(1);
+        assert_eq!(table.sweep(10), 1 << 2);
+        assert_eq!(table.clear_wake_tick(1), WakeReason::Woken);
+        assert_eq!(table.clear_wake_tick(2), WakeReason::TimedOut);
+    }
+
+    #[test]
+    fn wake_after_timeout_is_recorded() {
+        let mut table = SleepTable::new();
+        table.set_wake_tick(1, 10);
+        assert_eq!(table.sweep(10), 1 << 1);
+        table.wake(1);
+        assert_eq!(table.clear_wake_tick(1), WakeReason::Woken);
+    }
+
+    #[test]
+    fn wake_of_a_task_which_did_not_sleep_is_ignored() {
+        let mut table = SleepTable::new();
+        table.wake(1);
+        assert_eq!(table.clear_wake_tick(1), WakeReason::Pending);
+        table.set_wake_tick(1, 10);
+        assert_eq!(table.clear_wake_tick(1), WakeReason::Pending);
+        assert_eq!(table.sweep(10), 0);
+    }
+
+    #[test]
+    fn timeouts_expire_across_counter_overflow() {
+        let mut table = SleepTable::new();
+        table.set_wake_tick(1, u32::MAX);
+        table.set_wake_tick(2, 4);
+        assert_eq!(table.ticks_to_next_wake(u32::MAX - 1), Some(1));
+        assert_eq!(table.sweep(u32::MAX), 1 << 1);
+        assert_eq!(table.sweep(3), 0);
+        assert_eq!(table.sweep(4), 1 << 2);
+    }
+}
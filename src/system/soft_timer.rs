@@ -0,0 +1,190 @@
+//! # Software Timers
+//!
+//! Defines Data-structures to manage one-shot and periodic software timers.
+
+use crate::config::MAX_SOFT_TIMERS;
+use crate::KernelError;
+
+/// Identifies a software timer by its slot in the table and the generation of the slot, which is advanced
+/// each time the slot is reused; hence the id of a cancelled or fired timer never refers to a later timer in
+/// the same slot.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SoftTimerId {
+    slot: usize,
+    generation: u32,
+}
+
+/// The generations wrap within the bits of the packed id above the slot.
+const GENERATION_MASK: u32 = 0xFF_FFFF;
+
+impl SoftTimerId {
+    /// Packs the id into a word, e.g. the argument of a deferred work item.
+    pub fn to_raw(self) -> u32 {
+        self.generation << 8 | self.slot as u32
+    }
+
+    /// Unpacks an id packed by `to_raw`.
+    pub fn from_raw(raw: u32) -> Self {
+        Self { slot: (raw & 0xFF) as usize, generation: raw >> 8 }
+    }
+}
+
+/// Software timer descriptor
+#[derive(Clone, Copy)]
+pub struct SoftTimerEntry {
+    handler: fn(),
+    /// Ticks remaining until the timer fires.
+    remaining: u32,
+    /// The period with which the timer is re-armed, `None` for one-shot timers.
+    period: Option<u32>,
+    /// True while the timer is counting down. A fired one-shot timer stays in the table,
+    /// disarmed, until its handler has been dispatched.
+    armed: bool,
+}
+
+/// Holds all software timers.
+pub struct SoftTimerTable {
+    timers: [Option<SoftTimerEntry>; MAX_SOFT_TIMERS],
+    /// The generation of each slot, see `SoftTimerId`.
+    generations: [u32; MAX_SOFT_TIMERS],
+}
+
+impl SoftTimerTable {
+    pub const fn new() -> Self {
+        // The slot of a timer must fit in the low byte of its packed id.
+        [()][(MAX_SOFT_TIMERS > 256) as usize];
+        Self {
+            timers: [None; MAX_SOFT_TIMERS],
+            generations: [0; MAX_SOFT_TIMERS],
+        }
+    }
+
+    /// Creates a timer which fires after `ticks` ticks and then every `period` ticks, if a period is given.
+    pub fn create(&mut self, ticks: u32, period: Option<u32>, handler: fn()) -> Result<SoftTimerId, KernelError> {
+        let id = self.timers.iter().position(|timer| timer.is_none()).ok_or(KernelError::LimitExceeded)?;
+        self.timers[id] = Some(SoftTimerEntry {
+            handler,
+            remaining: ticks.max(1),
+            period: period.map(|period| period.max(1)),
+            armed: true,
+        });
+        self.generations[id] = self.generations[id].wrapping_add(1) & GENERATION_MASK;
+        Ok(self.id_of(id))
+    }
+
+    /// Returns the id of the timer in the slot `slot`.
+    pub fn id_of(&self, slot: usize) -> SoftTimerId {
+        SoftTimerId { slot, generation: self.generations[slot] }
+    }
+
+    /// Returns the timer `id`, unless its slot has been reused since.
+    fn get_mut(&mut self, id: SoftTimerId) -> Option<&mut Option<SoftTimerEntry>> {
+        if *self.generations.get(id.slot)? != id.generation {
+            return None;
+        }
+        self.timers.get_mut(id.slot)
+    }
+
+    /// Removes a timer; its handler won't be dispatched even if it has already fired.
+    /// Returns `KernelError::NotFound` if the timer no longer exists, even if its slot holds a later timer.
+    pub fn cancel(&mut self, id: SoftTimerId) -> Result<(), KernelError> {
+        self.get_mut(id).and_then(|timer| timer.take()).ok_or(KernelError::NotFound)?;
+        Ok(())
+    }
+
+    /// Returns the number of ticks until the nearest armed timer fires.
+    pub fn ticks_to_next_expiry(&self) -> Option<u32> {
+        self.timers.iter()
+            .filter_map(|timer| timer.as_ref())
+            .filter(|timer| timer.armed)
+            .map(|timer| timer.remaining)
+            .min()
+    }
+
+    /// Advances all armed timers by `ticks` ticks, and returns a bit vector of the timers which fired.
+    /// Periodic timers are re-armed in the same sweep, hence no ticks are lost.
+    pub fn sweep(&mut self, ticks: u32) -> u32 {
+        let mut fired = 0;
+        for id in 0..MAX_SOFT_TIMERS {
+            if let Some(ref mut timer) = self.timers[id] {
+                if !timer.armed {
+                    continue;
+                }
+                timer.remaining = timer.remaining.saturating_sub(ticks);
+                if timer.remaining == 0 {
+                    fired |= 1 << id;
+                    match timer.period {
+                        Some(period) => timer.remaining = period,
+                        None => timer.armed = false,
+                    }
+                }
+            }
+        }
+        fired
+    }
+
+    /// Returns the handler of a fired timer. One-shot timers are removed from the table. Returns `None` if the
+    /// timer has been cancelled since it fired, even if its slot holds a later timer.
+    pub fn take_handler(&mut self, id: SoftTimerId) -> Option<fn()> {
+        let timer = self.get_mut(id)?;
+        let handler = timer.as_ref()?.handler;
+        if !timer.as_ref()?.armed {
+            *timer = None;
+        }
+        Some(handler)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn handler() {}
+
+    #[test]
+    fn one_shot_timer_fires_once() {
+        let mut table = SoftTimerTable::new();
+        let id = table.create(3, None, handler).unwrap();
+        assert_eq!(table.ticks_to_next_expiry(), Some(3));
+        assert_eq!(table.sweep(2), 0);
+        assert_eq!(table.sweep(1), 1);
+        assert_eq!(table.ticks_to_next_expiry(), None);
+        assert!(table.take_handler(id).is_some());
+        assert!(table.take_handler(id).is_none());
+    }
+
+    #[test]
+    fn periodic_timer_is_rearmed() {
+        let mut table = SoftTimerTable::new();
+        let id = table.create(2, Some(2), handler).unwrap();
+        assert_eq!(table.sweep(2), 1);
+        assert!(table.take_handler(id).is_some());
+        assert_eq!(table.sweep(2), 1);
+        assert!(table.cancel(id).is_ok());
+        assert_eq!(table.sweep(2), 0);
+    }
+
+    #[test]
+    fn table_is_bounded() {
+        let mut table = SoftTimerTable::new();
+        for _ in 0..MAX_SOFT_TIMERS {
+            table.create(1, None, handler).unwrap();
+        }
+        assert!(matches!(table.create(1, None, handler), Err(KernelError::LimitExceeded)));
+    }
+
+    #[test]
+    fn stale_id_does_not_refer_to_reused_slot() {
+        let mut table = SoftTimerTable::new();
+        let stale = table.create(1, None, handler).unwrap();
+        assert_eq!(table.sweep(1), 1);
+        table.cancel(stale).unwrap();
+        let id = table.create(1, Some(1), handler).unwrap();
+        assert_eq!(table.sweep(1), 1);
+        assert!(matches!(table.cancel(stale), Err(KernelError::NotFound)));
+        assert!(table.take_handler(stale).is_none());
+        assert!(table.take_handler(SoftTimerId::from_raw(stale.to_raw())).is_none());
+        assert!(table.take_handler(SoftTimerId::from_raw(id.to_raw())).is_some());
+        assert!(table.cancel(id).is_ok());
+    }
+}
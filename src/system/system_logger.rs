@@ -1,18 +1,19 @@
 use crate::system::scheduler::{BooleanVector,TaskId};
 use crate::config::MAX_LOGS;
+use crate::system::log_sink::LogSink;
 use core::fmt;
 
 #[cfg(any(feature = "events_32", feature = "events_16", feature = "events_64"))]
 use crate::system::event::EventId;
 
-pub type Logs = [Option<LogEvent>; MAX_LOGS];
+pub type Logs = [Option<LogRecord>; MAX_LOGS];
 
 #[derive(Clone, Copy)]
 pub enum LogEventType {
     ReleaseTasks(BooleanVector),
     BlockTasks(BooleanVector),
     UnblockTasks(BooleanVector),
-    TaskExit(BooleanVector),
+    TaskExit(TaskId),
     ResourceLock(TaskId),
     ResourceUnlock(TaskId),
     MessageBroadcast(BooleanVector),
@@ -20,29 +21,62 @@ pub enum LogEventType {
     SemaphoreSignal(BooleanVector,BooleanVector),
     SemaphoreReset(TaskId),
     DeadlineExpired(TaskId,u32),
+    /// A task has panicked and been halted, along with the source line of the panic.
+    TaskPanic(TaskId,u32),
+    /// A device interrupt, by its number, has released the tasks registered for it.
+    IrqRelease(u8,BooleanVector),
     #[cfg(any(feature = "events_32", feature = "events_16", feature = "events_64"))]
     TimerEvent(EventId),
 }
 
+/// A log event along with the Kernel time (in ticks) at which it was reported.
+impl LogEventType {
+    /// Returns the boolean vector of tasks the event concerns. Events which do not concern any
+    /// particular task (timer events) concern all tasks.
+    pub fn tasks(&self) -> BooleanVector {
+        match *self {
+            LogEventType::ReleaseTasks(tasks_mask) => tasks_mask,
+            LogEventType::BlockTasks(tasks_mask) => tasks_mask,
+            LogEventType::UnblockTasks(tasks_mask) => tasks_mask,
+            LogEventType::TaskExit(task_id) => 1 << task_id,
+            LogEventType::ResourceLock(task_id) => 1 << task_id,
+            LogEventType::ResourceUnlock(task_id) => 1 << task_id,
+            LogEventType::MessageBroadcast(recievers) => recievers,
+            LogEventType::MessageRecieve(task_id) => 1 << task_id,
+            LogEventType::SemaphoreSignal(tasks_released, tasks_notified) => tasks_released | tasks_notified,
+            LogEventType::SemaphoreReset(task_id) => 1 << task_id,
+            LogEventType::DeadlineExpired(task_id, _) => 1 << task_id,
+            LogEventType::TaskPanic(task_id, _) => 1 << task_id,
+            LogEventType::IrqRelease(_, tasks_mask) => tasks_mask,
+            #[cfg(any(feature = "events_32", feature = "events_16", feature = "events_64"))]
+            LogEventType::TimerEvent(_) => !0,
+        }
+    }
+}
+
 #[derive(Clone, Copy, Debug)]
-pub struct LogEvent {
-    pub event_type: LogEventType,
-    pub timestamp: u32
+pub struct LogRecord {
+    /// Kernel time at which the event was reported, on the same clock as `logging::now_ticks`.
+    pub tick: u32,
+    pub event: LogEventType,
 }
 
-impl LogEvent {
-    pub fn new(event_type: LogEventType, timestamp: u32) -> Self {
+impl LogRecord {
+    pub const fn new(event: LogEventType, tick: u32) -> Self {
         Self {
-            event_type,
-            timestamp
+            tick,
+            event,
         }
     }
 }
 
+/// An in-RAM ring buffer of log events, the oldest event is overwritten when it is full.
 pub struct SystemLogger {
     logs: Logs,
+    /// Points to the oldest event in the buffer.
     start: usize,
-    end: usize,
+    /// Number of events in the buffer.
+    len: usize,
     pub release_log: bool,
     pub block_tasks_log: bool,
     pub unblock_tasks_log: bool,
@@ -56,15 +90,19 @@ pub struct SystemLogger {
     
     #[cfg(any(feature = "events_32", feature = "events_16", feature = "events_64"))]
     pub timer_event_log: bool,
+    /// Sink for formatted text logs, which are dropped if it is not set.
+    pub text_sink: Option<fn(&str)>,
+    /// Sink to which log events are written as they are reported, they are buffered if it is not set.
+    pub sink: Option<&'static dyn LogSink>,
+    /// Boolean vector of tasks whose events are recorded, all tasks by default.
+    pub task_filter: BooleanVector,
 }
-// use a circular queue instead of this crap.
-// ensure the handler is not None in start_kernel.
 impl SystemLogger {
     pub const fn new() -> Self {
         Self {
             logs: [None; MAX_LOGS],
             start: 0,
-            end: 0,
+            len: 0,
             release_log : false,
             block_tasks_log : false,
             unblock_tasks_log : false,
@@ -78,13 +116,18 @@ impl SystemLogger {
             
             #[cfg(any(feature = "events_32", feature = "events_16", feature = "events_64"))]
             timer_event_log : false,
+            text_sink: None,
+            sink: None,
+            task_filter: !0,
         }
     }
-    pub fn push(&mut self, event: LogEvent) {
-        self.logs[self.end] = Some(event);
-        self.end = (self.end+1)%MAX_LOGS;
-        if self.start == self.end {
-            self.start = (self.start+1)%MAX_LOGS;
+    /// Appends an event to the buffer, overwriting the oldest event if the buffer is full.
+    pub fn push(&mut self, event: LogRecord) {
+        self.logs[(self.start + self.len) % MAX_LOGS] = Some(event);
+        if self.len == MAX_LOGS {
+            self.start = (self.start + 1) % MAX_LOGS;
+        } else {
+            self.len += 1;
         }
     }
     pub fn clear(&mut self) {
@@ -92,15 +135,31 @@ impl SystemLogger {
             *val = None;
         }
         self.start = 0;
-        self.end = 0;
+        self.len = 0;
     }
-    pub fn pop(&mut self) -> Option<LogEvent> {
-        let pos = self.start;
-        let val = self.logs[pos];
-        self.logs[pos] = None;
-        self.start = (self.start+1)%MAX_LOGS;
+    /// Removes the oldest event from the buffer.
+    pub fn pop(&mut self) -> Option<LogRecord> {
+        if self.len == 0 {
+            return None;
+        }
+        let val = self.logs[self.start].take();
+        self.start = (self.start + 1) % MAX_LOGS;
+        self.len -= 1;
         return val;
     }
+    /// Moves the oldest events from the buffer into `out`, and returns the number of events moved.
+    /// Events which do not fit into `out` are left in the buffer for a later drain.
+    pub fn drain_logs(&mut self, out: &mut [LogRecord]) -> usize {
+        let mut count = 0;
+        while count < out.len() {
+            match self.pop() {
+                Some(event) => out[count] = event,
+                None => break,
+            }
+            count += 1;
+        }
+        count
+    }
 }
 
 impl fmt::Debug for LogEventType {
@@ -117,6 +176,8 @@ impl fmt::Debug for LogEventType {
             LogEventType::SemaphoreSignal(tasks_released,tasks_notified) => write!(f, "SemaphoreSignal"),
             LogEventType::SemaphoreReset(task_id) => write!(f, "SemaphoreReset"),
             LogEventType::DeadlineExpired(TaskId, u32) => write!(f, "DeadlineExpired"),
+            LogEventType::TaskPanic(task_id, line) => write!(f, "TaskPanic: task {} at line {}", task_id, line),
+            LogEventType::IrqRelease(irqn, tasks_mask) => write!(f, "IrqRelease: irq {} released {}", irqn, tasks_mask),
             #[cfg(any(feature = "events_32", feature = "events_16", feature = "events_64"))]
             LogEventType::TimerEvent(EventId) => write!(f, "TimerEvent"),
         }
@@ -24,13 +24,23 @@ impl TaskMonitor {
     pub fn clear_deadline(&mut self, tid: TaskId) {
         self.active_deadlines[tid as usize] = None;
     }
+    /// Returns true if a deadline is set for the task `tid`.
+    pub fn has_deadline(&self, tid: TaskId) -> bool {
+        self.active_deadlines[tid as usize].is_some()
+    }
+    /// Returns the number of ticks from `curr_time` until the nearest deadline.
+    pub fn ticks_to_next_deadline(&self, curr_time: u32) -> Option<u32> {
+        self.active_deadlines.iter()
+            .filter_map(|deadline| deadline.map(|deadline| deadline.wrapping_sub(curr_time)))
+            .min()
+    }
     pub fn sweep_deadlines(&mut self, curr_time: u32) {
         for tid in 0..MAX_TASKS {
             if let Some(deadline) = self.active_deadlines[tid] {
-                if deadline == curr_time {
+                if curr_time.wrapping_sub(deadline) as i32 >= 0 {
                     self.active_deadlines[tid] = None;
-                    if self.handler.is_some() {
-                        (self.handler.unwrap())();
+                    if let Some(handler) = self.handler {
+                        handler();
                     }
                 }
             }
@@ -0,0 +1,126 @@
+//! # Tickless Idle
+//!
+//! Defines Data-structures to track SysTick periods which are stretched over multiple ticks while idle.
+
+/// Largest value the 24-bit SysTick reload register can hold.
+const SYST_MAX_RELOAD: u32 = 0x00ff_ffff;
+
+/// Tracks the current SysTick period.
+pub struct TicklessState {
+    /// Number of CPU cycles in a single tick.
+    pub tick_cycles: u32,
+    /// Number of ticks the current SysTick period spans.
+    period_ticks: u32,
+    /// Cycles of the current period which elapsed before the reload register was last rewritten.
+    offset_cycles: u32,
+    /// True if the current period has been stretched, the reload register is to be restored when it ends.
+    pub stretched: bool,
+}
+
+impl TicklessState {
+    pub const fn new() -> Self {
+        Self {
+            tick_cycles: 0,
+            period_ticks: 1,
+            offset_cycles: 0,
+            stretched: false,
+        }
+    }
+
+    /// Maximum number of ticks a single SysTick period can span.
+    pub fn max_ticks(&self) -> u32 {
+        if self.tick_cycles == 0 {
+            return 1;
+        }
+        ((SYST_MAX_RELOAD + 1) / self.tick_cycles).max(1)
+    }
+
+    /// Stretches the current period, of which `elapsed_cycles` have elapsed, to end `ticks` ticks after
+    /// it started. Returns the reload value for the remainder of the period.
+    pub fn stretch(&mut self, ticks: u32, elapsed_cycles: u32) -> u32 {
+        self.period_ticks = ticks;
+        self.offset_cycles = elapsed_cycles;
+        self.stretched = true;
+        (ticks * self.tick_cycles - elapsed_cycles - 1).max(1)
+    }
+
+    /// Shortens a stretched period, of which `elapsed_cycles` have elapsed since the reload register was last
+    /// rewritten, to end on the next tick boundary. Returns the reload value for the remainder of the period.
+    pub fn shorten(&mut self, elapsed_cycles: u32) -> u32 {
+        let total_cycles = self.offset_cycles + elapsed_cycles;
+        let ticks = total_cycles / self.tick_cycles + 1;
+        self.period_ticks = ticks;
+        self.offset_cycles = total_cycles;
+        (ticks * self.tick_cycles - total_cycles - 1).max(1)
+    }
+
+    /// Ends the current period and returns the number of ticks it spanned.
+    pub fn end_period(&mut self) -> u32 {
+        let ticks = self.period_ticks;
+        self.period_ticks = 1;
+        self.offset_cycles = 0;
+        self.stretched = false;
+        ticks
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TICK_CYCLES: u32 = 1000;
+
+    fn state() -> TicklessState {
+        let mut state = TicklessState::new();
+        state.tick_cycles = TICK_CYCLES;
+        state
+    }
+
+    #[test]
+    fn max_ticks_fit_the_reload_register() {
+        let mut state = state();
+        assert_eq!(state.max_ticks(), (SYST_MAX_RELOAD + 1) / TICK_CYCLES);
+        state.tick_cycles = 0;
+        assert_eq!(state.max_ticks(), 1);
+    }
+
+    #[test]
+    fn stretched_period_ends_on_the_tick_boundary() {
+        let mut state = state();
+        let reload = state.stretch(5, 200);
+        assert!(state.stretched);
+        // The counter counts from `reload` down to 0, i.e. `reload + 1` cycles.
+        assert_eq!(200 + reload + 1, 5 * TICK_CYCLES);
+        assert_eq!(state.end_period(), 5);
+        assert!(!state.stretched);
+        assert_eq!(state.end_period(), 1);
+    }
+
+    #[test]
+    fn shortened_period_ends_on_the_next_tick_boundary() {
+        let mut state = state();
+        state.stretch(10, 300);
+        let reload = state.shorten(2500);
+        assert_eq!(300 + 2500 + reload + 1, 3 * TICK_CYCLES);
+        assert_eq!(state.end_period(), 3);
+    }
+
+    #[test]
+    fn accumulated_ticks_match_elapsed_cycles() {
+        let mut state = state();
+        let mut cycles = 0;
+        let mut ticks = 0;
+        for &(stretch_ticks, elapsed, woken_after) in [(4, 10, None), (7, 999, Some(1)), (3, 0, Some(2998)), (1, 500, None)].iter() {
+            let mut period_cycles = elapsed;
+            let mut reload = state.stretch(stretch_ticks, elapsed);
+            if let Some(woken_after) = woken_after {
+                period_cycles += woken_after;
+                reload = state.shorten(woken_after);
+            }
+            cycles += period_cycles + reload + 1;
+            ticks += state.end_period();
+        }
+        assert_eq!(cycles, ticks * TICK_CYCLES);
+        assert_eq!(ticks, 4 + 2 + 3 + 1);
+    }
+}
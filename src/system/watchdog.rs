@@ -0,0 +1,127 @@
+//! # Watchdog Table
+//!
+//! Defines Data-structures to monitor the liveness of tasks with per-task countdowns.
+
+use crate::config::MAX_TASKS;
+use crate::system::scheduler::{BooleanVector, TaskId};
+use crate::KernelError;
+
+/// Holds the watchdog period and the remaining ticks of each monitored task.
+pub struct WatchdogTable {
+    /// The number of ticks within which each monitored task must reach a checkpoint.
+    periods: [Option<u32>; MAX_TASKS],
+    /// The ticks left until the deadline of each task, `None` if the countdown is not running.
+    remaining: [Option<u32>; MAX_TASKS],
+    /// Called with the TaskId of a task which missed its deadline.
+    pub miss_handler: Option<fn(TaskId)>,
+}
+
+impl WatchdogTable {
+    pub const fn new() -> Self {
+        Self {
+            periods: [None; MAX_TASKS],
+            remaining: [None; MAX_TASKS],
+            miss_handler: None,
+        }
+    }
+
+    /// Returns `KernelError::InvalidArgument` if `tid` is not below `MAX_TASKS`.
+    fn check_task(tid: TaskId) -> Result<usize, KernelError> {
+        if tid as usize >= MAX_TASKS {
+            return Err(KernelError::InvalidArgument);
+        }
+        Ok(tid as usize)
+    }
+
+    /// Arms the countdown of the task `tid` with a period of `ticks` ticks.
+    pub fn arm(&mut self, tid: TaskId, ticks: u32) -> Result<(), KernelError> {
+        let tid = Self::check_task(tid)?;
+        self.periods[tid] = Some(ticks);
+        self.remaining[tid] = Some(ticks);
+        Ok(())
+    }
+
+    /// Stops monitoring the task `tid`.
+    pub fn disarm(&mut self, tid: TaskId) -> Result<(), KernelError> {
+        let tid = Self::check_task(tid)?;
+        self.periods[tid] = None;
+        self.remaining[tid] = None;
+        Ok(())
+    }
+
+    /// Returns true if the task `tid` is monitored.
+    pub fn is_armed(&self, tid: TaskId) -> bool {
+        self.periods.get(tid as usize).map_or(false, |period| period.is_some())
+    }
+
+    /// Restarts the countdown of the task `tid`, if it is monitored.
+    pub fn checkpoint(&mut self, tid: TaskId) -> Result<(), KernelError> {
+        let tid = Self::check_task(tid)?;
+        self.remaining[tid] = self.periods[tid];
+        Ok(())
+    }
+
+    /// Returns the number of ticks until the nearest deadline.
+    pub fn ticks_to_next_deadline(&self) -> Option<u32> {
+        self.remaining.iter().filter_map(|remaining| *remaining).min()
+    }
+
+    /// Advances all countdowns by `ticks` ticks, and returns the boolean vector of tasks which missed
+    /// their deadline. The countdown of such a task stops until its next checkpoint.
+    pub fn sweep(&mut self, ticks: u32) -> BooleanVector {
+        let mut missed: BooleanVector = 0;
+        for tid in 0..MAX_TASKS {
+            if let Some(remaining) = self.remaining[tid] {
+                if remaining <= ticks {
+                    self.remaining[tid] = None;
+                    missed |= 1 << tid;
+                } else {
+                    self.remaining[tid] = Some(remaining - ticks);
+                }
+            }
+        }
+        missed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn missed_checkpoint_is_reported_once() {
+        let mut table = WatchdogTable::new();
+        table.arm(2, 10).unwrap();
+        table.arm(3, 10).unwrap();
+        assert_eq!(table.sweep(6), 0);
+        table.checkpoint(2).unwrap();
+        assert_eq!(table.ticks_to_next_deadline(), Some(4));
+        assert_eq!(table.sweep(4), 1 << 3);
+        // The countdown of the task which missed its deadline stops until its next checkpoint.
+        assert_eq!(table.sweep(20), 1 << 2);
+        assert_eq!(table.sweep(20), 0);
+        table.checkpoint(3).unwrap();
+        assert_eq!(table.ticks_to_next_deadline(), Some(10));
+    }
+
+    #[test]
+    fn disarmed_task_is_not_reported() {
+        let mut table = WatchdogTable::new();
+        table.arm(1, 5).unwrap();
+        table.disarm(1).unwrap();
+        assert!(!table.is_armed(1));
+        assert_eq!(table.sweep(10), 0);
+        table.checkpoint(1).unwrap();
+        assert_eq!(table.ticks_to_next_deadline(), None);
+    }
+
+    #[test]
+    fn out_of_range_task_is_rejected() {
+        let mut table = WatchdogTable::new();
+        let tid = MAX_TASKS as TaskId;
+        assert!(matches!(table.arm(tid, 5), Err(KernelError::InvalidArgument)));
+        assert!(matches!(table.disarm(tid), Err(KernelError::InvalidArgument)));
+        assert!(matches!(table.checkpoint(tid), Err(KernelError::InvalidArgument)));
+        assert!(!table.is_armed(tid));
+    }
+}
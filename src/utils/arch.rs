@@ -1,18 +1,34 @@
 //! # Machine specific
 //!
 //! Defines functions which are defined majorly in assembly. Thus, might change for one board to another.
+//! When the Kernel is built for tests, the functions which touch the hardware are taken from `utils::host`.
 
 // Platform specific Exports
-pub use cortex_m::interrupt::free as critical_section;
 pub use cortex_m::interrupt::Mutex;
 pub use cortex_m::peripheral::syst::SystClkSource;
 pub use cortex_m::peripheral::Peripherals;
 
+use core::cell::RefCell;
+
+#[cfg(not(test))]
 use cortex_m_rt::exception;
+#[cfg(not(test))]
 use cortex_m::register::control;
 
+use crate::KernelError;
 use crate::kernel::tasks::{TaskManager,schedule};
+#[cfg(not(test))]
+use crate::kernel::syscall::dispatch;
+#[cfg(not(test))]
 use crate::system::scheduler::TaskControlBlock;
+use crate::utils::port::{CriticalSection, Port, TargetPort};
+
+#[cfg(test)]
+pub use crate::utils::host::{svc_call, save_context, load_context, wait_for_interrupt, is_privileged, in_interrupt, set_thread_privileged};
+#[cfg(all(test, feature="cpu_stats"))]
+pub use crate::utils::host::{enable_cycle_counter, read_cycle_counter};
+#[cfg(all(test, feature="mpu"))]
+pub use crate::utils::host::{enable_mpu, set_stack_guard_region, clear_stack_guard_region};
 
 #[cfg(any(feature = "events_32", feature = "events_16", feature = "events_64"))]
 use crate::kernel::events::sweep_event_table;
@@ -21,34 +37,71 @@ use crate::kernel::events::sweep_event_table;
 use crate::kernel::task_monitor::sweep_deadlines;
 
 #[cfg(feature="timer")]
-use crate::kernel::timer::update_time;
+use crate::kernel::timer::{update_time, sweep_sleeping};
 
-/// Returns the MSB of `val`. It is written using CLZ instruction.
-pub fn get_msb(val: u32) -> Option<usize> {
-    let mut res: usize;
-    unsafe {
-        asm!(
-            "clz {1}, {0}",
-            in(reg) val,
-            out(reg) res,
-        );
+#[cfg(feature="timer")]
+use crate::kernel::soft_timer::sweep_soft_timers;
+
+#[cfg(feature="timer")]
+use crate::kernel::watchdog::sweep_watchdog;
+
+#[cfg(all(not(test), feature="tickless"))]
+use crate::kernel::tickless::end_period;
+
+/// A global Kernel state container, it wraps the `Mutex<RefCell<T>>` pattern. The accessors enter a
+/// critical section and borrow the state once; a nested access to the same state returns
+/// `KernelError::ReentrantBorrow` instead of panicking.
+pub struct KernelCell<T> {
+    inner: Mutex<RefCell<T>>,
+}
+
+impl<T> KernelCell<T> {
+    pub const fn new(val: T) -> Self {
+        Self { inner: Mutex::new(RefCell::new(val)) }
     }
-    res = 32 - res;
-    if res == 0 {
-        return None
-    } else {
-        res -= 1;
+
+    /// Executes `handler` with mutable access to the state.
+    pub fn with<F, R>(&self, handler: F) -> Result<R, KernelError>
+    where
+        F: FnOnce(&mut T) -> R,
+    {
+        critical_section(|cs_token| {
+            let mut state = self.inner.borrow(cs_token).try_borrow_mut().map_err(|_| KernelError::ReentrantBorrow)?;
+            Ok(handler(&mut state))
+        })
     }
-    return Some(res);
+
+    /// Executes `handler` with shared access to the state.
+    pub fn with_ref<F, R>(&self, handler: F) -> Result<R, KernelError>
+    where
+        F: FnOnce(&T) -> R,
+    {
+        critical_section(|cs_token| {
+            let state = self.inner.borrow(cs_token).try_borrow().map_err(|_| KernelError::ReentrantBorrow)?;
+            Ok(handler(&state))
+        })
+    }
+}
+
+pub use crate::utils::helpers::get_msb;
+
+/// Executes `handler` with interrupts disabled, via the `TargetPort`.
+pub fn critical_section<F, R>(handler: F) -> R
+where
+    F: FnOnce(&CriticalSection) -> R,
+{
+    TargetPort::critical_section(handler)
 }
 
-/// Creates an SVC Interrupt
+/// Creates an SVC Interrupt, requesting a context switch (`Syscall::Schedule`).
+#[cfg(not(test))]
 pub fn svc_call() {
     unsafe {
         asm!("svc 1");
     }
 }
 
+#[cfg(not(test))]
 #[inline(always)]
 pub unsafe fn return_to_psp() {
         asm!("
@@ -57,6 +110,17 @@ pub unsafe fn return_to_psp() {
         ");
 }
 
+/// Returns to thread mode with an extended (floating point) exception frame on the process stack.
+#[cfg(all(not(test), feature="fpu"))]
+#[inline(always)]
+pub unsafe fn return_to_psp_fpu() {
+        asm!("
+        ldr r0, =0xFFFFFFED
+        bx	r0
+        ");
+}
+
+#[cfg(all(not(test), not(feature="fpu")))]
 #[inline(always)]
 pub fn save_context(task_stack: &TaskControlBlock) {
     unsafe {
@@ -81,6 +145,48 @@ pub fn save_context(task_stack: &TaskControlBlock) {
     };
 }
 
+/// Saves R4-R11 and, for FPU tasks, S16-S31 below the exception frame. S0-S15 are saved by the
+/// hardware (lazily) as part of the extended exception frame.
+#[cfg(all(not(test), feature="fpu"))]
+#[inline(always)]
+pub fn save_context(task_stack: &TaskControlBlock) {
+    unsafe {
+        asm!(
+            "mrs r0, psp",
+            "cmp {1}, #0",
+            "beq 1f",
+            "vstmdb r0!, {{s16-s31}}",
+            "1:",
+            "stmdb r0!, {{r4-r11}}",
+            "str r0, [{0}]",
+            in(reg) task_stack,
+            in(reg) task_stack.meta.uses_fpu as u32,
+            out("r0") _,
+        )
+    };
+}
+
+#[cfg(all(not(test), feature="fpu"))]
+#[inline(always)]
+pub fn load_context(task_stack: &TaskControlBlock) {
+    unsafe {
+        asm!(
+            "cpsid i",
+            "ldr r0, [{0}]",
+            "ldmia r0!, {{r4-r11}}",
+            "cmp {1}, #0",
+            "beq 1f",
+            "vldmia r0!, {{s16-s31}}",
+            "1:",
+            "msr psp, r0",
+            in(reg) task_stack,
+            in(reg) task_stack.meta.uses_fpu as u32,
+            out("r0") _,
+        )
+    };
+}
+
+#[cfg(all(not(test), not(feature="fpu")))]
 #[inline(always)]
 pub fn load_context(task_stack: &TaskControlBlock) {
     unsafe {
@@ -110,61 +216,234 @@ pub fn load_context(task_stack: &TaskControlBlock) {
 /// The interrupt handler also calls `schedule()` in here so as to dispatch any higher priority
 /// task if there are any.
 
-#[cfg(feature="timer")]
+#[cfg(all(not(test), feature="timer"))]
 #[exception]
 fn SysTick() {
 
+    // With tickless idle, a single SysTick period may span multiple ticks.
+    #[cfg(feature="tickless")]
+    let ticks = end_period();
+    #[cfg(not(feature="tickless"))]
+    let ticks = 1;
+
+    sweep_tick(ticks);
+}
+
+/// Advances the time by `ticks` timer ticks, dispatches the timer sweeps and schedules the highest priority
+/// task. It is called from the SysTick handler, and by the host port in its place.
+#[cfg(feature="timer")]
+pub(crate) fn sweep_tick(ticks: u32) {
     #[cfg(any(feature = "events_32", feature = "events_16", feature = "events_64"))]
     sweep_event_table();
 
-    #[cfg(feature="timer")]
-    update_time();
+    update_time(ticks);
+
+    sweep_sleeping();
+
+    sweep_soft_timers(ticks);
+
+    sweep_watchdog(ticks);
     
     #[cfg(feature="task_monitor")]
     sweep_deadlines();
     
-    // hprintln!("hello");
     schedule();
 }
 /// ### SVC Interrupt handler,
-/// calls `tasks::schedule()`
+/// executes the syscall selected by the SVC number on behalf of unprivileged code (see `kernel::syscall`).
+/// `svc 1` pends the PendSV interrupt; the callers (`tasks::schedule()` and `tasks::task_yield()`)
+/// decide whether a context switch is to be requested.
+/// The SVC instruction is only executed by tasks, which run on the process stack; hence the exception
+/// frame is read from the PSP.
+#[cfg(not(test))]
 #[exception]
 fn SVCall() {
-    schedule();
+    unsafe {
+        let frame = cortex_m::register::psp::read() as *mut u32;
+        // The stacked PC points past the `svc` instruction, whose low byte is the SVC number.
+        let svc_number = *((*frame.add(6) - 2) as *const u8);
+        let args = [*frame, *frame.add(1), *frame.add(2), *frame.add(3)];
+        // `svc_call` (`Syscall::Schedule`) does not declare any register as clobbered, hence it gets no result.
+        if let Some([r0, r1]) = dispatch(svc_number, args) {
+            *frame = r0;
+            *frame.add(1) = r1;
+        }
+    }
 }
 /// ### PendSV Interrupt handler,
-/// PendSV interrupt handler does the actual context switch in the Kernel.
+/// PendSV interrupt handler does the actual context switch in the Kernel, see `switch_context`.
+#[cfg(not(test))]
 #[exception]
 fn PendSV() {
+    let _uses_fpu = switch_context();
+    #[cfg(feature="fpu")] {
+        if _uses_fpu {
+            unsafe {return_to_psp_fpu()}
+        }
+    }
+    unsafe {return_to_psp()}
+}
+
+/// Switches the context to the highest priority ready task (`Scheduler::switch_to_next`), after checking the
+/// stack of the outgoing task and calling the hooks which bracket the switch. Returns true if the incoming
+/// task uses the FPU. It is called from the PendSV handler, and by the host port in its place.
+pub(crate) fn switch_context() -> bool {
     critical_section(|cs_token| {
-        let handler = &mut TaskManager.borrow(cs_token).borrow_mut();
-        let curr_tid: usize = handler.curr_tid;
-        let next_tid: usize = handler.get_next_tid() as usize;
-        if curr_tid != next_tid || (!handler.started) {
+        // The kernel reads the guard word and paint of the task stacks, hence the guard is lifted while switching.
+        #[cfg(feature="mpu")]
+        clear_stack_guard_region();
+        {
+            let handler = &mut TaskManager.borrow(cs_token).borrow_mut();
             if handler.started {
-                let curr_task = handler.task_control_blocks[curr_tid].as_ref().unwrap();
-                curr_task.save_context();
-            } else {
-                handler.started = true;
+                // A task whose stack has overflowed is halted before it corrupts neighboring memory further.
+                handler.check_stack_overflow().ok();
             }
-            let next_task = handler.task_control_blocks[next_tid].as_ref().unwrap();
-            next_task.load_context();
-    
-            handler.curr_tid = next_tid;
         }
+        let idle_hook = TaskManager.borrow(cs_token).borrow().get_idle_hook();
+        if let Some(idle_hook) = idle_hook {
+            idle_hook();
+        }
+        #[cfg(feature="stack_guard")] {
+            let low_stack = TaskManager.borrow(cs_token).borrow().check_low_stack();
+            if let Some((low_stack_hook, tid, free_words)) = low_stack {
+                low_stack_hook(tid, free_words);
+            }
+        }
+        let handler = &mut TaskManager.borrow(cs_token).borrow_mut();
+        handler.switch_to_next();
+        let _curr_task = crate::kernel_assert!(Some(handler.tcb(handler.curr_tid)), "the running task has no TCB");
+        #[cfg(feature="mpu")]
+        set_stack_guard_region(_curr_task.meta.stack_base);
+        #[cfg(feature="fpu")]
+        return _curr_task.meta.uses_fpu;
+        #[cfg(not(feature="fpu"))]
+        return false;
+    })
+}
+
+/// ### MemManage Interrupt handler,
+/// an access to the MPU stack guard region means the currently running task has overflowed its stack.
+/// The task is halted (`KernelError::StackOverflow`) and another task is scheduled.
+#[cfg(all(not(test), feature="mpu"))]
+#[exception]
+fn MemoryManagement() {
+    critical_section(|cs_token| {
+        TaskManager.borrow(cs_token).borrow_mut().halt_curr_task().ok();
+        clear_stack_guard_region();
+        // Clears the MemManage fault status bits.
+        unsafe { (*cortex_m::peripheral::SCB::ptr()).cfsr.write(0xff) };
     });
-    unsafe {return_to_psp()}
+    set_pendsv();
 }
 
+/// MPU region used as the stack guard of the running task.
+#[cfg(feature="mpu")]
+const STACK_GUARD_REGION: u32 = 0;
+
+/// Enables the MPU with the default memory map as background region for privileged code, and the
+/// MemManage fault. The background region is not applied to unprivileged code.
+#[cfg(all(not(test), feature="mpu"))]
+pub fn enable_mpu() {
+    unsafe {
+        let mpu = &*cortex_m::peripheral::MPU::ptr();
+        // ENABLE | PRIVDEFENA
+        mpu.ctrl.write(0b101);
+        // MEMFAULTENA
+        (*cortex_m::peripheral::SCB::ptr()).shcsr.modify(|shcsr| shcsr | (1 << 16));
+    }
+    cortex_m::asm::dsb();
+    cortex_m::asm::isb();
+}
+
+/// Programs the stack guard region as a 32 byte no-access region at the bottom of the stack starting at `stack_base`.
+/// MPU regions must be aligned to their size; hence the region starts at the first 32 byte boundary at or above `stack_base`.
+#[cfg(all(not(test), feature="mpu"))]
+pub fn set_stack_guard_region(stack_base: usize) {
+    let base = (stack_base + 31) & !31;
+    unsafe {
+        let mpu = &*cortex_m::peripheral::MPU::ptr();
+        mpu.rnr.write(STACK_GUARD_REGION);
+        mpu.rbar.write(base as u32);
+        // XN | AP = no access | SIZE = 2^(4+1) bytes | ENABLE
+        mpu.rasr.write((1 << 28) | (4 << 1) | 1);
+    }
+    cortex_m::asm::dsb();
+    cortex_m::asm::isb();
+}
+
+/// Disables the stack guard region.
+#[cfg(all(not(test), feature="mpu"))]
+pub fn clear_stack_guard_region() {
+    unsafe {
+        let mpu = &*cortex_m::peripheral::MPU::ptr();
+        mpu.rnr.write(STACK_GUARD_REGION);
+        mpu.rasr.write(0);
+    }
+}
+
+/// Requests a context switch, which is carried out by the PendSV handler once no critical section or
+/// interrupt handler is active.
 pub fn set_pendsv() {
-    cortex_m::peripheral::SCB::set_pendsv();
+    TargetPort::pend_switch();
+}
+
+/// Enables the DWT cycle counter, which is used as the free-running counter for CPU statistics.
+#[cfg(all(not(test), feature="cpu_stats"))]
+pub fn enable_cycle_counter() {
+    unsafe {
+        (*cortex_m::peripheral::DCB::ptr()).demcr.modify(|demcr| demcr | (1 << 24));
+        (*cortex_m::peripheral::DWT::ptr()).ctrl.modify(|ctrl| ctrl | 1);
+    }
+}
+
+/// Returns the current value of the DWT cycle counter.
+#[cfg(all(not(test), feature="cpu_stats"))]
+pub fn read_cycle_counter() -> u32 {
+    cortex_m::peripheral::DWT::get_cycle_count()
 }
 
+#[cfg(not(test))]
 pub fn wait_for_interrupt() {
     cortex_m::asm::wfi();
 }
 
 /// Returns true if Currently the Kernel is operating in Privileged mode.
+/// Handler mode is always privileged, irrespective of the `nPRIV` bit of the thread mode.
+#[cfg(not(test))]
 pub fn is_privileged() -> bool {
     return control::read().npriv() == control::Npriv::Privileged
-}
\ No newline at end of file
+        || cortex_m::peripheral::SCB::vect_active() != cortex_m::peripheral::scb::VectActive::ThreadMode
+}
+
+/// Returns true if the CPU is executing an exception or interrupt handler, rather than a task.
+#[cfg(not(test))]
+pub fn in_interrupt() -> bool {
+    cortex_m::peripheral::SCB::vect_active() != cortex_m::peripheral::scb::VectActive::ThreadMode
+}
+
+/// Sets the privilege level of the thread mode, it takes effect on the return to thread mode.
+#[cfg(not(test))]
+pub fn set_thread_privileged(privileged: bool) {
+    let mut control = control::read();
+    control.set_npriv(if privileged { control::Npriv::Privileged } else { control::Npriv::Unprivileged });
+    unsafe { control::write(control) };
+    cortex_m::asm::isb();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn nested_access_is_a_reentrant_borrow() {
+        let cell = KernelCell::new(1u32);
+        let nested = cell.with(|value| {
+            *value += 1;
+            cell.with_ref(|_| ())
+        });
+        assert!(matches!(nested, Ok(Err(KernelError::ReentrantBorrow))));
+        assert!(matches!(cell.with_ref(|_| cell.with(|_| ())), Ok(Err(KernelError::ReentrantBorrow))));
+        // Shared accesses nest.
+        assert!(matches!(cell.with_ref(|value| cell.with_ref(|again| *value + *again)), Ok(Ok(4))));
+    }
+}
@@ -10,6 +10,66 @@ pub enum KernelError {
     AccessDenied,
     Empty,
     Exists,
+    CeilingViolation,
+    TimedOut,
+    NotInitialized,
+    AlreadyRunning,
+    StackOverflow,
+    BufferOverflow,
+    ReentrantBorrow,
+    InvalidArgument,
+    PiStackOverflow,
+    NotInTaskContext,
+    DoesNotExist,
+}
+
+impl KernelError {
+    /// Returns a stable numeric code for the error, for logging over constrained links.
+    pub fn code(&self) -> u8 {
+        match *self {
+            KernelError::NotFound => 1,
+            KernelError::StackTooSmall => 2,
+            KernelError::LimitExceeded => 3,
+            KernelError::AccessDenied => 4,
+            KernelError::Empty => 5,
+            KernelError::Exists => 6,
+            KernelError::CeilingViolation => 7,
+            KernelError::TimedOut => 8,
+            KernelError::NotInitialized => 9,
+            KernelError::AlreadyRunning => 10,
+            KernelError::StackOverflow => 11,
+            KernelError::BufferOverflow => 12,
+            KernelError::ReentrantBorrow => 13,
+            KernelError::InvalidArgument => 14,
+            KernelError::PiStackOverflow => 15,
+            KernelError::NotInTaskContext => 16,
+            KernelError::DoesNotExist => 17,
+        }
+    }
+
+    /// Returns the error corresponding to the numeric `code`, the inverse of `code()`.
+    pub fn from_code(code: u8) -> Option<Self> {
+        match code {
+            1 => Some(KernelError::NotFound),
+            2 => Some(KernelError::StackTooSmall),
+            3 => Some(KernelError::LimitExceeded),
+            4 => Some(KernelError::AccessDenied),
+            5 => Some(KernelError::Empty),
+            6 => Some(KernelError::Exists),
+            7 => Some(KernelError::CeilingViolation),
+            8 => Some(KernelError::TimedOut),
+            9 => Some(KernelError::NotInitialized),
+            10 => Some(KernelError::AlreadyRunning),
+            11 => Some(KernelError::StackOverflow),
+            12 => Some(KernelError::BufferOverflow),
+            13 => Some(KernelError::ReentrantBorrow),
+            14 => Some(KernelError::InvalidArgument),
+            15 => Some(KernelError::PiStackOverflow),
+            16 => Some(KernelError::NotInTaskContext),
+            17 => Some(KernelError::DoesNotExist),
+            _ => None,
+        }
+    }
 }
 
 impl fmt::Debug for KernelError {
@@ -21,6 +81,78 @@ impl fmt::Debug for KernelError {
             KernelError::AccessDenied => write!(f, "AccessDenied"),
             KernelError::Empty => write!(f, "Empty"),
             KernelError::Exists => write!(f, "Exists"),
+            KernelError::CeilingViolation => write!(f, "CeilingViolation"),
+            KernelError::TimedOut => write!(f, "TimedOut"),
+            KernelError::NotInitialized => write!(f, "NotInitialized"),
+            KernelError::AlreadyRunning => write!(f, "AlreadyRunning"),
+            KernelError::StackOverflow => write!(f, "StackOverflow"),
+            KernelError::BufferOverflow => write!(f, "BufferOverflow"),
+            KernelError::ReentrantBorrow => write!(f, "ReentrantBorrow"),
+            KernelError::InvalidArgument => write!(f, "InvalidArgument"),
+            KernelError::PiStackOverflow => write!(f, "PiStackOverflow"),
+            KernelError::NotInTaskContext => write!(f, "NotInTaskContext"),
+            KernelError::DoesNotExist => write!(f, "DoesNotExist"),
+        }
+    }
+}
+
+impl fmt::Display for KernelError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            KernelError::NotFound => write!(f, "not found"),
+            KernelError::StackTooSmall => write!(f, "stack too small"),
+            KernelError::LimitExceeded => write!(f, "limit exceeded"),
+            KernelError::AccessDenied => write!(f, "access denied"),
+            KernelError::Empty => write!(f, "empty"),
+            KernelError::Exists => write!(f, "already exists"),
+            KernelError::CeilingViolation => write!(f, "ceiling violation"),
+            KernelError::TimedOut => write!(f, "timed out"),
+            KernelError::NotInitialized => write!(f, "kernel not initialized"),
+            KernelError::AlreadyRunning => write!(f, "kernel already running"),
+            KernelError::StackOverflow => write!(f, "stack overflow"),
+            KernelError::BufferOverflow => write!(f, "buffer overflow"),
+            KernelError::ReentrantBorrow => write!(f, "reentrant borrow"),
+            KernelError::InvalidArgument => write!(f, "invalid argument"),
+            KernelError::PiStackOverflow => write!(f, "pi stack overflow"),
+            KernelError::NotInTaskContext => write!(f, "not in task context"),
+            KernelError::DoesNotExist => write!(f, "task does not exist"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_code_maps_back_to_its_error() {
+        let messages = [
+            (1, "NotFound", "not found"),
+            (2, "StackTooSmall", "stack too small"),
+            (3, "LimitExceeded", "limit exceeded"),
+            (4, "AccessDenied", "access denied"),
+            (5, "Empty", "empty"),
+            (6, "Exists", "already exists"),
+            (7, "CeilingViolation", "ceiling violation"),
+            (8, "TimedOut", "timed out"),
+            (9, "NotInitialized", "kernel not initialized"),
+            (10, "AlreadyRunning", "kernel already running"),
+            (11, "StackOverflow", "stack overflow"),
+            (12, "BufferOverflow", "buffer overflow"),
+            (13, "ReentrantBorrow", "reentrant borrow"),
+            (14, "InvalidArgument", "invalid argument"),
+            (15, "PiStackOverflow", "pi stack overflow"),
+            (16, "NotInTaskContext", "not in task context"),
+            (17, "DoesNotExist", "task does not exist"),
+        ];
+        for (code, name, message) in messages {
+            let error = KernelError::from_code(code).unwrap();
+            assert_eq!(error.code(), code);
+            assert_eq!(format!("{:?}", error), name);
+            assert_eq!(format!("{}", error), message);
         }
+        // Code 0 stands for success in the syscall results.
+        assert!(KernelError::from_code(0).is_none());
+        assert!(KernelError::from_code(messages.len() as u8 + 1).is_none());
     }
 }
@@ -1,12 +1,18 @@
+use crate::config::MAX_TASKS;
+use crate::system::scheduler::{BooleanVector, VECTOR_BITS};
+
 /// A helper struct to generate Boolean vector corresponding to an array of TaskIds at compile time.
 pub struct TaskMask<const N: usize> {}
 
 impl<const N: usize> TaskMask<N> {
     /// Takes an array of TaskIds and returns a BooleanVector corresponding to it.
-    pub const fn generate(tasks: [u32; N]) -> u32{
-        let mut task_mask: u32 = 0;
+    /// It can be used in `static` initializers; a TaskId at or above `MAX_TASKS` then fails the
+    /// compilation (and panics if evaluated at runtime).
+    pub const fn generate(tasks: [u32; N]) -> BooleanVector {
+        let mut task_mask: BooleanVector = 0;
         let mut i = 0;
         while i < N {
+            [()][(tasks[i] as usize >= MAX_TASKS) as usize];
             task_mask |= 1<<tasks[i];
             i += 1;
         }
@@ -14,15 +20,104 @@ impl<const N: usize> TaskMask<N> {
     }
 }
 
+/// Returns the position of the most significant set bit of `val`, or `None` if `val` is zero.
+/// `leading_zeros` compiles to a single CLZ instruction on Cortex-M; hence it runs in constant time.
+/// It does not depend on the target, so that the scheduling decisions built on it can run on any host.
+pub fn get_msb(val: BooleanVector) -> Option<usize> {
+    if val == 0 {
+        return None
+    }
+    return Some(VECTOR_BITS - 1 - val.leading_zeros() as usize);
+}
 
-pub const fn get_msb_const(val: u32) -> usize {
-    let mut res = 0;
+/// Returns the position of the most significant set bit of `val`, or `None` if `val` is zero. Unlike
+/// `get_msb`, it can be evaluated at compile time.
+pub const fn get_msb_const(val: BooleanVector) -> Option<usize> {
+    let mut res = None;
     let mut i = 0;
-    while i < 32 {
+    while i < VECTOR_BITS {
         if val & (1<<i) > 0 {
-            res = i;
+            res = Some(i);
         }
         i += 1;
     }
     return res;
-}
\ No newline at end of file
+}
+
+/// Compile-time check that `TaskMask::generate` can build masks in const context.
+const _: () = [()][(TaskMask::<3>::generate([0, 2, 5]) != 0b10_0101) as usize];
+
+/// Compile-time checks that `get_msb_const` returns the most significant set bit, and `None` for zero.
+const _: () = {
+    [()][(msb_or_sentinel(0) != usize::MAX) as usize];
+    [()][(msb_or_sentinel(1) != 0) as usize];
+    [()][(msb_or_sentinel(1 << 31) != 31) as usize];
+    [()][(msb_or_sentinel(0b1011_0010) != 7) as usize];
+    [()][(msb_or_sentinel(0xffff_ffff) != 31) as usize];
+    [()][(msb_or_sentinel(1 << (VECTOR_BITS - 1)) != VECTOR_BITS - 1) as usize];
+};
+
+/// Maps the result of `get_msb_const` to a `usize` which can be compared in the compile-time checks.
+const fn msb_or_sentinel(val: BooleanVector) -> usize {
+    match get_msb_const(val) {
+        Some(msb) => msb,
+        None => usize::MAX,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn task_mask_sets_a_bit_per_task() {
+        assert_eq!(TaskMask::<0>::generate([]), 0);
+        assert_eq!(TaskMask::<1>::generate([0]), 1);
+        assert_eq!(TaskMask::<3>::generate([1, 1, 4]), 0b1_0010);
+        assert_eq!(TaskMask::<1>::generate([MAX_TASKS as u32 - 1]), 1 << (MAX_TASKS - 1));
+    }
+
+    #[test]
+    #[should_panic]
+    fn task_mask_rejects_task_at_max_tasks() {
+        TaskMask::<1>::generate([MAX_TASKS as u32]);
+    }
+
+    #[test]
+    fn task_mask_macro_matches_generate() {
+        const SENSOR: u32 = 1;
+        const LOGGER: u32 = 4;
+        const MASK: BooleanVector = crate::task_mask!(SENSOR, LOGGER, MAX_TASKS - 1);
+        assert_eq!(MASK, TaskMask::<3>::generate([SENSOR, LOGGER, MAX_TASKS as u32 - 1]));
+        assert_eq!(crate::task_mask!(), 0);
+    }
+
+    #[test]
+    fn msb_of_zero_is_none() {
+        assert_eq!(get_msb(0), None);
+        assert_eq!(get_msb_const(0), None);
+    }
+
+    #[test]
+    fn msb_matches_const_version() {
+        for bit in 0..VECTOR_BITS {
+            let val: BooleanVector = 1 << bit;
+            assert_eq!(get_msb(val), Some(bit));
+            assert_eq!(get_msb(val | 1), Some(bit));
+            assert_eq!(get_msb_const(val | 1), Some(bit));
+        }
+        assert_eq!(get_msb(0b1011_0010), Some(7));
+        assert_eq!(get_msb(BooleanVector::MAX), Some(VECTOR_BITS - 1));
+    }
+
+    #[test]
+    fn msb_ignores_the_lower_bits() {
+        for bit in 0..VECTOR_BITS {
+            let top: BooleanVector = 1 << bit;
+            for lower in [0, top - 1, (top - 1) & 0x5555_5555, (top - 1) & 0xaaaa_aaaa] {
+                // The highest priority ready task wins, whichever lower priority tasks are ready.
+                assert_eq!(get_msb(top | lower), Some(bit));
+            }
+        }
+    }
+}
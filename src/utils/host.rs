@@ -0,0 +1,300 @@
+//! # Host port
+//!
+//! Stands in for the Cortex-M when the Kernel is built for tests on the host, see `utils::port`.
+//! Critical sections take a global reentrant lock, so that the Kernel state is never accessed by two threads
+//! at once. A pended context switch is carried out by `arch::switch_context` once the outermost critical
+//! section ends outside of a simulated interrupt handler, as PendSV would.
+//!
+//! Once `boot` has started the Kernel, every task runs its handler on a thread of its own, and only the
+//! thread of the running task executes: a context switch hands the CPU to the thread of the incoming task,
+//! and the outgoing thread waits until its task is dispatched again. The test thread plays the idle task (`IDLE_TASK`);
+//! hence the Kernel routines it calls return once every task has exited, blocked or gone to sleep.
+//! A deleted or restarted task keeps its thread, i.e. a restarted task resumes where it was switched out.
+
+use core::cell::{Cell, RefCell};
+use core::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, AtomicUsize, Ordering};
+
+use crate::kernel::tasks::{self, TaskManager};
+use crate::system::scheduler::{KernelState, Scheduler, TaskControlBlock, TaskId, IDLE_TASK};
+use crate::utils::arch::Mutex;
+use crate::utils::port::{CriticalSection, Port};
+
+/// The id of the thread which holds the critical section lock, 0 if it is free.
+static CS_OWNER: AtomicUsize = AtomicUsize::new(0);
+/// The source of the thread ids, which start at 1.
+static NEXT_THREAD_ID: AtomicUsize = AtomicUsize::new(1);
+/// Held by the test which uses the Kernel state, see `kernel_test`.
+static KERNEL_TEST: AtomicBool = AtomicBool::new(false);
+/// Incremented by every `kernel_test`; the task threads of an earlier test never run again.
+static GENERATION: AtomicUsize = AtomicUsize::new(0);
+/// The task whose thread executes, `NO_TASK` until the Kernel has started.
+static RUNNING: AtomicUsize = AtomicUsize::new(NO_TASK);
+/// A bit vector of the tasks which have a thread.
+static SPAWNED: AtomicU64 = AtomicU64::new(0);
+/// Set when the handler of a task panics, the test thread then panics too.
+static TASK_PANICKED: AtomicBool = AtomicBool::new(false);
+static SWITCH_PENDING: AtomicBool = AtomicBool::new(false);
+static IN_INTERRUPT: AtomicBool = AtomicBool::new(false);
+static THREAD_PRIVILEGED: AtomicBool = AtomicBool::new(true);
+static CYCLE_COUNTER: AtomicU32 = AtomicU32::new(0);
+/// The events recorded with `trace`.
+static TRACE: Mutex<RefCell<Vec<u32>>> = Mutex::new(RefCell::new(Vec::new()));
+
+const NO_TASK: usize = usize::MAX;
+
+thread_local! {
+    static THREAD_ID: usize = NEXT_THREAD_ID.fetch_add(1, Ordering::Relaxed);
+    static CS_DEPTH: Cell<usize> = Cell::new(0);
+    static SWITCHING: Cell<bool> = Cell::new(false);
+    /// The generation and TaskId of the task the thread runs, if any.
+    static THREAD_TASK: Cell<Option<(usize, usize)>> = Cell::new(None);
+}
+
+/// The simulated target.
+pub struct HostPort;
+
+/// Leaves the critical section when dropped, also when the handler panics.
+struct CsGuard;
+
+impl Drop for CsGuard {
+    fn drop(&mut self) {
+        let depth = CS_DEPTH.with(|depth| {
+            depth.set(depth.get() - 1);
+            depth.get()
+        });
+        if depth == 0 {
+            CS_OWNER.store(0, Ordering::Release);
+        }
+    }
+}
+
+impl Port for HostPort {
+    fn critical_section<F, R>(handler: F) -> R
+    where
+        F: FnOnce(&CriticalSection) -> R,
+    {
+        if CS_DEPTH.with(|depth| depth.get()) == 0 {
+            let id = THREAD_ID.with(|id| *id);
+            while CS_OWNER.compare_exchange(0, id, Ordering::Acquire, Ordering::Relaxed).is_err() {
+                std::thread::yield_now();
+            }
+        }
+        CS_DEPTH.with(|depth| depth.set(depth.get() + 1));
+        let result = {
+            let _guard = CsGuard;
+            handler(unsafe { &CriticalSection::new() })
+        };
+        dispatch_pending();
+        result
+    }
+
+    fn pend_switch() {
+        SWITCH_PENDING.store(true, Ordering::SeqCst);
+        dispatch_pending();
+    }
+}
+
+/// Carries out the pended context switch, unless it is masked by a critical section or an interrupt handler,
+/// and then waits until the task of the calling thread is dispatched.
+fn dispatch_pending() {
+    let masked = CS_DEPTH.with(|depth| depth.get()) != 0
+        || IN_INTERRUPT.load(Ordering::SeqCst)
+        || SWITCHING.with(|switching| switching.get());
+    if masked {
+        return;
+    }
+    SWITCHING.with(|switching| switching.set(true));
+    let mut switched = false;
+    while SWITCH_PENDING.swap(false, Ordering::SeqCst) {
+        crate::utils::arch::switch_context();
+        switched = true;
+    }
+    let running = HostPort::critical_section(|cs_token| {
+        let handler = TaskManager.borrow(cs_token).borrow();
+        if handler.state != KernelState::Running {
+            return None;
+        }
+        let tcb = handler.tcb(handler.curr_tid);
+        Some((handler.curr_tid, tcb.and_then(|tcb| tcb.meta.handler)))
+    });
+    SWITCHING.with(|switching| switching.set(false));
+    if let (true, Some((curr_tid, task_handler))) = (switched, running) {
+        RUNNING.store(curr_tid, Ordering::SeqCst);
+        if curr_tid != IDLE_TASK as usize && SPAWNED.fetch_or(1 << curr_tid, Ordering::SeqCst) & (1 << curr_tid) == 0 {
+            if let Some(task_handler) = task_handler {
+                spawn_task_thread(curr_tid, task_handler);
+            }
+        }
+        wait_until_dispatched();
+    }
+}
+
+/// Runs the handler of the task `tid` on a new thread.
+fn spawn_task_thread(tid: usize, task_handler: fn() -> !) {
+    let generation = GENERATION.load(Ordering::SeqCst);
+    std::thread::spawn(move || {
+        THREAD_TASK.with(|task| task.set(Some((generation, tid))));
+        wait_until_dispatched();
+        let result = std::panic::catch_unwind(|| task_handler());
+        if result.is_err() && GENERATION.load(Ordering::SeqCst) == generation {
+            TASK_PANICKED.store(true, Ordering::SeqCst);
+            RUNNING.store(IDLE_TASK as usize, Ordering::SeqCst);
+        }
+    });
+}
+
+/// Blocks the calling thread until its task is the running task. The thread of a task of an earlier test
+/// is parked for good, its Kernel state is gone.
+fn wait_until_dispatched() {
+    let (generation, tid) = match THREAD_TASK.with(|task| task.get()) {
+        Some(task) => task,
+        None => return,
+    };
+    loop {
+        if GENERATION.load(Ordering::SeqCst) != generation {
+            loop {
+                std::thread::park();
+            }
+        }
+        if TASK_PANICKED.load(Ordering::SeqCst) && tid == IDLE_TASK as usize {
+            panic!("a task panicked");
+        }
+        if RUNNING.load(Ordering::SeqCst) == tid {
+            break;
+        }
+        std::thread::yield_now();
+    }
+}
+
+/// Executes `handler` as an interrupt handler: `in_interrupt` holds, and a context switch pended meanwhile
+/// is carried out once it returns.
+pub fn run_isr<F: FnOnce() -> R, R>(handler: F) -> R {
+    let preempted = IN_INTERRUPT.swap(true, Ordering::SeqCst);
+    let result = handler();
+    IN_INTERRUPT.store(preempted, Ordering::SeqCst);
+    dispatch_pending();
+    result
+}
+
+/// Executes the syscall `number` as the SVC handler does, and returns the resulting `r0` and `r1`.
+pub fn svc(number: u8, args: [u32; 4]) -> [u32; 2] {
+    run_isr(|| crate::kernel::syscall::dispatch(number, args)).unwrap_or([args[0], args[1]])
+}
+
+/// Advances the time by `ticks` timer ticks as the SysTick handler does.
+#[cfg(feature = "timer")]
+pub fn tick(ticks: u32) {
+    run_isr(|| crate::utils::arch::sweep_tick(ticks));
+}
+
+pub fn svc_call() {
+    svc(1, [0; 4]);
+}
+
+pub fn save_context(_task_stack: &TaskControlBlock) {}
+
+pub fn load_context(_task_stack: &TaskControlBlock) {}
+
+pub fn wait_for_interrupt() {}
+
+pub fn is_privileged() -> bool {
+    THREAD_PRIVILEGED.load(Ordering::SeqCst) || in_interrupt()
+}
+
+pub fn in_interrupt() -> bool {
+    IN_INTERRUPT.load(Ordering::SeqCst)
+}
+
+pub fn set_thread_privileged(privileged: bool) {
+    THREAD_PRIVILEGED.store(privileged, Ordering::SeqCst);
+}
+
+#[cfg(feature = "cpu_stats")]
+pub fn enable_cycle_counter() {}
+
+#[cfg(feature = "cpu_stats")]
+pub fn read_cycle_counter() -> u32 {
+    CYCLE_COUNTER.load(Ordering::SeqCst)
+}
+
+/// Advances the simulated cycle counter by `cycles`.
+pub fn advance_cycle_counter(cycles: u32) {
+    CYCLE_COUNTER.fetch_add(cycles, Ordering::SeqCst);
+}
+
+#[cfg(feature = "mpu")]
+pub fn enable_mpu() {}
+
+#[cfg(feature = "mpu")]
+pub fn set_stack_guard_region(_stack_base: usize) {}
+
+#[cfg(feature = "mpu")]
+pub fn clear_stack_guard_region() {}
+
+/// Grants the calling test exclusive use of the Kernel state, which is reset to an uninitialized scheduler
+/// without task deadlines.
+/// The state is released when the returned guard is dropped.
+pub fn kernel_test() -> KernelTest {
+    while KERNEL_TEST.compare_exchange(false, true, Ordering::Acquire, Ordering::Relaxed).is_err() {
+        std::thread::yield_now();
+    }
+    GENERATION.fetch_add(1, Ordering::SeqCst);
+    RUNNING.store(NO_TASK, Ordering::SeqCst);
+    SPAWNED.store(0, Ordering::SeqCst);
+    TASK_PANICKED.store(false, Ordering::SeqCst);
+    SWITCH_PENDING.store(false, Ordering::SeqCst);
+    IN_INTERRUPT.store(false, Ordering::SeqCst);
+    THREAD_PRIVILEGED.store(true, Ordering::SeqCst);
+    HostPort::critical_section(|cs_token| {
+        *TaskManager.borrow(cs_token).borrow_mut() = Scheduler::new();
+        TRACE.borrow(cs_token).borrow_mut().clear();
+    });
+    // A deadline left by an earlier test would pin its task, see `tasks::set_priority`.
+    #[cfg(feature = "task_monitor")]
+    for tid in 0..crate::config::MAX_TASKS {
+        crate::kernel::task_monitor::clear_deadline(tid as TaskId);
+    }
+    KernelTest
+}
+
+/// Exclusive use of the Kernel state, see `kernel_test`.
+pub struct KernelTest;
+
+impl Drop for KernelTest {
+    fn drop(&mut self) {
+        GENERATION.fetch_add(1, Ordering::SeqCst);
+        THREAD_TASK.with(|task| task.set(None));
+        KERNEL_TEST.store(false, Ordering::Release);
+    }
+}
+
+/// Records `event`, so that a test can check the order in which its tasks ran.
+pub fn trace(event: u32) {
+    HostPort::critical_section(|cs_token| TRACE.borrow(cs_token).borrow_mut().push(event));
+}
+
+/// Returns the events recorded since the last call, and clears them.
+pub fn take_trace() -> Vec<u32> {
+    HostPort::critical_section(|cs_token| core::mem::replace(&mut *TRACE.borrow(cs_token).borrow_mut(), Vec::new()))
+}
+
+/// Creates the task `tid` with the handler `handler`.
+pub fn create(tid: TaskId, handler: fn() -> !) {
+    let stack = Box::leak(vec![0u32; 64].into_boxed_slice());
+    #[cfg(feature = "task_monitor")]
+    tasks::create_task(tid, 100, stack, handler).unwrap();
+    #[cfg(not(feature = "task_monitor"))]
+    tasks::create_task(tid, stack, handler).unwrap();
+}
+
+/// Initializes the Kernel with the tasks `tasks`, which run `handlers`, starts it and dispatches the idle task,
+/// which the calling thread plays from then on. The tasks are not released.
+pub fn boot(tasks: &[(TaskId, fn() -> !)]) {
+    tasks::init().unwrap();
+    for &(tid, handler) in tasks {
+        create(tid, handler);
+    }
+    HostPort::critical_section(|cs_token| TaskManager.borrow(cs_token).borrow_mut().start()).unwrap();
+    THREAD_TASK.with(|task| task.set(Some((GENERATION.load(Ordering::SeqCst), IDLE_TASK as usize))));
+    HostPort::pend_switch();
+}
@@ -3,6 +3,10 @@
 pub mod arch;
 pub mod errors;
 pub mod helpers;
+pub mod port;
+
+#[cfg(test)]
+pub mod host;
 
 #[cfg(feature = "alloc")]
 pub mod heap;
@@ -0,0 +1,44 @@
+//! # Port
+//!
+//! The target operations the scheduler relies on: critical sections, and requesting a context switch.
+//! The Kernel reaches them through `utils::arch`, which forwards to the `TargetPort`; on a host (`cfg(test)`)
+//! the `HostPort` of `utils::host` stands in for the Cortex-M, so that the scheduling can be tested there.
+
+pub use cortex_m::interrupt::CriticalSection;
+
+/// The operations a target provides to the scheduler.
+pub trait Port {
+    /// Executes `handler` with interrupts disabled, i.e. without being preempted by a task or an interrupt.
+    fn critical_section<F, R>(handler: F) -> R
+    where
+        F: FnOnce(&CriticalSection) -> R;
+
+    /// Requests a context switch. It is carried out once the interrupts are enabled again and no interrupt
+    /// handler is executing, as the PendSV exception has the lowest priority.
+    fn pend_switch();
+}
+
+/// The Cortex-M port: interrupts are masked with `PRIMASK`, and a context switch is requested by pending PendSV.
+#[cfg(not(test))]
+pub struct CortexM;
+
+#[cfg(not(test))]
+impl Port for CortexM {
+    fn critical_section<F, R>(handler: F) -> R
+    where
+        F: FnOnce(&CriticalSection) -> R,
+    {
+        cortex_m::interrupt::free(handler)
+    }
+
+    fn pend_switch() {
+        cortex_m::peripheral::SCB::set_pendsv();
+    }
+}
+
+/// The port the Kernel is built for.
+#[cfg(not(test))]
+pub type TargetPort = CortexM;
+
+#[cfg(test)]
+pub type TargetPort = crate::utils::host::HostPort;
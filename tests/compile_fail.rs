@@ -0,0 +1,9 @@
+//! Checks that misuses of the Kernel macros are rejected at compile time. The expected compiler output is kept
+//! next to each case in `tests/ui`, as emitted by a nightly 1.97 toolchain; run with `TRYBUILD=overwrite` to
+//! regenerate it for another toolchain.
+
+#[test]
+fn compile_fail() {
+    let cases = trybuild::TestCases::new();
+    cases.compile_fail("tests/ui/*.rs");
+}
@@ -0,0 +1,8 @@
+use harsark::primitives::Resource;
+
+// With the default 32 tasks, a task mask is 32 bits wide; a mask which refers to the task 40 fails to compile.
+static RES: Resource<u32> = Resource::new(0, 1 << 40);
+
+fn main() {
+    let _ = &RES;
+}
@@ -0,0 +1,9 @@
+use harsark::helpers::MAX_TASKS;
+use harsark::spawn;
+use harsark::tasks::*;
+
+static mut stack1: [u32; 128] = [0; 128];
+
+fn main() {
+    spawn!(MAX_TASKS as u32, stack1, {});
+}